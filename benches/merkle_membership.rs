@@ -0,0 +1,28 @@
+#[macro_use]
+extern crate criterion;
+extern crate bulletproofs_examples;
+
+use criterion::Criterion;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use bulletproofs_examples::gadget_poseidon::PoseidonParams;
+use bulletproofs_examples::gadget_vsmt_2::bench_merkle_membership;
+
+fn merkle_membership(c: &mut Criterion) {
+    let p_params = PoseidonParams::new(6, 8, 8, 105);
+    let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+    for depth in [8, 16, 32].iter() {
+        let result = bench_merkle_membership(*depth, &p_params, &mut rng).unwrap();
+        println!("Depth {}: proving time {:?}, verification time {:?}, proof size {} bytes, {} multipliers",
+                 result.depth, result.prove_time, result.verify_time, result.proof_size_bytes, result.num_multipliers);
+    }
+
+    c.bench_function("VSMT membership depth 32", |b| b.iter(|| bench_merkle_membership(32, &p_params, &mut rng).unwrap()));
+}
+
+criterion_group!{
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = merkle_membership
+}
+criterion_main!(benches);
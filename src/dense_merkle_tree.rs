@@ -0,0 +1,133 @@
+use curve25519_dalek::scalar::Scalar;
+
+use crate::gadget_poseidon::PoseidonParams;
+use crate::gadget_vsmt_2::compute_root_from_proof;
+use crate::hash_function::{HashFunction, PoseidonHash};
+
+/// Binary merkle tree where (nearly) all leaves are populated. All levels are kept in vectors so a node is accessed by
+/// index without the hashmap of `VanillaSparseMerkleTree`. Nodes are hashed the same way as in `VanillaSparseMerkleTree`,
+/// so the root and proofs are the same as of a sparse tree of the same depth with the same leaves, and membership is
+/// proven with `vanilla_merkle_merkle_tree_verif_gadget` or `gen_proof_of_leaf_membership_from_path`.
+pub struct DenseMerkleTree<'a> {
+    pub depth: usize,
+    /// Element at index i has the nodes at height i, the leaves first and the root last
+    levels: Vec<Vec<Scalar>>,
+    hash_params: &'a PoseidonParams
+}
+
+impl<'a> DenseMerkleTree<'a> {
+    /// Create a tree with `leaves` at indices 0 to `leaves.len()`. The number of leaves is padded with empty leaves,
+    /// i.e. 0, to a power of 2 of at least 2.
+    pub fn new(leaves: &[Scalar], hash_params: &'a PoseidonParams) -> DenseMerkleTree<'a> {
+        let num_leaves = leaves.len().next_power_of_two().max(2);
+        let depth = num_leaves.trailing_zeros() as usize;
+
+        let mut level = leaves.to_vec();
+        level.resize(num_leaves, Scalar::zero());
+
+        let hasher = PoseidonHash(hash_params);
+        let mut levels = Vec::with_capacity(depth + 1);
+        for _ in 0..depth {
+            let next = level.chunks(2).map(|c| hasher.hash_2(&c[0], &c[1])).collect();
+            levels.push(level);
+            level = next;
+        }
+        levels.push(level);
+
+        DenseMerkleTree {
+            depth,
+            levels,
+            hash_params
+        }
+    }
+
+    pub fn root(&self) -> Scalar {
+        self.levels[self.depth][0]
+    }
+
+    /// Number of leaves including the padding
+    pub fn num_leaves(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn leaf(&self, index: usize) -> Option<Scalar> {
+        self.levels[0].get(index).cloned()
+    }
+
+    /// Merkle proof of the leaf at `index` in the same order as returned by `VanillaSparseMerkleTree::get`, i.e. the
+    /// proof node for the root's level first. None if `index` is not less than `num_leaves`.
+    pub fn proof(&self, index: usize) -> Option<Vec<Scalar>> {
+        if index >= self.num_leaves() {
+            return None;
+        }
+        let mut proof: Vec<Scalar> = (0..self.depth).map(|h| self.levels[h][(index >> h) ^ 1]).collect();
+        proof.reverse();
+        Some(proof)
+    }
+
+    /// Verify a proof returned by `proof` against the current root
+    pub fn verify_proof(&self, index: usize, val: Scalar, proof: &[Scalar]) -> bool {
+        compute_root_from_proof(Scalar::from(index as u64), val, proof, self.hash_params, self.depth) == Some(self.root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bulletproofs::{BulletproofGens, PedersenGens};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::gadget_vsmt_2::{VanillaSparseMerkleTree, gen_proof_of_leaf_membership_from_path,
+                               verify_proof_of_leaf_membership};
+
+    #[test]
+    fn test_dense_merkle_tree() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let leaves: Vec<Scalar> = (1..=256u32).map(|i| Scalar::from(i)).collect();
+        let tree = DenseMerkleTree::new(&leaves, &p_params);
+        assert_eq!(tree.depth, 8);
+        assert_eq!(tree.num_leaves(), 256);
+
+        // Same root as the sparse tree with the same leaves
+        let mut sparse_tree = VanillaSparseMerkleTree::new_with_depth(8, &p_params);
+        for (i, l) in leaves.iter().enumerate() {
+            sparse_tree.update(Scalar::from(i as u64), *l);
+        }
+        assert_eq!(tree.root(), sparse_tree.raw_root());
+
+        for i in [0usize, 1, 77, 128, 255].iter() {
+            let proof = tree.proof(*i).unwrap();
+            let mut sparse_proof = Some(Vec::<Scalar>::new());
+            sparse_tree.get(Scalar::from(*i as u64), &mut sparse_proof);
+            assert_eq!(proof, sparse_proof.unwrap());
+            assert!(tree.verify_proof(*i, leaves[*i], &proof));
+            assert!(!tree.verify_proof(*i, leaves[*i] + Scalar::one(), &proof));
+        }
+        assert!(tree.proof(256).is_none());
+
+        let index = 77;
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 13, 1);
+        let (proof, commitments) = gen_proof_of_leaf_membership_from_path(&tree.root(), &p_params, Scalar::from(index as u64),
+                                                                          tree.leaf(index).unwrap(), &tree.proof(index).unwrap(),
+                                                                          &mut test_rng, b"DenseMT", &pc_gens, &bp_gens).unwrap();
+        assert!(verify_proof_of_leaf_membership(tree.depth, &tree.root(), &p_params, proof, commitments, b"DenseMT",
+                                                &pc_gens, &bp_gens).is_ok());
+    }
+
+    #[test]
+    fn test_dense_merkle_tree_padding() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let leaves: Vec<Scalar> = (1..=5u32).map(|i| Scalar::from(i)).collect();
+        let tree = DenseMerkleTree::new(&leaves, &p_params);
+        assert_eq!(tree.depth, 3);
+        assert_eq!(tree.leaf(4), Some(Scalar::from(5u32)));
+        assert_eq!(tree.leaf(5), Some(Scalar::zero()));
+        assert!(tree.verify_proof(6, Scalar::zero(), &tree.proof(6).unwrap()));
+
+        let tree = DenseMerkleTree::new(&[], &p_params);
+        assert_eq!(tree.depth, 1);
+        assert!(tree.verify_proof(0, Scalar::zero(), &tree.proof(0).unwrap()));
+    }
+}
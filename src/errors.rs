@@ -0,0 +1,64 @@
+use bulletproofs::r1cs::R1CSError;
+use curve25519_dalek::scalar::Scalar;
+use std::fmt;
+
+/// Errors returned by the gadgets. Keeps misuse of a gadget, like passing a proof of the wrong length,
+/// separate from the errors of the constraint system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GadgetError {
+    /// Error while building the constraint system
+    ConstraintSystem(R1CSError),
+    /// Depth of the tree is not supported by the gadget
+    InvalidDepth(usize),
+    /// Number of proof nodes or leaf index bits does not match the depth of the tree
+    ProofLengthMismatch { expected: usize, found: usize },
+    /// Prover did not provide a value that is needed to build the constraint system
+    MissingAssignment,
+    /// Round keys or MDS matrix do not match the width and number of rounds of the hash function, or values
+    /// precomputed with the hash function are invalid
+    InvalidHashParams { description: String },
+    /// Opening given by the prover does not match the expected commitment
+    CommitmentMismatch,
+    /// Child index in a path is not less than the arity of the tree
+    InvalidPath { level: usize, child: u8 },
+    /// Trees being merged have different non-empty leaves at the same position
+    ConflictingLeaf,
+    /// Children stored for the node do not hash to it, or the node is missing from the store
+    CorruptNode(Scalar),
+    /// Leaves are not in increasing order of their index
+    UnsortedLeaves,
+    /// Index has bits set beyond the depth of the tree so it would be truncated to the index of another leaf
+    IndexOutOfRange { depth: usize },
+    /// Hashed key is placed at the same leaf as another key of the tree
+    KeyCollision,
+    /// Witness was committed to more than once by the same prover
+    AlreadyCommitted,
+}
+
+impl From<R1CSError> for GadgetError {
+    fn from(e: R1CSError) -> Self {
+        GadgetError::ConstraintSystem(e)
+    }
+}
+
+impl fmt::Display for GadgetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GadgetError::ConstraintSystem(e) => write!(f, "constraint system error: {:?}", e),
+            GadgetError::InvalidDepth(d) => write!(f, "invalid tree depth {}", d),
+            GadgetError::ProofLengthMismatch { expected, found } => write!(f, "expected {} elements in proof but found {}", expected, found),
+            GadgetError::MissingAssignment => "missing assignment for a variable".fmt(f),
+            GadgetError::InvalidHashParams { description } => write!(f, "invalid hash parameters: {}", description),
+            GadgetError::CommitmentMismatch => "opening does not match the commitment".fmt(f),
+            GadgetError::InvalidPath { level, child } => write!(f, "invalid child {} at level {} of path", child, level),
+            GadgetError::ConflictingLeaf => "trees have different leaves at the same position".fmt(f),
+            GadgetError::CorruptNode(node) => write!(f, "node {:?} is missing or its children do not hash to it", node.as_bytes()),
+            GadgetError::UnsortedLeaves => "leaves are not sorted by index".fmt(f),
+            GadgetError::IndexOutOfRange { depth } => write!(f, "index does not fit in a tree of depth {}", depth),
+            GadgetError::KeyCollision => "key is placed at the same leaf as another key".fmt(f),
+            GadgetError::AlreadyCommitted => "witness is already committed".fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for GadgetError {}
@@ -12,7 +12,8 @@ use merlin::Transcript;
 use rand::{RngCore, CryptoRng};
 use std::cmp;
 
-use crate::r1cs_utils::{AllocatedQuantity, positive_no_gadget, constrain_lc_with_scalar};
+use crate::r1cs_utils::{AllocatedQuantity, AllocatedScalar, positive_no_gadget, constrain_lc_with_scalar};
+use crate::scalar_utils::{scalar_to_u64_array, get_bits};
 
 
 pub fn bound_check_gadget<CS: ConstraintSystem>(
@@ -44,6 +45,122 @@ pub fn bound_check_gadget<CS: ConstraintSystem>(
     Ok(())
 }
 
+/// Enforces that `a <= b` where both are less than 2^`n_bits`. Proves that `a` and `b - a` are in [0, 2^n_bits) so that
+/// `b - a` cannot wrap around the order of the group. Composed with the membership of `a` and `b` in a tree this proves
+/// the ordering of two leaves without revealing them. `n_bits` should not be more than 64.
+pub fn leaf_ordering_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    a: AllocatedScalar,
+    b: AllocatedScalar,
+    n_bits: usize
+) -> Result<(), R1CSError> {
+    if n_bits > 64 {
+        return Err(R1CSError::GadgetError {description: format!("at most 64 bits are supported but {} given", n_bits)});
+    }
+
+    // If a > b or a is too large the assignments do not have the right bits and the constraints are not satisfied
+    let a_val = a.assignment.map(|v| scalar_to_u64_array(&v)[0]);
+    let diff = match (a.assignment, b.assignment) {
+        (Some(a_v), Some(b_v)) => Some(b_v - a_v),
+        _ => None
+    };
+    let diff_val = diff.map(|d| scalar_to_u64_array(&d)[0]);
+
+    let var_diff = cs.allocate(diff)?;
+
+    // diff = b - a
+    cs.constrain(b.variable - a.variable - var_diff);
+
+    // Constrain a in [0, 2^n)
+    positive_no_gadget(cs, AllocatedQuantity { variable: a.variable, assignment: a_val }, n_bits)?;
+    // Constrain b - a in [0, 2^n)
+    positive_no_gadget(cs, AllocatedQuantity { variable: var_diff, assignment: diff_val }, n_bits)?;
+
+    Ok(())
+}
+
+/// Enforces that `lo <= x <= hi` where all are less than 2^`n_bits`, by proving the ordering of `lo` and `x` and of `x`
+/// and `hi` with `leaf_ordering_gadget`. With `lo` and `hi` as adjacent leaves of a tree sorted by value, this proves
+/// that `x` falls between them, as for range queries. `n_bits` should not be more than 64.
+pub fn leaf_between_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    x: AllocatedScalar,
+    lo: AllocatedScalar,
+    hi: AllocatedScalar,
+    n_bits: usize
+) -> Result<(), R1CSError> {
+    // x - lo in [0, 2^n)
+    leaf_ordering_gadget(cs, lo, x, n_bits)?;
+    // hi - x in [0, 2^n)
+    leaf_ordering_gadget(cs, x, hi, n_bits)
+}
+
+/// Enforces that the sum of `leaves` is `expected_total`, e.g. balances from leaves proven to be in a tree. The sum is
+/// modulo the group order, so unless the leaves are known to be small, pass `bound_bits` to prove that each leaf is in
+/// [0, 2^bound_bits) so that the sum cannot wrap around. `bound_bits` should not be more than 64.
+pub fn leaves_sum_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    leaves: &[AllocatedScalar],
+    expected_total: Scalar,
+    bound_bits: Option<usize>
+) -> Result<(), R1CSError> {
+    if let Some(n_bits) = bound_bits {
+        if n_bits > 64 {
+            return Err(R1CSError::GadgetError {description: format!("at most 64 bits are supported but {} given", n_bits)});
+        }
+        for l in leaves {
+            let val = l.assignment.map(|v| scalar_to_u64_array(&v)[0]);
+            positive_no_gadget(cs, AllocatedQuantity { variable: l.variable, assignment: val }, n_bits)?;
+        }
+    }
+
+    let sum: LinearCombination = leaves.iter().map(|l| (l.variable, Scalar::one())).collect();
+    constrain_lc_with_scalar::<CS>(cs, sum, &expected_total);
+
+    Ok(())
+}
+
+/// Enforces that `val` is a power of two less than 2^`n_bits`, i.e. exactly one of its `n_bits` lower bits is set and
+/// no other bit is, e.g. a leaf that encodes a single flag among `n_bits` options. `n_bits` should not be more than 252
+/// so that the sum of the bits cannot wrap around the group order.
+pub fn is_power_of_two_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    val: AllocatedScalar,
+    n_bits: usize
+) -> Result<(), R1CSError> {
+    if n_bits > 252 {
+        return Err(R1CSError::GadgetError {description: format!("at most 252 bits are supported but {} given", n_bits)});
+    }
+
+    let bits = val.assignment.map(|v| get_bits(&v, n_bits));
+    let mut weighted_sum = vec![(val.variable, -Scalar::one())];
+    let mut bit_sum = LinearCombination::default();
+    let mut exp_2 = Scalar::one();
+    for i in 0..n_bits {
+        let (a, b, o) = cs.allocate_multiplier(bits.as_ref().map(|bits| {
+            let bit = Scalar::from(bits[i]);
+            (Scalar::one() - bit, bit)
+        }))?;
+
+        // Enforce a * b = 0, so one of (a,b) is zero
+        cs.constrain(o.into());
+
+        // Enforce that a = 1 - b, so they both are 1 or 0.
+        cs.constrain(a + (b - Scalar::one()));
+
+        weighted_sum.push((b, exp_2));
+        bit_sum = bit_sum + b;
+        exp_2 = exp_2 + exp_2;
+    }
+
+    // Bits add up to val
+    cs.constrain(weighted_sum.iter().collect());
+    // Exactly one bit is set
+    constrain_lc_with_scalar::<CS>(cs, bit_sum, &Scalar::one());
+
+    Ok(())
+}
+
 /// Accepts the num for which the bounds have to proved and optionally the randomness used in committing to that number.
 /// This randomness argument is accepted so that this can be used as a sub-protocol where the protocol on upper layer will create the commitment.
 pub fn gen_proof_of_bounded_num<R: RngCore + CryptoRng>(val: u64, randomness: Option<Scalar>, lower: u64, upper: u64,
@@ -169,4 +286,113 @@ mod tests {
         bound_check(min, max, bit_size);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_leaves_sum_gadget() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(256, 1);
+
+        let sum_proof = |leaves: &[Scalar], total: Scalar, bound_bits: Option<usize>| -> bool {
+            prove_and_verify!(b"LeavesSumTest", &bp_gens, |cs, inputs| {
+                let allocs = inputs.values(cs, leaves);
+                leaves_sum_gadget(cs, &allocs, total, bound_bits)
+            })
+        };
+
+        let leaves = [Scalar::from(10u64), Scalar::from(250u64), Scalar::from(5000u64)];
+        assert!(sum_proof(&leaves, Scalar::from(5260u64), None));
+        assert!(sum_proof(&leaves, Scalar::from(5260u64), Some(32)));
+        assert!(!sum_proof(&leaves, Scalar::from(5261u64), None));
+
+        // A leaf that is -1 modulo the group order gives the right sum only if the leaves are not bounded
+        let wrapping = [Scalar::from(10u64), -Scalar::one(), Scalar::from(5000u64)];
+        assert!(sum_proof(&wrapping, Scalar::from(5009u64), None));
+        assert!(!sum_proof(&wrapping, Scalar::from(5009u64), Some(32)));
+
+        let mut prover_transcript = Transcript::new(b"LeavesSumTest");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        assert!(leaves_sum_gadget(&mut prover, &[], Scalar::zero(), Some(65)).is_err());
+    }
+
+    #[test]
+    fn test_leaf_ordering_gadget() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(256, 1);
+        let n_bits = 64;
+
+        let ordering = |a: Scalar, b: Scalar| -> bool {
+            prove_and_verify!(b"LeafOrderingTest", &bp_gens, |cs, inputs| {
+                let alloc_a = inputs.value(cs, a);
+                let alloc_b = inputs.value(cs, b);
+                leaf_ordering_gadget(cs, alloc_a, alloc_b, n_bits)
+            })
+        };
+
+        assert!(ordering(Scalar::from(10u64), Scalar::from(1000u64)));
+        assert!(ordering(Scalar::from(500u64), Scalar::from(500u64)));
+        assert!(!ordering(Scalar::from(1000u64), Scalar::from(10u64)));
+        // a wraps around to a small b - a
+        assert!(!ordering(-Scalar::one(), Scalar::from(10u64)));
+
+        let mut prover_transcript = Transcript::new(b"LeafOrderingTest");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        let (_, var) = prover.commit(Scalar::one(), Scalar::one());
+        let alloc = AllocatedScalar { variable: var, assignment: Some(Scalar::one()) };
+        assert!(leaf_ordering_gadget(&mut prover, alloc, alloc, 65).is_err());
+    }
+
+    #[test]
+    fn test_is_power_of_two_gadget() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(64, 1);
+        let n_bits = 16;
+
+        let power_of_two = |val: Scalar| -> bool {
+            prove_and_verify!(b"PowerOfTwoTest", &bp_gens, |cs, inputs| {
+                let alloc = inputs.value(cs, val);
+                is_power_of_two_gadget(cs, alloc, n_bits)
+            })
+        };
+
+        assert!(power_of_two(Scalar::from(1u64)));
+        assert!(power_of_two(Scalar::from(2u64)));
+        assert!(power_of_two(Scalar::from(4u64)));
+        assert!(power_of_two(Scalar::from(1u64 << 15)));
+        assert!(!power_of_two(Scalar::from(3u64)));
+        assert!(!power_of_two(Scalar::zero()));
+        assert!(!power_of_two(Scalar::from(6u64)));
+        // Power of two beyond `n_bits`
+        assert!(!power_of_two(Scalar::from(1u64 << 16)));
+
+        let mut prover_transcript = Transcript::new(b"PowerOfTwoTest");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        let (_, var) = prover.commit(Scalar::one(), Scalar::one());
+        let alloc = AllocatedScalar { variable: var, assignment: Some(Scalar::one()) };
+        assert!(is_power_of_two_gadget(&mut prover, alloc, 253).is_err());
+    }
+
+    #[test]
+    fn test_leaf_between_gadget() {
+        let bp_gens = BulletproofGens::new(512, 1);
+        let n_bits = 64;
+
+        let between = |x: Scalar, lo: Scalar, hi: Scalar| -> bool {
+            prove_and_verify!(b"LeafBetweenTest", &bp_gens, |cs, inputs| {
+                let allocs = inputs.values(cs, &[x, lo, hi]);
+                leaf_between_gadget(cs, allocs[0], allocs[1], allocs[2], n_bits)
+            })
+        };
+
+        let (lo, hi) = (Scalar::from(100u64), Scalar::from(200u64));
+        // In range
+        assert!(between(Scalar::from(150u64), lo, hi));
+        // At the boundaries
+        assert!(between(lo, lo, hi));
+        assert!(between(hi, lo, hi));
+        assert!(between(lo, lo, lo));
+        // Out of range
+        assert!(!between(Scalar::from(99u64), lo, hi));
+        assert!(!between(Scalar::from(201u64), lo, hi));
+        assert!(!between(Scalar::from(150u64), hi, lo));
+        assert!(!between(-Scalar::one(), lo, hi));
+    }
+}
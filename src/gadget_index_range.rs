@@ -0,0 +1,139 @@
+extern crate bulletproofs;
+extern crate curve25519_dalek;
+extern crate merlin;
+extern crate rand;
+
+use bulletproofs::r1cs::{ConstraintSystem, R1CSError, R1CSProof, Variable, Prover, Verifier};
+use curve25519_dalek::scalar::Scalar;
+use bulletproofs::{BulletproofGens, PedersenGens};
+use curve25519_dalek::ristretto::CompressedRistretto;
+use bulletproofs::r1cs::LinearCombination;
+
+use crate::r1cs_utils::{AllocatedQuantity, AllocatedScalar};
+use crate::gadget_bound_check::bound_check_gadget;
+
+/// Enforces that the index represented by `index_bits` lies in [lo, hi].
+/// `index_bits` are the same bits (LSB first) passed to the merkle tree gadget so the index
+/// is reconstructed as a linear combination of them and does not have to be committed again.
+pub fn index_range_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    index_bits: &[AllocatedScalar],
+    lo: u64,
+    hi: u64
+) -> Result<(), R1CSError> {
+    if index_bits.len() > 64 {
+        return Err(R1CSError::GadgetError {description: String::from("Index should fit in 64 bits")});
+    }
+    if lo > hi {
+        return Err(R1CSError::GadgetError {description: String::from("Lower bound should not exceed upper bound")});
+    }
+
+    // index = Sum(b_i * 2^i, i = 0..n-1)
+    let mut index_terms: Vec<(Variable, Scalar)> = vec![];
+    let mut index_val = Some(0u64);
+    let mut exp_2 = Scalar::one();
+    for (i, b) in index_bits.iter().enumerate() {
+        index_terms.push((b.variable, exp_2));
+        index_val = match (index_val, b.assignment) {
+            (Some(v), Some(bit)) => Some(v | ((bit.as_bytes()[0] as u64 & 1) << i)),
+            _ => None
+        };
+        exp_2 = exp_2 + exp_2;
+    }
+
+    // The prover might have an index outside the range in which case the subtraction below wraps
+    // and the proof will fail to verify.
+    let a_val = index_val.map(|v| v.wrapping_sub(lo));
+    let b_val = index_val.map(|v| hi.wrapping_sub(v));
+
+    let var_v = cs.allocate(index_val.map(|v| Scalar::from(v)))?;
+    let var_a = cs.allocate(a_val.map(|v| Scalar::from(v)))?;
+    let var_b = cs.allocate(b_val.map(|v| Scalar::from(v)))?;
+
+    // Allocated index should be same as the index formed from the bits
+    index_terms.push((var_v, -Scalar::one()));
+    cs.constrain(index_terms.iter().collect());
+
+    let bit_size = count_bits(hi - lo);
+    bound_check_gadget(
+        cs,
+        AllocatedQuantity { variable: var_v, assignment: index_val },
+        AllocatedQuantity { variable: var_a, assignment: a_val },
+        AllocatedQuantity { variable: var_b, assignment: b_val },
+        hi,
+        lo,
+        bit_size
+    )
+}
+
+/// Number of bits needed to represent `number`, at least 1.
+fn count_bits(number: u64) -> usize {
+    let used_bits = 64 - number.leading_zeros();
+    if used_bits == 0 { 1 } else { used_bits as usize }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merlin::Transcript;
+    use crate::scalar_utils::get_bits;
+
+    #[test]
+    fn test_index_range_gadget() {
+        // Index inside the range
+        assert!(index_range_gadget_helper(7, 5, 20).is_ok());
+        assert!(index_range_gadget_helper(5, 5, 20).is_ok());
+        assert!(index_range_gadget_helper(20, 5, 20).is_ok());
+
+        // Index outside the range
+        assert!(index_range_gadget_helper(4, 5, 20).is_err());
+        assert!(index_range_gadget_helper(21, 5, 20).is_err());
+    }
+
+    fn index_range_gadget_helper(index: u64, lo: u64, hi: u64) -> Result<(), R1CSError> {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(256, 1);
+        let num_bits = 32;
+
+        let (proof, commitments) = {
+            let mut comms: Vec<CompressedRistretto> = vec![];
+
+            let mut prover_transcript = Transcript::new(b"IndexRangeTest");
+            let mut rng = rand::thread_rng();
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+            let mut index_bits = vec![];
+            for b in get_bits(&Scalar::from(index), num_bits).iter() {
+                let val = Scalar::from(*b);
+                let (c, v) = prover.commit(val, Scalar::random(&mut rng));
+                comms.push(c);
+                index_bits.push(AllocatedScalar {
+                    variable: v,
+                    assignment: Some(val),
+                });
+            }
+
+            assert!(index_range_gadget(&mut prover, &index_bits, lo, hi).is_ok());
+
+            let proof = prover.prove(&bp_gens)?;
+
+            (proof, comms)
+        };
+
+        let mut verifier_transcript = Transcript::new(b"IndexRangeTest");
+        let mut verifier = Verifier::new(&mut verifier_transcript);
+
+        let mut index_bits = vec![];
+        for c in commitments {
+            let v = verifier.commit(c);
+            index_bits.push(AllocatedScalar {
+                variable: v,
+                assignment: None,
+            });
+        }
+
+        assert!(index_range_gadget(&mut verifier, &index_bits, lo, hi).is_ok());
+
+        Ok(verifier.verify(&proof, &pc_gens, &bp_gens)?)
+    }
+}
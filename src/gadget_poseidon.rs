@@ -13,9 +13,11 @@ use merlin::Transcript;
 use bulletproofs::r1cs::LinearCombination;
 
 use crate::r1cs_utils::{AllocatedScalar, constrain_lc_with_scalar};
+use crate::errors::GadgetError;
 use crate::gadget_zero_nonzero::is_nonzero_gadget;
 use crate::poseidon_constants::{MDS_ENTRIES, ROUND_CONSTS};
-use crate::scalar_utils::get_scalar_from_hex;
+use crate::scalar_utils::{get_scalar_from_hex, bytes_to_scalars, scalar_from_bytes, BytesPerScalar};
+use byteorder::{ByteOrder, LittleEndian};
 use rand::SeedableRng;
 use rand::rngs::StdRng;
 use curve25519_dalek::ristretto::CompressedRistretto;
@@ -51,6 +53,89 @@ impl PoseidonParams {
         }
     }
 
+    /// Create parameters from given round keys and MDS matrix, like the ones of a fixed specification. `round_keys`
+    /// has `width` constants for each round in order of the rounds and `mds` is a `width` x `width` matrix.
+    pub fn from_constants(width: usize, full_rounds_beginning: usize, full_rounds_end: usize, partial_rounds: usize,
+                          round_keys: Vec<Scalar>, mds: Vec<Vec<Scalar>>) -> Result<PoseidonParams, GadgetError> {
+        let total_rounds = full_rounds_beginning + partial_rounds + full_rounds_end;
+        if round_keys.len() != total_rounds * width {
+            return Err(GadgetError::InvalidHashParams {
+                description: format!("expected {} round keys but found {}", total_rounds * width, round_keys.len())
+            });
+        }
+        if mds.len() != width || mds.iter().any(|row| row.len() != width) {
+            return Err(GadgetError::InvalidHashParams {
+                description: format!("MDS matrix should be {} x {}", width, width)
+            });
+        }
+        Ok(PoseidonParams {
+            width,
+            full_rounds_beginning,
+            full_rounds_end,
+            partial_rounds,
+            round_keys,
+            MDS_matrix: mds
+        })
+    }
+
+    /// Width and the number of rounds as 4 bytes little endian each, followed by the round keys and then the rows
+    /// of the MDS matrix with 32 bytes for each scalar.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; 16];
+        LittleEndian::write_u32(&mut bytes[0..4], self.width as u32);
+        LittleEndian::write_u32(&mut bytes[4..8], self.full_rounds_beginning as u32);
+        LittleEndian::write_u32(&mut bytes[8..12], self.full_rounds_end as u32);
+        LittleEndian::write_u32(&mut bytes[12..16], self.partial_rounds as u32);
+        for k in &self.round_keys {
+            bytes.extend_from_slice(k.as_bytes());
+        }
+        for row in &self.MDS_matrix {
+            for e in row {
+                bytes.extend_from_slice(e.as_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Parse the output of `to_bytes`. The number of round keys and size of the MDS matrix are checked as in `from_constants`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PoseidonParams, GadgetError> {
+        if bytes.len() < 16 {
+            return Err(GadgetError::InvalidHashParams { description: format!("expected at least 16 bytes but found {}", bytes.len()) });
+        }
+        let width = LittleEndian::read_u32(&bytes[0..4]) as usize;
+        let full_rounds_beginning = LittleEndian::read_u32(&bytes[4..8]) as usize;
+        let full_rounds_end = LittleEndian::read_u32(&bytes[8..12]) as usize;
+        let partial_rounds = LittleEndian::read_u32(&bytes[12..16]) as usize;
+        if width == 0 {
+            return Err(GadgetError::InvalidHashParams { description: String::from("width should not be 0") });
+        }
+
+        // The header is not trusted so the sizes it gives can overflow
+        let num_round_keys = full_rounds_beginning.checked_add(partial_rounds)
+            .and_then(|r| r.checked_add(full_rounds_end))
+            .and_then(|r| r.checked_mul(width));
+        let expected_len = num_round_keys
+            .and_then(|n| width.checked_mul(width).and_then(|m| n.checked_add(m)))
+            .and_then(|n| n.checked_mul(32))
+            .and_then(|n| n.checked_add(16));
+        let (num_round_keys, expected_len) = match (num_round_keys, expected_len) {
+            (Some(n), Some(l)) => (n, l),
+            _ => return Err(GadgetError::InvalidHashParams { description: String::from("sizes in the header are too large") })
+        };
+        if bytes.len() != expected_len {
+            return Err(GadgetError::InvalidHashParams { description: format!("expected {} bytes but found {}", expected_len, bytes.len()) });
+        }
+
+        let mut scalars = vec![];
+        for chunk in bytes[16..].chunks(32) {
+            let s = scalar_from_bytes(chunk).map_err(|e| GadgetError::InvalidHashParams { description: e.to_string() })?;
+            scalars.push(s);
+        }
+        let mds = scalars.split_off(num_round_keys).chunks(width).map(|row| row.to_vec()).collect();
+
+        Self::from_constants(width, full_rounds_beginning, full_rounds_end, partial_rounds, scalars, mds)
+    }
+
     // TODO: Write logic to generate correct round keys.
     fn gen_round_keys(width: usize, total_rounds: usize) -> Vec<Scalar> {
         let cap = total_rounds * width;
@@ -91,6 +176,16 @@ impl PoseidonParams {
     pub fn get_total_rounds(&self) -> usize {
         self.full_rounds_beginning + self.partial_rounds + self.full_rounds_end
     }
+
+    /// Round constants, `width` constants for each round in order of the rounds
+    pub fn round_keys(&self) -> &[Scalar] {
+        &self.round_keys
+    }
+
+    /// MDS matrix of size `width` x `width`
+    pub fn mds(&self) -> &[Vec<Scalar>] {
+        &self.MDS_matrix
+    }
 }
 
 /// Simplify linear combination by taking Variables common across terms and adding their corresponding scalars.
@@ -111,6 +206,7 @@ fn simplify_lc(lc: LinearCombination) -> LinearCombination {
     new_lc_terms.iter().collect()
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SboxType {
     Cube,
     Inverse
@@ -429,14 +525,14 @@ pub fn Poseidon_hash_2(xl: Scalar, xr: Scalar, params: &PoseidonParams, sbox: &S
     // Only 2 inputs to the permutation are set to the input of this hash function,
     // one is set to the padding constant and rest are 0. Always keep the 1st input as 0
 
-    let input = vec![
+    assert!(params.width >= 4);
+    let mut input = vec![
         Scalar::from(ZERO_CONST),
         xl,
         xr,
-        Scalar::from(PADDING_CONST),
-        Scalar::from(ZERO_CONST),
-        Scalar::from(ZERO_CONST)
+        Scalar::from(PADDING_CONST)
     ];
+    input.resize(params.width, Scalar::from(ZERO_CONST));
 
     // Never take the first output
     Poseidon_permutation(&input, params, sbox)[1]
@@ -485,6 +581,24 @@ pub fn Poseidon_hash_2_gadget<'a, CS: ConstraintSystem>(
     Ok(())
 }
 
+/// Hiding commitment to `value` using `blinding` as randomness, C = Poseidon_hash_2(value, blinding).
+pub fn poseidon_commit(value: Scalar, blinding: Scalar, params: &PoseidonParams) -> Scalar {
+    Poseidon_hash_2(value, blinding, params, &SboxType::Inverse)
+}
+
+/// Returns the commitment to `value` with `blinding` as randomness as a linear combination. Can be used to prove
+/// knowledge of the opening of a commitment created with `poseidon_commit`.
+pub fn poseidon_commit_gadget<'a, CS: ConstraintSystem>(
+    cs: &mut CS,
+    value: AllocatedScalar,
+    blinding: AllocatedScalar,
+    params: &'a PoseidonParams,
+    statics: Vec<AllocatedScalar>
+) -> Result<LinearCombination, R1CSError> {
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+    Poseidon_hash_2_constraints::<CS>(cs, value.variable.into(), blinding.variable.into(), statics, params, &SboxType::Inverse)
+}
+
 pub fn Poseidon_hash_4(inputs: [Scalar; 4], params: &PoseidonParams, sbox: &SboxType) -> Scalar {
     // Only 4 inputs to the permutation are set to the input of this hash function,
     // one is set to the padding constant and one is set to 0. Always keep the 1st input as 0
@@ -550,6 +664,158 @@ pub fn Poseidon_hash_4_gadget<'a, CS: ConstraintSystem>(
     Ok(())
 }
 
+/// Hash any number of inputs by absorbing them, `width - 2` at a time, into the state of the permutation.
+/// The 1st element of the state is never absorbed into and is never output. The last element of the state
+/// is initialized with the number of inputs so that inputs of different lengths do not collide.
+pub fn Poseidon_hash_n(inputs: &[Scalar], params: &PoseidonParams, sbox: &SboxType) -> Scalar {
+    let width = params.width;
+    let rate = width - 2;
+
+    let mut state = vec![Scalar::zero(); width];
+    state[width-1] = Scalar::from(inputs.len() as u64);
+
+    if inputs.is_empty() {
+        state = Poseidon_permutation(&state, params, sbox);
+    }
+    for chunk in inputs.chunks(rate) {
+        for (i, inp) in chunk.iter().enumerate() {
+            state[i+1] += inp;
+        }
+        state = Poseidon_permutation(&state, params, sbox);
+    }
+
+    // Never take the first output
+    state[1]
+}
+
+pub fn Poseidon_hash_n_constraints<'a, CS: ConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<LinearCombination>,
+    params: &'a PoseidonParams,
+    sbox_type: &SboxType,
+) -> Result<LinearCombination, R1CSError> {
+    let width = params.width;
+    let rate = width - 2;
+
+    let mut state = vec![LinearCombination::default(); width];
+    state[width-1] = LinearCombination::from(Scalar::from(inputs.len() as u64));
+
+    if inputs.is_empty() {
+        state = Poseidon_permutation_constraints::<CS>(cs, state, params, sbox_type)?;
+    }
+    for chunk in inputs.chunks(rate) {
+        for (i, inp) in chunk.iter().enumerate() {
+            state[i+1] = state[i+1].clone() + inp.clone();
+        }
+        state = Poseidon_permutation_constraints::<CS>(cs, state, params, sbox_type)?;
+    }
+
+    Ok(state[1].to_owned())
+}
+
+pub fn Poseidon_hash_n_gadget<'a, CS: ConstraintSystem>(
+    cs: &mut CS,
+    inputs: Vec<AllocatedScalar>,
+    params: &'a PoseidonParams,
+    sbox_type: &SboxType,
+    output: &Scalar
+) -> Result<(), R1CSError> {
+
+    let inputs: Vec<LinearCombination> = inputs.iter().map(|s| s.variable.into()).collect();
+    let hash = Poseidon_hash_n_constraints::<CS>(cs, inputs, params, sbox_type)?;
+
+    constrain_lc_with_scalar::<CS>(cs, hash, output);
+
+    Ok(())
+}
+
+/// 8:1 hash. The permutation is not wide enough to take all 8 inputs at once so they are absorbed
+/// with `Poseidon_hash_n`, needing 2 permutations.
+pub fn Poseidon_hash_8(inputs: [Scalar; 8], params: &PoseidonParams, sbox: &SboxType) -> Scalar {
+    Poseidon_hash_n(&inputs, params, sbox)
+}
+
+pub fn Poseidon_hash_8_constraints<'a, CS: ConstraintSystem>(
+    cs: &mut CS,
+    input: [LinearCombination; 8],
+    params: &'a PoseidonParams,
+    sbox_type: &SboxType,
+) -> Result<LinearCombination, R1CSError> {
+    Poseidon_hash_n_constraints::<CS>(cs, input.to_vec(), params, sbox_type)
+}
+
+/// Hash a byte string. The bytes are split into scalars with `bytes_to_scalars` and the number of bytes
+/// is prepended so that byte strings differing only in trailing zeroes do not collide.
+pub fn Poseidon_hash_bytes(bytes: &[u8], params: &PoseidonParams, sbox: &SboxType) -> Scalar {
+    let mut inputs = vec![Scalar::from(bytes.len() as u64)];
+    inputs.extend(bytes_to_scalars(bytes));
+    Poseidon_hash_n(&inputs, params, sbox)
+}
+
+/// Constraints for `Poseidon_hash_bytes` of a byte string of `num_bytes` bytes given as the scalars returned by
+/// `bytes_to_scalars`, so one scalar for every `BytesPerScalar` bytes. The length is a constant of the circuit. The
+/// scalars are not checked to fit in `BytesPerScalar` bytes, finding scalars other than those of the bytes that hash to
+/// the same output is as hard as finding a second preimage.
+pub fn Poseidon_hash_bytes_constraints<'a, CS: ConstraintSystem>(
+    cs: &mut CS,
+    num_bytes: usize,
+    chunks: Vec<LinearCombination>,
+    params: &'a PoseidonParams,
+    sbox_type: &SboxType,
+) -> Result<LinearCombination, R1CSError> {
+    let num_chunks = (num_bytes + BytesPerScalar - 1) / BytesPerScalar;
+    if chunks.len() != num_chunks {
+        return Err(R1CSError::GadgetError {description: format!("expected {} scalars for {} bytes but found {}", num_chunks, num_bytes, chunks.len())});
+    }
+
+    let mut inputs = vec![LinearCombination::from(Scalar::from(num_bytes as u64))];
+    inputs.extend(chunks);
+    Poseidon_hash_n_constraints::<CS>(cs, inputs, params, sbox_type)
+}
+
+pub fn Poseidon_hash_bytes_gadget<'a, CS: ConstraintSystem>(
+    cs: &mut CS,
+    num_bytes: usize,
+    chunks: Vec<AllocatedScalar>,
+    params: &'a PoseidonParams,
+    sbox_type: &SboxType,
+    output: &Scalar
+) -> Result<(), R1CSError> {
+
+    let chunks: Vec<LinearCombination> = chunks.iter().map(|s| s.variable.into()).collect();
+    let hash = Poseidon_hash_bytes_constraints::<CS>(cs, num_bytes, chunks, params, sbox_type)?;
+
+    constrain_lc_with_scalar::<CS>(cs, hash, output);
+
+    Ok(())
+}
+
+/// Known answer tests for the hash functions with the given parameters and the inverse S-box, which is what the
+/// merkle trees use. Returns pairs of inputs and output, in this order:
+/// 1. Poseidon_hash_2 of (0, 0)
+/// 2. Poseidon_hash_2 of (1, 1)
+/// 3. Poseidon_hash_2 of (1, 2)
+/// 4. Poseidon_hash_4 of (0, 1, 2, 3)
+/// Other implementations can compare against these to catch a mismatch in parameters.
+pub fn poseidon_test_vectors(params: &PoseidonParams) -> Vec<(Vec<Scalar>, Scalar)> {
+    let sbox = SboxType::Inverse;
+    let mut vectors = vec![];
+    for (l, r) in vec![(0u64, 0u64), (1, 1), (1, 2)] {
+        let (xl, xr) = (Scalar::from(l), Scalar::from(r));
+        vectors.push((vec![xl, xr], Poseidon_hash_2(xl, xr, params, &sbox)));
+    }
+    let input = [Scalar::from(0u64), Scalar::from(1u64), Scalar::from(2u64), Scalar::from(3u64)];
+    vectors.push((input.to_vec(), Poseidon_hash_4(input, params, &sbox)));
+    vectors
+}
+
+/// Number of statics, i.e. the padding constant and zeroes, needed by `Poseidon_hash_2_constraints` with `params`. The
+/// permutation takes `width` inputs of which 2 are the inputs of the hash, so this depends on the width and is 4 for
+/// width 6. `Poseidon_hash_4_constraints` needs 2 less.
+pub fn num_required_statics(params: &PoseidonParams) -> usize {
+    params.width - 2
+}
+
 /// Allocate padding constant and zeroes for Prover
 pub fn allocate_statics_for_prover(prover: &mut Prover, num_statics: usize) -> Vec<AllocatedScalar> {
     let mut statics = vec![];
@@ -610,6 +876,7 @@ pub fn allocate_statics_for_verifier(verifier: &mut Verifier, num_statics: usize
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scalar_utils::scalar_to_hex;
     // For benchmarking
     use std::time::{Duration, Instant};
     use std::sync::atomic::Ordering::SeqCst;
@@ -874,6 +1141,181 @@ mod tests {
         println!("Verification time is {:?}", end);
     }
 
+    fn poseidon_hash_n(sbox_type: &SboxType, transcript_label: &'static [u8]) {
+        let s_params = get_poseidon_params();
+
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let input = (0..7).map(|_| Scalar::random(&mut test_rng)).collect::<Vec<_>>();
+        let expected_output = Poseidon_hash_n(&input, &s_params, sbox_type);
+
+        // Different number of inputs give different hash
+        assert_ne!(expected_output, Poseidon_hash_n(&input[0..6], &s_params, sbox_type));
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(4096, 1);
+
+        let (proof, commitments) = {
+            let mut prover_transcript = Transcript::new(transcript_label);
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+            let mut comms = vec![];
+            let mut allocs = vec![];
+
+            for inp in input.iter() {
+                let (com, var) = prover.commit(inp.clone(), Scalar::random(&mut test_rng));
+                comms.push(com);
+                allocs.push(AllocatedScalar {
+                    variable: var,
+                    assignment: Some(inp.clone()),
+                });
+            }
+
+            assert!(Poseidon_hash_n_gadget(&mut prover,
+                                           allocs,
+                                           &s_params,
+                                           sbox_type,
+                                           &expected_output).is_ok());
+
+            println!("For Poseidon hash of {} inputs, no of constraints is {}, no of multipliers is {}", input.len(), &prover.num_constraints(), &prover.num_multipliers());
+
+            let proof = prover.prove(&bp_gens).unwrap();
+            (proof, comms)
+        };
+
+        let mut verifier_transcript = Transcript::new(transcript_label);
+        let mut verifier = Verifier::new(&mut verifier_transcript);
+        let mut allocs = vec![];
+        for com in commitments {
+            let v = verifier.commit(com);
+            allocs.push(AllocatedScalar {
+                variable: v,
+                assignment: None,
+            });
+        }
+
+        assert!(Poseidon_hash_n_gadget(&mut verifier,
+                                       allocs,
+                                       &s_params,
+                                       sbox_type,
+                                       &expected_output).is_ok());
+
+        assert!(verifier.verify(&proof, &pc_gens, &bp_gens).is_ok());
+    }
+
+    #[test]
+    fn test_poseidon_hash_n_cube_sbox() {
+        poseidon_hash_n(&SboxType::Cube, b"Poseidon_hash_n_cube");
+    }
+
+    #[test]
+    fn test_poseidon_hash_n_inverse_sbox() {
+        poseidon_hash_n(&SboxType::Inverse, b"Poseidon_hash_n_inverse");
+    }
+
+    #[test]
+    fn test_poseidon_test_vectors() {
+        let s_params = get_poseidon_params();
+        assert_eq!(s_params.round_keys().len(), s_params.get_total_rounds() * s_params.width);
+        assert_eq!(s_params.mds().len(), s_params.width);
+
+        // Outputs for width 6 with 4 full rounds at the beginning and end and 140 partial rounds, as computed by a
+        // separate implementation of the permutation over the same constants. A change here breaks every tree and
+        // proof created before.
+        let expected = [
+            "0x9545e53710401d3a82fdaff157057f56dc4d687f09ee7e045f72b516e96eec00",
+            "0x211bc21697d7b02f13efeab332590258a3978280a96c4e197aef1a1ad175060c",
+            "0xc69cbbcf39be8e422439786fb0511ae49fc30561e76ff888d9cf005196526704",
+            "0x98266d5e003145934d39ad5bd0c5a66ecb40521854ed344279a17a4ae809450d",
+        ];
+        let vectors = poseidon_test_vectors(&s_params);
+        assert_eq!(vectors.len(), expected.len());
+        for ((_, output), e) in vectors.iter().zip(expected.iter()) {
+            assert_eq!(scalar_to_hex(output), *e);
+        }
+        assert_eq!(vectors[2].0, vec![Scalar::from(1u64), Scalar::from(2u64)]);
+    }
+
+    #[test]
+    fn test_poseidon_params_from_constants() {
+        let s_params = get_poseidon_params();
+        let (width, full_b, full_e, partial_rounds) = (s_params.width, s_params.full_rounds_beginning,
+                                                      s_params.full_rounds_end, s_params.partial_rounds);
+
+        // Same constants give the same hash
+        let params = PoseidonParams::from_constants(width, full_b, full_e, partial_rounds,
+                                                    s_params.round_keys().to_vec(), s_params.mds().to_vec()).unwrap();
+        assert_eq!(Poseidon_hash_2(Scalar::from(1u64), Scalar::from(2u64), &params, &SboxType::Inverse),
+                   Poseidon_hash_2(Scalar::from(1u64), Scalar::from(2u64), &s_params, &SboxType::Inverse));
+
+        // Too few round keys
+        let mut round_keys = s_params.round_keys().to_vec();
+        round_keys.pop();
+        assert!(PoseidonParams::from_constants(width, full_b, full_e, partial_rounds,
+                                               round_keys, s_params.mds().to_vec()).is_err());
+
+        // Matrix with a missing row
+        let mut mds = s_params.mds().to_vec();
+        mds.pop();
+        assert!(PoseidonParams::from_constants(width, full_b, full_e, partial_rounds,
+                                               s_params.round_keys().to_vec(), mds).is_err());
+
+        // Matrix with a short row
+        let mut mds = s_params.mds().to_vec();
+        mds[1].pop();
+        assert!(PoseidonParams::from_constants(width, full_b, full_e, partial_rounds,
+                                               s_params.round_keys().to_vec(), mds).is_err());
+    }
+
+    #[test]
+    fn test_poseidon_params_serialization() {
+        let s_params = get_poseidon_params();
+        let bytes = s_params.to_bytes();
+        let params = PoseidonParams::from_bytes(&bytes).unwrap();
+        assert_eq!(params.width, s_params.width);
+        assert_eq!(params.get_total_rounds(), s_params.get_total_rounds());
+        assert_eq!(params.round_keys(), s_params.round_keys());
+        assert_eq!(params.mds(), s_params.mds());
+        assert_eq!(params.to_bytes(), bytes);
+        assert_eq!(Poseidon_hash_2(Scalar::from(1u64), Scalar::from(2u64), &params, &SboxType::Inverse),
+                   Poseidon_hash_2(Scalar::from(1u64), Scalar::from(2u64), &s_params, &SboxType::Inverse));
+
+        // Truncated bytes and a header that does not match the constants
+        assert!(PoseidonParams::from_bytes(&bytes[..bytes.len() - 32]).is_err());
+        assert!(PoseidonParams::from_bytes(&bytes[..10]).is_err());
+        let mut bad_bytes = bytes.clone();
+        bad_bytes[12] += 1;
+        assert!(PoseidonParams::from_bytes(&bad_bytes).is_err());
+
+        // Non canonical scalar
+        let mut bad_bytes = bytes.clone();
+        for b in bad_bytes[16..48].iter_mut() {
+            *b = 0xff;
+        }
+        assert!(PoseidonParams::from_bytes(&bad_bytes).is_err());
+
+        // Header whose number of round keys and MDS entries overflow
+        let mut bad_bytes = bytes.clone();
+        for b in bad_bytes[0..16].iter_mut() {
+            *b = 0xff;
+        }
+        match PoseidonParams::from_bytes(&bad_bytes) {
+            Err(GadgetError::InvalidHashParams { .. }) => (),
+            _ => panic!("expected InvalidHashParams")
+        }
+    }
+
+    #[test]
+    fn test_poseidon_hash_bytes() {
+        let s_params = get_poseidon_params();
+        let h = Poseidon_hash_bytes(&[1u8; 64], &s_params, &SboxType::Inverse);
+        assert_eq!(h, Poseidon_hash_bytes(&[1u8; 64], &s_params, &SboxType::Inverse));
+        assert_ne!(h, Poseidon_hash_bytes(&[1u8; 63], &s_params, &SboxType::Inverse));
+
+        // Trailing zeroes matter
+        assert_ne!(Poseidon_hash_bytes(&[1u8], &s_params, &SboxType::Inverse),
+                   Poseidon_hash_bytes(&[1u8, 0u8], &s_params, &SboxType::Inverse));
+    }
+
     #[test]
     fn test_poseidon_perm_cube_sbox() {
         poseidon_perm(&SboxType::Cube, b"Poseidon_perm_cube");
@@ -4,49 +4,398 @@ extern crate merlin;
 extern crate bulletproofs;
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use rand::SeedableRng;
-use rand::rngs::OsRng;
+use rand::{RngCore, CryptoRng};
 use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::ristretto::CompressedRistretto;
 use bulletproofs::r1cs::{ConstraintSystem, R1CSError, R1CSProof, Variable, Prover, Verifier};
 use bulletproofs::{BulletproofGens, PedersenGens};
 use merlin::Transcript;
 use bulletproofs::r1cs::LinearCombination;
+use once_cell::sync::Lazy;
+#[cfg(feature = "constant-time")]
+use subtle::ConstantTimeEq;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, Zeroizing};
 
-use crate::scalar_utils::{ScalarBytes, ScalarBits, get_bits};
-use crate::r1cs_utils::{AllocatedScalar, constrain_lc_with_scalar};
+use crate::scalar_utils::{ScalarBytes, get_bits, scalar_to_hex, scalar_bytes_from_canonical, index_fits, bits_to_scalar,
+                          scalar_to_u64_array};
+use crate::r1cs_utils::{AllocatedScalar, AllocatedQuantity, constrain_lc_with_scalar, positive_no_gadget};
+use crate::errors::GadgetError;
+use crate::hash_function::{TreeNode, HashFunction, PoseidonHash, empty_tree_hashes, merkle_root_from_path, index_path, traverse_path, hash_path};
+use crate::gadget_zero_nonzero::leaf_nonzero_gadget;
+use crate::gadget_bound_check::leaf_ordering_gadget;
+use crate::{gadget_vsmt_4, gadget_vsmt_8};
 // use crate::gadget_mimc::{mimc, MIMC_ROUNDS, mimc_hash_2, mimc_gadget};
 use crate::gadget_poseidon::{PoseidonParams, Poseidon_hash_2, Poseidon_hash_2_constraints, Poseidon_hash_2_gadget, SboxType,
-                             allocate_statics_for_prover, allocate_statics_for_verifier};
+                             Poseidon_hash_bytes, Poseidon_hash_bytes_constraints, poseidon_commit, poseidon_commit_gadget,
+                             allocate_statics_for_prover, allocate_statics_for_verifier, num_required_statics};
 
-type DBVal = (Scalar, Scalar);
+pub type DBVal = (Scalar, Scalar);
+
+/// Storage for the nodes of `VanillaSparseMerkleTree`, maps the hash of a node to its children. Implement it over a
+/// persistent key-value store like RocksDB or sled for trees that should outlive the process or do not fit in memory.
+pub trait NodeStore {
+    fn get(&self, key: &ScalarBytes) -> Option<DBVal>;
+    fn put(&mut self, key: ScalarBytes, val: DBVal);
+}
+
+/// In-memory store, used by the tree unless another store is given with `new_with_store`
+impl NodeStore for HashMap<ScalarBytes, DBVal> {
+    fn get(&self, key: &ScalarBytes) -> Option<DBVal> {
+        HashMap::get(self, key).cloned()
+    }
+
+    fn put(&mut self, key: ScalarBytes, val: DBVal) {
+        self.insert(key, val);
+    }
+}
+
+/// Nodes of `OwnedSparseMerkleTree` and its snapshots. Nodes are keyed by their hash so a stored node never changes,
+/// which lets the nodes be split in layers that are shared rather than copied. `latest` takes the new nodes until it
+/// is shared by a clone or snapshot, then it is frozen as the newest of `frozen` and new nodes go to a new `latest`.
+/// Lookups go through the layers from the newest so there is a layer for each snapshot taken between updates; adjacent
+/// layers no longer shared are merged before each update of the tree.
+#[derive(Clone, Default)]
+struct SharedNodes {
+    frozen: Vec<Arc<HashMap<ScalarBytes, DBVal>>>,
+    latest: Arc<HashMap<ScalarBytes, DBVal>>,
+}
+
+impl SharedNodes {
+    fn latest_mut(&mut self) -> &mut HashMap<ScalarBytes, DBVal> {
+        if Arc::get_mut(&mut self.latest).is_none() {
+            let shared = mem::replace(&mut self.latest, Arc::new(HashMap::new()));
+            self.frozen.push(shared);
+        }
+        Arc::get_mut(&mut self.latest).unwrap()
+    }
+
+    /// Merge adjacent layers that no clone or snapshot holds anymore, the smaller into the larger
+    fn merge_unshared(&mut self) {
+        let latest = mem::replace(&mut self.latest, Arc::new(HashMap::new()));
+        let mut merged: Vec<Arc<HashMap<ScalarBytes, DBVal>>> = Vec::with_capacity(self.frozen.len() + 1);
+        for mut layer in self.frozen.drain(..).chain(std::iter::once(latest)) {
+            if let Some(prev) = merged.last_mut() {
+                if let (Some(p), Some(l)) = (Arc::get_mut(prev), Arc::get_mut(&mut layer)) {
+                    if p.len() < l.len() {
+                        mem::swap(p, l);
+                    }
+                    p.extend(l.drain());
+                    continue;
+                }
+            }
+            merged.push(layer);
+        }
+        self.latest = merged.pop().unwrap();
+        self.frozen = merged;
+    }
+}
+
+impl NodeStore for SharedNodes {
+    fn get(&self, key: &ScalarBytes) -> Option<DBVal> {
+        self.latest.get(key).or_else(|| self.frozen.iter().rev().find_map(|l| l.get(key))).cloned()
+    }
+
+    fn put(&mut self, key: ScalarBytes, val: DBVal) {
+        self.latest_mut().insert(key, val);
+    }
+}
+
+/// Nodes borrowed from an `OwnedSparseMerkleTree` to update them through `VanillaSparseMerkleTree`
+impl<'s> NodeStore for &'s mut SharedNodes {
+    fn get(&self, key: &ScalarBytes) -> Option<DBVal> {
+        SharedNodes::get(self, key)
+    }
+
+    fn put(&mut self, key: ScalarBytes, val: DBVal) {
+        SharedNodes::put(self, key, val)
+    }
+}
 
 pub const TreeDepth: usize = 32;
 
+/// Transcript label of the membership proofs for callers without their own protocol. Proofs are bound to the label
+/// they are created with, so protocols sharing these proofs should use distinct labels, otherwise a proof created for
+/// one protocol verifies in the other.
+pub const DefaultTranscriptLabel: &'static [u8] = b"VSMT";
+
+/// Number of bits the hashed key is decomposed into in `hashed_key_position_gadget`. 2^252 is smaller than the
+/// order of the group so the decomposition is unique.
+pub const HashedKeyBits: usize = 252;
+
+/// Root of a merkle tree. Wraps the `Scalar` so that it is not confused with a leaf value or index.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MerkleRoot(pub Scalar);
+
+impl MerkleRoot {
+    pub fn to_bytes(&self) -> ScalarBytes {
+        self.0.to_bytes()
+    }
+
+    /// Returns None if `bytes` is not a canonical encoding of a scalar
+    pub fn from_bytes(bytes: ScalarBytes) -> Option<MerkleRoot> {
+        Scalar::from_canonical_bytes(bytes).map(|s| MerkleRoot(s))
+    }
+}
+
+/// Hex of the bytes of the root, can be parsed back with `scalar_from_hex`
+impl fmt::Display for MerkleRoot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", scalar_to_hex(&self.0))
+    }
+}
+
+/// Merkle proof without the proof nodes that are roots of empty subtrees. Bit i of `bitmap` is set if the proof node i,
+/// in the order returned by `VanillaSparseMerkleTree::get`, is not empty and `nodes` has the nodes with the bit set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedProof {
+    pub bitmap: u32,
+    pub nodes: Vec<Scalar>
+}
+
+impl CompressedProof {
+    /// Compress a proof of a tree with the given `empty_tree_hashes`, as returned by
+    /// `VanillaSparseMerkleTree::empty_tree_hashes`. The proof has at most 32 nodes.
+    pub fn compress(proof: &[Scalar], empty_tree_hashes: &[Scalar]) -> Result<CompressedProof, GadgetError> {
+        let depth = Self::depth(empty_tree_hashes)?;
+        if proof.len() != depth || depth > 32 {
+            return Err(GadgetError::ProofLengthMismatch { expected: depth, found: proof.len() });
+        }
+        let mut bitmap = 0u32;
+        let mut nodes = vec![];
+        for (i, p) in proof.iter().enumerate() {
+            // Sibling of a node at level i from the root is the root of a subtree of height depth-1-i
+            if *p != empty_tree_hashes[depth-1-i] {
+                bitmap |= 1 << i;
+                nodes.push(*p);
+            }
+        }
+        Ok(CompressedProof { bitmap, nodes })
+    }
+
+    /// Full proof to use with `verify_proof` or the gadgets.
+    pub fn decompress(&self, empty_tree_hashes: &[Scalar]) -> Result<Vec<Scalar>, GadgetError> {
+        let depth = Self::depth(empty_tree_hashes)?;
+        let num_nodes = self.bitmap.count_ones() as usize;
+        if num_nodes != self.nodes.len() {
+            return Err(GadgetError::ProofLengthMismatch { expected: num_nodes, found: self.nodes.len() });
+        }
+        if depth < 32 && self.bitmap >> depth != 0 {
+            return Err(GadgetError::InvalidDepth(depth));
+        }
+        let mut nodes = self.nodes.iter();
+        let proof = (0..depth).map(|i| {
+            if self.bitmap & (1 << i) != 0 {
+                *nodes.next().unwrap()
+            } else {
+                empty_tree_hashes[depth-1-i]
+            }
+        }).collect();
+        Ok(proof)
+    }
+
+    /// Depth of the tree with `empty_tree_hashes`, which has a hash for each height from 0 to the depth
+    fn depth(empty_tree_hashes: &[Scalar]) -> Result<usize, GadgetError> {
+        match empty_tree_hashes.len() {
+            0 => Err(GadgetError::InvalidDepth(0)),
+            n => Ok(n - 1)
+        }
+    }
+}
+
+/// Side of a node relative to its sibling
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Merkle proof where each sibling is stored with its side, so it can be checked without the index of the leaf.
+/// `siblings` go from the leaf's level to the root's, the opposite of the proof returned by
+/// `VanillaSparseMerkleTree::get`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthPath {
+    pub siblings: Vec<(Scalar, Side)>
+}
+
+impl AuthPath {
+    /// Root computed from `leaf` and the siblings. `leaf` is the leaf as stored in the tree, see
+    /// `VanillaSparseMerkleTree::encode_leaf`.
+    pub fn compute_root(&self, leaf: Scalar, params: &PoseidonParams) -> Scalar {
+        self.siblings.iter().fold(leaf, |cur, (sibling, side)| match side {
+            Side::Left => Poseidon_hash_2(*sibling, cur, params, &SboxType::Inverse),
+            Side::Right => Poseidon_hash_2(cur, *sibling, params, &SboxType::Inverse)
+        })
+    }
+
+    pub fn verify(&self, leaf: Scalar, root: &Scalar, params: &PoseidonParams) -> bool {
+        self.compute_root(leaf, params) == *root
+    }
+}
+
+/// How the value given to `update` is stored as the leaf.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LeafEncoding {
+    /// The value itself is the leaf. A value can then be equal to an internal node and a proof for a leaf at a lower
+    /// level can be passed off as a proof for an internal node and vice versa, which some designs consider insecure.
+    Raw,
+    /// The leaf is `Poseidon_hash_2(position, value)` using the leaf hash parameters, which binds the value to its
+    /// position. Empty leaves are still 0.
+    Hashed,
+}
+
+/// Size of a tree as returned by `VanillaSparseMerkleTree::stats`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeStats {
+    /// Number of non-empty leaves
+    pub num_leaves: usize,
+    pub depth: usize,
+    /// Number of nodes in the store, including the ones no longer reachable from the root as nodes are never removed
+    pub db_nodes: usize,
+    /// `num_leaves` divided by the number of leaves of the tree, 2^depth
+    pub fill_ratio: f64,
+}
+
 // TODO: ABSTRACT HASH FUNCTION BETTER
 
-pub struct VanillaSparseMerkleTree<'a> {
+pub struct VanillaSparseMerkleTree<'a, S: NodeStore = HashMap<ScalarBytes, DBVal>> {
     pub depth: usize,
     empty_tree_hashes: Vec<Scalar>,
-    db: HashMap<ScalarBytes, DBVal>,
+    db: S,
     //hash_constants: &'a [Scalar],
     hash_params: &'a PoseidonParams,
-    pub root: Scalar
+    /// Parameters for computing leaves, like commitments or hashes of byte strings. Same as `hash_params` unless
+    /// the tree is created with `new_with_leaf_hash_params`
+    leaf_hash_params: &'a PoseidonParams,
+    root: Scalar,
+    /// If true, the position of a leaf in the tree is the hash of its index rather than the index itself
+    hashed_keys: bool,
+    /// For trees with hashed keys, maps the position of a leaf truncated to the depth to its original index and value
+    leaf_keys: HashMap<ScalarBytes, DBVal>,
+    leaf_encoding: LeafEncoding,
+    /// Values and merkle proofs returned by `get_cached` for the current root, keyed by index. Cleared when the root changes.
+    path_cache: HashMap<ScalarBytes, (Scalar, Vec<Scalar>)>,
+    /// Index, value and merkle proof of the last `update`, None if the tree was changed otherwise since
+    last_update: Option<(Scalar, Scalar, Vec<Scalar>)>
 }
 
 impl<'a> VanillaSparseMerkleTree<'a> {
     pub fn new(hash_params: &'a PoseidonParams) -> VanillaSparseMerkleTree<'a> {
-        let depth = TreeDepth;
-        let mut db = HashMap::new();
-        let mut empty_tree_hashes: Vec<Scalar> = vec![];
-        empty_tree_hashes.push(Scalar::zero());
+        Self::new_with_depth(TreeDepth, hash_params)
+    }
+
+    /// Create a tree of the given depth. `depth` should not be more than `TreeDepth`.
+    pub fn new_with_depth(depth: usize, hash_params: &'a PoseidonParams) -> VanillaSparseMerkleTree<'a> {
+        Self::new_with_store(depth, hash_params, HashMap::new())
+    }
+
+    /// Create a tree of the given depth from `empty_tree_hashes` as returned by `empty_tree_hashes` of a tree with the
+    /// same `hash_params`, which avoids computing them again. The hashes are trusted to be computed with `hash_params`,
+    /// only the length and the empty leaf are checked.
+    pub fn with_empty_hashes(hash_params: &'a PoseidonParams, empty_tree_hashes: Vec<Scalar>, depth: usize) -> Result<VanillaSparseMerkleTree<'a>, GadgetError> {
+        if depth > TreeDepth {
+            return Err(GadgetError::InvalidDepth(depth));
+        }
+        if empty_tree_hashes.len() != depth + 1 {
+            return Err(GadgetError::InvalidHashParams {
+                description: format!("expected {} empty tree hashes but found {}", depth + 1, empty_tree_hashes.len())
+            });
+        }
+        if empty_tree_hashes[0] != PoseidonHash(hash_params).empty_leaf() {
+            return Err(GadgetError::InvalidHashParams { description: String::from("empty leaf should be zero") });
+        }
+        Ok(Self::from_empty_tree_hashes(depth, hash_params, empty_tree_hashes, HashMap::new()))
+    }
+
+    /// Create a tree where the position of a leaf is the lower `depth` bits of `Poseidon_hash_2(idx, idx)` rather than
+    /// `idx`. This spreads sequential indices across the tree. Leaves are encoded with `LeafEncoding::Hashed` so that
+    /// the leaf commits to the hash of `idx` and not only to its lower bits. As positions are truncated, two keys are
+    /// expected to be placed at the same leaf after about 2^(depth/2) keys, `try_update` then returns
+    /// `GadgetError::KeyCollision`. Use `hashed_key_membership_gadget` to prove membership in the circuit.
+    pub fn new_hashed_keys(hash_params: &'a PoseidonParams) -> VanillaSparseMerkleTree<'a> {
+        let mut tree = Self::new(hash_params);
+        tree.hashed_keys = true;
+        tree.leaf_encoding = LeafEncoding::Hashed;
+        tree
+    }
+
+    /// Create a tree where the internal nodes are hashed with `hash_params` and leaves are computed with `leaf_hash_params`.
+    /// The two can have different widths. Gadgets that compute the leaf, like `committed_leaf_verif_gadget`, take
+    /// statics for each set of params while `vanilla_merkle_merkle_tree_verif_gadget` proves the stored leaf, so it only
+    /// needs `hash_params`.
+    pub fn new_with_leaf_hash_params(hash_params: &'a PoseidonParams, leaf_hash_params: &'a PoseidonParams) -> VanillaSparseMerkleTree<'a> {
+        let mut tree = Self::new(hash_params);
+        tree.leaf_hash_params = leaf_hash_params;
+        tree
+    }
+
+    /// Create a tree that stores values as leaves using `leaf_encoding`. With `LeafEncoding::Hashed`, `get` returns
+    /// the stored leaf rather than the value, use `hashed_leaf_verif_gadget` to prove knowledge of the value.
+    pub fn new_with_leaf_encoding(hash_params: &'a PoseidonParams, leaf_encoding: LeafEncoding) -> VanillaSparseMerkleTree<'a> {
+        let mut tree = Self::new(hash_params);
+        tree.leaf_encoding = leaf_encoding;
+        tree
+    }
+
+    /// Create a tree from the internal nodes laid out as returned by `to_flat`. The depth is given by the number of
+    /// nodes. Returns `GadgetError::CorruptNode` if a node reachable from the root is missing or its children do not
+    /// hash to it. The tree has raw leaves and keys as the flat layout does not keep them.
+    pub fn from_flat(flat: &[Option<DBVal>], hash_params: &'a PoseidonParams) -> Result<VanillaSparseMerkleTree<'a>, GadgetError> {
+        if !(flat.len() + 1).is_power_of_two() {
+            return Err(GadgetError::InvalidDepth(flat.len()));
+        }
+        let depth = (flat.len() + 1).trailing_zeros() as usize;
+        if depth > TreeDepth {
+            return Err(GadgetError::InvalidDepth(depth));
+        }
+
+        let mut tree = Self::new_with_depth(depth, hash_params);
+        for (left, right) in flat.iter().filter_map(|n| *n) {
+            let node = Poseidon_hash_2(left, right, hash_params, &SboxType::Inverse);
+            tree.update_db_with_key_val(node, (left, right));
+        }
+        if let Some(Some((left, right))) = flat.first() {
+            tree.root = Poseidon_hash_2(*left, *right, hash_params, &SboxType::Inverse);
+        }
+
+        tree.verify_integrity()?;
+        Ok(tree)
+    }
+
+    /// Number of leaves and nodes of the tree, for monitoring its density and the growth of the store. Traverses all
+    /// non-empty subtrees.
+    pub fn stats(&self) -> TreeStats {
+        let num_leaves = self.non_empty_leaves().len();
+        TreeStats {
+            num_leaves,
+            depth: self.depth,
+            db_nodes: self.db.len(),
+            fill_ratio: num_leaves as f64 / 2f64.powi(self.depth as i32),
+        }
+    }
+}
+
+impl<'a, S: NodeStore> VanillaSparseMerkleTree<'a, S> {
+    /// Create a tree of the given depth whose nodes are kept in `db`. The nodes of the empty tree are written to `db`.
+    /// `depth` should not be more than `TreeDepth`.
+    pub fn new_with_store(depth: usize, hash_params: &'a PoseidonParams, db: S) -> VanillaSparseMerkleTree<'a, S> {
+        assert!(depth <= TreeDepth);
+        let empty_tree_hashes = empty_tree_hashes(&PoseidonHash(hash_params), depth);
+        Self::from_empty_tree_hashes(depth, hash_params, empty_tree_hashes, db)
+    }
+
+    /// Create a tree from the roots of empty subtrees of each height, `empty_tree_hashes[i]` is the root of an empty
+    /// subtree of height i. The nodes of the empty tree are written to `db`.
+    fn from_empty_tree_hashes(depth: usize, hash_params: &'a PoseidonParams, empty_tree_hashes: Vec<Scalar>, mut db: S) -> VanillaSparseMerkleTree<'a, S> {
         for i in 1..=depth {
             let prev = empty_tree_hashes[i-1];
-            //let new = mimc(&prev, &prev, hash_constants);
-            let new = Poseidon_hash_2(prev.clone(), prev.clone(), hash_params, &SboxType::Inverse);
-            let key = new.to_bytes();
-
-            db.insert(key, (prev, prev));
-            empty_tree_hashes.push(new);
+            db.put(TreeNode::to_bytes(&empty_tree_hashes[i]), (prev, prev));
         }
 
         let root = empty_tree_hashes[depth].clone();
@@ -56,220 +405,5821 @@ impl<'a> VanillaSparseMerkleTree<'a> {
             empty_tree_hashes,
             db,
             hash_params,
-            root
+            leaf_hash_params: hash_params,
+            root,
+            hashed_keys: false,
+            leaf_keys: HashMap::new(),
+            leaf_encoding: LeafEncoding::Raw,
+            path_cache: HashMap::new(),
+            last_update: None
+        }
+    }
+
+    /// Roots of empty subtrees, the element at index i is the root of an empty subtree of height i
+    pub fn empty_tree_hashes(&self) -> &[Scalar] {
+        &self.empty_tree_hashes
+    }
+
+    pub fn leaf_hash_params(&self) -> &PoseidonParams {
+        self.leaf_hash_params
+    }
+
+    pub fn root(&self) -> MerkleRoot {
+        MerkleRoot(self.root)
+    }
+
+    pub fn raw_root(&self) -> Scalar {
+        self.root
+    }
+
+    /// Number of nodes in a merkle proof of the tree, as returned by `get`, which is also the number of index bits and
+    /// proof nodes the gadgets take.
+    pub fn proof_len(&self) -> usize {
+        self.depth
+    }
+
+    /// Position of the leaf for `idx` in the tree.
+    pub fn key_position(&self, idx: &Scalar) -> Scalar {
+        if self.hashed_keys {
+            Poseidon_hash_2(idx.clone(), idx.clone(), self.hash_params, &SboxType::Inverse)
+        } else {
+            idx.clone()
+        }
+    }
+
+    pub fn leaf_encoding(&self) -> LeafEncoding {
+        self.leaf_encoding
+    }
+
+    /// Leaf stored at the bottom level of the tree for value `val` at index `idx`
+    pub fn encode_leaf(&self, idx: &Scalar, val: Scalar) -> Scalar {
+        match self.leaf_encoding {
+            LeafEncoding::Raw => val,
+            LeafEncoding::Hashed => hashed_leaf(self.key_position(idx), val, self.leaf_hash_params)
+        }
+    }
+
+    /// For trees with hashed keys, return the original index and value of the leaf at `position`.
+    pub fn get_index_at_position(&self, position: &Scalar) -> Option<DBVal> {
+        self.leaf_keys.get(&self.leaf_slot(position)).map(|v| v.clone())
+    }
+
+    /// Key of `leaf_keys` for `position`, the position truncated to the depth so keys placed at the same leaf have the
+    /// same slot.
+    fn leaf_slot(&self, position: &Scalar) -> ScalarBytes {
+        bits_to_scalar(&get_bits(&position.reduce(), self.depth)).to_bytes()
+    }
+
+    /// Whether `idx` would be placed at the leaf of another key of a tree with hashed keys
+    fn key_collides(&self, idx: &Scalar) -> bool {
+        self.hashed_keys && match self.leaf_keys.get(&self.leaf_slot(&self.key_position(idx))) {
+            Some((other, _)) => other != idx,
+            None => false
         }
     }
 
+    /// Update the tree and return the new root. Panics if `idx` does not fit in the tree, see `try_update`.
     pub fn update(&mut self, idx: Scalar, val: Scalar) -> Scalar {
+        self.update_with_proof(idx, val, &mut None)
+    }
+
+    /// Like `update` but returns `GadgetError::IndexOutOfRange` if `idx` has bits set beyond the depth of the tree
+    /// and `GadgetError::KeyCollision` if a hashed key would overwrite the leaf of another key, rather than panicking.
+    pub fn try_update(&mut self, idx: Scalar, val: Scalar) -> Result<Scalar, GadgetError> {
+        if !self.index_in_range(&idx) {
+            return Err(GadgetError::IndexOutOfRange { depth: self.depth });
+        }
+        if self.key_collides(&idx) {
+            return Err(GadgetError::KeyCollision);
+        }
+        Ok(self.update(idx, val))
+    }
+
+    /// Like `get` but returns `GadgetError::IndexOutOfRange` if `idx` has bits set beyond the depth of the tree
+    /// rather than panicking.
+    pub fn try_get(&self, idx: Scalar, proof: &mut Option<Vec<Scalar>>) -> Result<Scalar, GadgetError> {
+        if !self.index_in_range(&idx) {
+            return Err(GadgetError::IndexOutOfRange { depth: self.depth });
+        }
+        Ok(self.get(idx, proof))
+    }
+
+    /// Whether `idx` is the index of a leaf, i.e. has no bits set beyond the depth. Positions of hashed keys are
+    /// always truncated to the depth so any index is accepted for them.
+    fn index_in_range(&self, idx: &Scalar) -> bool {
+        self.hashed_keys || index_fits(idx, self.depth)
+    }
+
+    /// Update the tree and return the new root and the previous value at `idx` as returned by `get`, zero if `idx`
+    /// was never set.
+    pub fn update_returning_old(&mut self, idx: Scalar, val: Scalar) -> (Scalar, Scalar) {
+        let old_val = self.get(idx, &mut None);
+        let new_root = self.update(idx, val);
+        (new_root, old_val)
+    }
+
+    /// Update the tree and if `proof` is not None, populate `proof` with the merkle proof of the updated leaf.
+    /// The proof is same as the one returned by `get` after the update. Panics if `idx` does not fit in the tree or
+    /// collides with another hashed key, see `try_update`.
+    pub fn update_with_proof(&mut self, idx: Scalar, val: Scalar, proof: &mut Option<Vec<Scalar>>) -> Scalar {
+        assert!(self.index_in_range(&idx), "index does not fit in a tree of depth {}", self.depth);
+        assert!(!self.key_collides(&idx), "key is placed at the same leaf as another key");
+        let position = self.key_position(&idx);
+        if self.hashed_keys {
+            let slot = self.leaf_slot(&position);
+            self.leaf_keys.insert(slot, (idx, val));
+        }
+
+        let leaf = self.encode_leaf(&idx, val);
+
+        // Binary path from the root, i.e. bits of the position MSB first
+        let path = index_path(&position, self.depth);
+
+        let mut path_proof = Some(Vec::with_capacity(self.proof_len()));
+        let root = self.update_leaf_at_path(&path, leaf, &mut path_proof);
+        let path_proof = path_proof.unwrap();
+        if let Some(v) = proof {
+            v.extend_from_slice(&path_proof);
+        }
+        self.last_update = Some((idx, val, path_proof));
+        root
+    }
+
+    /// Index and value given to the last `update` with the merkle proof of the leaf, the one `get` would return, so
+    /// that a membership proof can be created right after an update without traversing the tree. None if the tree was not updated yet or was
+    /// changed by something other than `update` since, like `update_at_path` or `merge`.
+    pub fn last_update_proof(&self) -> Option<(Scalar, Scalar, Vec<Scalar>)> {
+        self.last_update.clone()
+    }
+
+    /// Set `val` as the leaf at the end of `path`. `path` has the child to go to at each level from the root, 0 for
+    /// left and 1 for right, so it has `depth` elements less than the arity of the tree, 2. Unlike `update`, the leaf
+    /// is stored as it is, the tree's `LeafEncoding` and hashed keys are not applied.
+    pub fn update_at_path(&mut self, path: &[u8], val: Scalar) -> Result<Scalar, GadgetError> {
+        if path.len() != self.depth {
+            return Err(GadgetError::ProofLengthMismatch { expected: self.depth, found: path.len() });
+        }
+        for (level, child) in path.iter().enumerate() {
+            if *child >= 2 {
+                return Err(GadgetError::InvalidPath { level, child: *child });
+            }
+        }
+        Ok(self.update_leaf_at_path(path, val, &mut None))
+    }
+
+    /// Add the non-empty leaves of `other` to this tree and return the new root. Both trees must have the same depth,
+    /// hash parameters and leaf encoding, and are expected to have leaves at disjoint positions like shards over
+    /// disjoint index ranges. If a position has different non-empty leaves in both trees, nothing is changed and
+    /// `GadgetError::ConflictingLeaf` is returned.
+    pub fn merge<T: NodeStore>(&mut self, other: &VanillaSparseMerkleTree<T>) -> Result<Scalar, GadgetError> {
+        if other.depth != self.depth {
+            return Err(GadgetError::InvalidDepth(other.depth));
+        }
+        // Same depth and hash parameters give the same empty subtrees
+        if other.empty_tree_hashes != self.empty_tree_hashes || other.leaf_encoding != self.leaf_encoding
+            || other.hashed_keys != self.hashed_keys {
+            return Err(GadgetError::InvalidHashParams { description: String::from("trees are not over the same hash function and leaf encoding") });
+        }
+
+        let leaves = other.non_empty_leaves();
+        for (path, leaf) in &leaves {
+            let cur = self.subtree_root(path);
+            if cur != self.hasher().empty_leaf() && cur != *leaf {
+                return Err(GadgetError::ConflictingLeaf);
+            }
+        }
+
+        // Leaves are already encoded so they are set as they are
+        for (path, leaf) in &leaves {
+            self.update_leaf_at_path(path, *leaf, &mut None);
+        }
+        for (k, v) in &other.leaf_keys {
+            self.leaf_keys.insert(*k, *v);
+        }
+        Ok(self.root)
+    }
+
+    /// Tree of the same depth, key hashing and leaf encoding with the same leaves but hashed with `new_params`, e.g. to
+    /// migrate to new Poseidon parameters. With hashed keys the original indices and values are kept so the positions
+    /// and leaves are computed again with `new_params`. Otherwise the leaves are copied as they are stored, so leaves
+    /// encoded with `LeafEncoding::Hashed` keep being hashed with the old leaf hash parameters, also for later updates.
+    pub fn rehash<'b>(&self, new_params: &'b PoseidonParams) -> VanillaSparseMerkleTree<'b> where 'a: 'b {
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(self.depth, new_params);
+        tree.hashed_keys = self.hashed_keys;
+        tree.leaf_encoding = self.leaf_encoding;
+        if !self.hashed_keys && self.leaf_encoding == LeafEncoding::Hashed {
+            // The values are not known so the leaves cannot be encoded with `new_params`
+            tree.leaf_hash_params = self.leaf_hash_params;
+        }
+        if self.hashed_keys {
+            for (idx, val) in self.leaf_keys.values() {
+                tree.update(*idx, *val);
+            }
+        } else {
+            for (path, leaf) in self.non_empty_leaves() {
+                tree.update_leaf_at_path(&path, leaf, &mut None);
+            }
+        }
+        tree
+    }
+
+    /// Paths from the root (as for `update_at_path`) and values of all non-empty leaves. Empty subtrees are skipped.
+    fn non_empty_leaves(&self) -> Vec<(Vec<u8>, Scalar)> {
+        let mut leaves = vec![];
+        let mut path = Vec::with_capacity(self.depth);
+        self.collect_leaves(self.root, self.depth, &mut path, &mut leaves);
+        leaves
+    }
+
+    fn collect_leaves(&self, node: Scalar, height: usize, path: &mut Vec<u8>, leaves: &mut Vec<(Vec<u8>, Scalar)>) {
+        if node == self.empty_tree_hashes[height] {
+            return;
+        }
+        if height == 0 {
+            leaves.push((path.clone(), node));
+            return;
+        }
+        let (left, right) = self.get_node(&node);
+        path.push(0);
+        self.collect_leaves(left, height - 1, path, leaves);
+        path.pop();
+        path.push(1);
+        self.collect_leaves(right, height - 1, path, leaves);
+        path.pop();
+    }
+
+    /// Set `leaf` at the end of `path`, which is trusted to be valid.
+    fn update_leaf_at_path(&mut self, path: &[u8], leaf: Scalar, proof: &mut Option<Vec<Scalar>>) -> Scalar {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("vsmt_update", depth = self.depth).entered();
 
         // Find path to insert the new key
         let mut sidenodes_wrap = Some(Vec::<Scalar>::new());
-        self.get(idx, &mut sidenodes_wrap);
-        let mut sidenodes: Vec<Scalar> = sidenodes_wrap.unwrap();
-
-        let mut cur_idx = ScalarBits::from_scalar(&idx, TreeDepth);
-        let mut cur_val = val.clone();
-
-        for i in 0..self.depth {
-            let side_elem = sidenodes.pop().unwrap();
-            let new_val = {
-                if cur_idx.is_lsb_set() {
-                    // LSB is set, so put new value on right
-                    //let h =  mimc(&side_elem, &cur_val, self.hash_constants);
-                    let h =  Poseidon_hash_2(side_elem.clone(), cur_val.clone(), self.hash_params, &SboxType::Inverse);
-                    self.update_db_with_key_val(h, (side_elem, cur_val));
-                    h
-                } else {
-                    // LSB is unset, so put new value on left
-                    //let h =  mimc(&cur_val, &side_elem, self.hash_constants);
-                    let h =  Poseidon_hash_2(cur_val.clone(), side_elem.clone(), self.hash_params, &SboxType::Inverse);
-                    self.update_db_with_key_val(h, (cur_val, side_elem));
-                    h
+        self.subtree_root_with_proof(path, &mut sidenodes_wrap);
+        let sidenodes: Vec<Scalar> = sidenodes_wrap.unwrap();
+
+        // Updating a leaf does not change its sibling nodes so they are the proof for the new value
+        match proof {
+            Some(v) => {
+                v.extend_from_slice(&sidenodes);
+            }
+            None => ()
+        }
+
+        let hasher = self.hasher();
+        let db = &mut self.db;
+        let cur_val = hash_path(&hasher, path, leaf, &sidenodes, |h, children| {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(node = %scalar_to_hex(&h));
+            db.put(TreeNode::to_bytes(&h), children);
+        });
+
+        self.root = cur_val;
+        // Cached proofs are for the old root
+        self.path_cache.clear();
+        self.last_update = None;
+
+        cur_val
+    }
+
+    /// Get a value from tree, if `proof` is not None, populate `proof` with the merkle proof. For trees with
+    /// `LeafEncoding::Hashed`, this is the stored leaf and not the value.
+    pub fn get(&self, idx: Scalar, proof: &mut Option<Vec<Scalar>>) -> Scalar {
+        assert!(self.index_in_range(&idx), "index does not fit in a tree of depth {}", self.depth);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("vsmt_get", depth = self.depth).entered();
+        let path = index_path(&self.key_position(&idx), self.depth);
+        traverse_path(self.root, &path, |n| {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(node = %scalar_to_hex(n));
+            self.get_node(n)
+        }, proof)
+    }
+
+    /// Same as `get` but the value and proof are cached until the tree is updated, so repeated gets of the same index
+    /// do not traverse the tree.
+    pub fn get_cached(&mut self, idx: Scalar, proof: &mut Option<Vec<Scalar>>) -> Scalar {
+        let key = idx.to_bytes();
+        if !self.path_cache.contains_key(&key) {
+            let mut p = Some(Vec::with_capacity(self.proof_len()));
+            let val = self.get(idx, &mut p);
+            self.path_cache.insert(key, (val, p.unwrap()));
+        }
+        let (val, cached_proof) = &self.path_cache[&key];
+        if let Some(p) = proof { p.extend_from_slice(cached_proof); }
+        *val
+    }
+
+    /// Root of the subtree reached by following `prefix_bits` from the root, 1 to go right and 0 to go left. These are
+    /// the top bits of the position of the leaves in the subtree, MSB first. At most `depth` bits are followed.
+    pub fn subtree_root(&self, prefix_bits: &[u8]) -> Scalar {
+        self.subtree_root_with_proof(prefix_bits, &mut None)
+    }
+
+    /// Merkle proof of the subtree root returned by `subtree_root` for `prefix_bits`, the proof node for the root's
+    /// level first. Verify it once with `compute_root_from_proof` with the prefix as the index and `prefix_bits.len()`
+    /// as depth, then verify the leaves of the subtree against the subtree root with `partial_proof`.
+    pub fn subtree_proof(&self, prefix_bits: &[u8]) -> Vec<Scalar> {
+        let mut proof = Some(Vec::with_capacity(prefix_bits.len()));
+        self.subtree_root_with_proof(prefix_bits, &mut proof);
+        proof.unwrap()
+    }
+
+    /// Merkle proof of the leaf for `idx` up to the root of its subtree at `prefix_len` levels below the root, i.e. the
+    /// last `depth - prefix_len` nodes of the proof returned by `get`. Verify it against the subtree root with
+    /// `compute_root_from_proof` with `depth - prefix_len` as depth.
+    pub fn partial_proof(&self, idx: Scalar, prefix_len: usize) -> Vec<Scalar> {
+        let mut proof = Some(Vec::with_capacity(self.proof_len()));
+        self.get(idx, &mut proof);
+        proof.unwrap().split_off(prefix_len.min(self.depth))
+    }
+
+    /// Internal nodes of the current tree, with their children, that are not at the same position in the tree with root
+    /// `old_root`, i.e. the nodes to send to someone having the old tree so that they have the current one. Roots of
+    /// empty subtrees are skipped as every tree has them. The nodes of the tree with `old_root` must be in `db`, which
+    /// is the case for any earlier root of this tree as nodes are never removed.
+    pub fn diff(&self, old_root: &Scalar) -> Vec<(Scalar, DBVal)> {
+        let mut nodes = vec![];
+        self.diff_nodes(self.root, *old_root, self.depth, &mut nodes);
+        nodes
+    }
+
+    fn diff_nodes(&self, node: Scalar, old_node: Scalar, height: usize, nodes: &mut Vec<(Scalar, DBVal)>) {
+        // Leaves are not in `db`
+        if node == old_node || height == 0 || node == self.empty_tree_hashes[height] {
+            return;
+        }
+        let (left, right) = self.get_node(&node);
+        nodes.push((node, (left, right)));
+        let (old_left, old_right) = self.get_node(&old_node);
+        self.diff_nodes(left, old_left, height - 1, nodes);
+        self.diff_nodes(right, old_right, height - 1, nodes);
+    }
+
+    /// Check that the children stored for each node reachable from the root hash to the node, e.g. after restoring the
+    /// tree from a store. Returns `GadgetError::CorruptNode` with the first node that is missing or does not match.
+    pub fn verify_integrity(&self) -> Result<(), GadgetError> {
+        // Empty subtrees are shared so check them once
+        for height in 1..=self.depth {
+            let node = self.empty_tree_hashes[height];
+            let prev = self.empty_tree_hashes[height-1];
+            if self.db.get(&TreeNode::to_bytes(&node)) != Some((prev, prev)) {
+                return Err(GadgetError::CorruptNode(node));
+            }
+        }
+        self.verify_subtree_integrity(self.root, self.depth)
+    }
+
+    fn verify_subtree_integrity(&self, node: Scalar, height: usize) -> Result<(), GadgetError> {
+        if height == 0 || node == self.empty_tree_hashes[height] {
+            return Ok(());
+        }
+        let (left, right) = self.db.get(&TreeNode::to_bytes(&node)).ok_or(GadgetError::CorruptNode(node))?;
+        if self.hasher().hash_2(&left, &right) != node {
+            return Err(GadgetError::CorruptNode(node));
+        }
+        self.verify_subtree_integrity(left, height - 1)?;
+        self.verify_subtree_integrity(right, height - 1)
+    }
+
+    /// Children of the internal node `node`. Nodes are hash outputs so their encoding is canonical, a non-canonical
+    /// one would never be found in `db`.
+    fn get_node(&self, node: &Scalar) -> DBVal {
+        let k = scalar_bytes_from_canonical(TreeNode::to_bytes(node)).expect("node is not a canonical scalar");
+        self.db.get(&k).unwrap()
+    }
+
+    fn subtree_root_with_proof(&self, prefix_bits: &[u8], proof: &mut Option<Vec<Scalar>>) -> Scalar {
+        let prefix_bits = &prefix_bits[..prefix_bits.len().min(self.depth)];
+        traverse_path(self.root, prefix_bits, |n| self.get_node(n), proof)
+    }
+
+    /// Lazily get the value and merkle proof for each index in `indices`, in order. Each proof is allocated once with
+    /// space for exactly `depth` nodes, so memory stays bounded by the proofs the caller keeps.
+    pub fn proofs_for<'b>(&'b self, indices: &'b [Scalar]) -> impl Iterator<Item = (Scalar, Vec<Scalar>)> + 'b {
+        indices.iter().map(move |idx| {
+            let mut proof = Some(Vec::with_capacity(self.proof_len()));
+            let val = self.get(*idx, &mut proof);
+            (val, proof.unwrap())
+        })
+    }
+
+    /// Verify a merkle proof, if `root` is None, use the current root else use given root. `val` is the value given
+    /// to `update`, it is encoded as a leaf as per the tree's `LeafEncoding`. An index that does not fit in the tree
+    /// fails rather than being truncated to the index of another leaf.
+    pub fn verify_proof(&self, idx: Scalar, val: Scalar, proof: &[Scalar], root: Option<&MerkleRoot>) -> bool {
+        if !self.index_in_range(&idx) {
+            return false;
+        }
+        let root = match root {
+            Some(r) => r.0,
+            None => self.root
+        };
+        verify_merkle_proof(self.key_position(&idx), self.encode_leaf(&idx, val), proof, &root, self.hash_params, self.depth)
+    }
+
+    /// Verify each `(idx, val, proof)` of `items` against the current root as `verify_proof` does, e.g. to reject
+    /// invalid inputs before the costly proving. An index that does not fit in the tree fails. With the `rayon`
+    /// feature the items are verified in parallel. The store must be `Sync` with or without the feature so that enabling
+    /// it does not break callers.
+    #[cfg(not(feature = "rayon"))]
+    pub fn verify_proofs_batch(&self, items: &[(Scalar, Scalar, Vec<Scalar>)]) -> Vec<bool> where S: Sync {
+        items.iter().map(|(idx, val, proof)| self.verify_proof_in_batch(idx, val, proof)).collect()
+    }
+
+    /// Verify each `(idx, val, proof)` of `items` against the current root as `verify_proof` does, e.g. to reject
+    /// invalid inputs before the costly proving. An index that does not fit in the tree fails. The items are verified
+    /// in parallel.
+    #[cfg(feature = "rayon")]
+    pub fn verify_proofs_batch(&self, items: &[(Scalar, Scalar, Vec<Scalar>)]) -> Vec<bool> where S: Sync {
+        use rayon::prelude::*;
+        items.par_iter().map(|(idx, val, proof)| self.verify_proof_in_batch(idx, val, proof)).collect()
+    }
+
+    fn verify_proof_in_batch(&self, idx: &Scalar, val: &Scalar, proof: &[Scalar]) -> bool {
+        self.verify_proof(*idx, *val, proof, None)
+    }
+
+    /// Children of each internal node in an array addressed by position, level by level from the root and from left
+    /// to right in a level, so the children of the node at index i are at 2i+1 and 2i+2 and the array has 2^depth - 1
+    /// elements. Roots of empty subtrees are None. Meant for processing the nodes in batches, e.g. on a GPU, so only
+    /// small trees should be flattened. Use `from_flat` to get the tree back.
+    pub fn to_flat(&self) -> Vec<Option<DBVal>> {
+        let mut flat = Vec::with_capacity((1 << self.depth) - 1);
+        // Nodes of the current level, None for roots of empty subtrees
+        let mut level = vec![Some(self.root)];
+        for height in (1..=self.depth).rev() {
+            let mut next = Vec::with_capacity(2 * level.len());
+            for node in level {
+                let children = node.filter(|n| *n != self.empty_tree_hashes[height]).map(|n| self.get_node(&n));
+                flat.push(children);
+                match children {
+                    Some((left, right)) => {
+                        next.push(Some(left));
+                        next.push(Some(right));
+                    }
+                    None => {
+                        next.push(None);
+                        next.push(None);
+                    }
                 }
-            };
-            //println!("Root at level {} is {:?}", i, &cur_val);
-            cur_idx.shr();
-            cur_val = new_val;
+            }
+            level = next;
+        }
+        flat
+    }
+
+    /// Get a value from tree along with its merkle proof without the empty proof nodes.
+    pub fn get_compressed(&self, idx: Scalar) -> (Scalar, CompressedProof) {
+        let mut proof = Some(Vec::with_capacity(self.proof_len()));
+        let val = self.get(idx, &mut proof);
+        // Proof of the tree always has `depth` nodes
+        (val, CompressedProof::compress(&proof.unwrap(), &self.empty_tree_hashes).unwrap())
+    }
+
+    /// Verify a proof returned by `get_compressed`, if `root` is None, use the current root else use given root
+    pub fn verify_compressed(&self, idx: Scalar, val: Scalar, proof: &CompressedProof, root: Option<&MerkleRoot>) -> bool {
+        match proof.decompress(&self.empty_tree_hashes) {
+            Ok(p) => self.verify_proof(idx, val, &p, root),
+            Err(_) => false
         }
+    }
+
+    /// Merkle proof of the leaf at `idx` with the side of each sibling, see `AuthPath`
+    pub fn authentication_path(&self, idx: Scalar) -> AuthPath {
+        let mut proof = Some(Vec::with_capacity(self.proof_len()));
+        self.get(idx, &mut proof);
+        let bits = get_bits(&self.key_position(&idx).reduce(), self.depth);
+        // Proof has the sibling at the root's level first and bits are from the leaf's level
+        let siblings = proof.unwrap().into_iter().rev().zip(bits.iter()).map(|(sibling, b)| {
+            // Sibling of a right child is on the left
+            let side = if *b == 1 { Side::Left } else { Side::Right };
+            (sibling, side)
+        }).collect();
+        AuthPath { siblings }
+    }
+
+    /// Like `verify_proof` but the computed root is compared with `root` in constant time. Computing the root does
+    /// not depend on the values, so this only matters when the root is secret, e.g. in MPC. As in `verify_proof`, an
+    /// index that does not fit in the tree fails. The index is public so this check need not be constant time.
+    #[cfg(feature = "constant-time")]
+    pub fn verify_proof_ct(&self, idx: Scalar, val: Scalar, proof: &[Scalar], root: Option<&MerkleRoot>) -> bool {
+        let root = match root {
+            Some(r) => r.0,
+            None => self.root
+        };
+        if !self.index_in_range(&idx) {
+            return false;
+        }
+        match compute_root_from_proof(self.key_position(&idx), self.encode_leaf(&idx, val), proof, self.hash_params, self.depth) {
+            Some(computed_root) => computed_root.ct_eq(&root).into(),
+            None => false
+        }
+    }
+
+    /// Leaf for a byte string value. The bytes are hashed with `Poseidon_hash_bytes`.
+    pub fn leaf_from_bytes(&self, value: &[u8]) -> Scalar {
+        Poseidon_hash_bytes(value, self.leaf_hash_params, &SboxType::Inverse)
+    }
+
+    /// Leaf that is a commitment to `value` with `blinding` as randomness. Use `committed_leaf_verif_gadget` to prove
+    /// knowledge of the opening of the leaf.
+    pub fn leaf_commitment(&self, value: Scalar, blinding: Scalar) -> Scalar {
+        poseidon_commit(value, blinding, self.leaf_hash_params)
+    }
+
+    /// Store hash of the byte string `value` as the leaf at index `idx`.
+    pub fn update_bytes(&mut self, idx: Scalar, value: &[u8]) -> Scalar {
+        let leaf = self.leaf_from_bytes(value);
+        self.update(idx, leaf)
+    }
+
+    /// Verify a merkle proof for a leaf set with `update_bytes`, if `root` is None, use the current root else use given root
+    pub fn verify_proof_bytes(&self, idx: Scalar, value: &[u8], proof: &[Scalar], root: Option<&MerkleRoot>) -> bool {
+        let leaf = self.leaf_from_bytes(value);
+        self.verify_proof(idx, leaf, proof, root)
+    }
+
+    /// Prove in a single R1CS proof that the leaf at `idx` is in the tree and is in [0, 2^`range_bits`), e.g. that an
+    /// amount is present and not negative. The range is proven on the committed leaf, so the tree should store values
+    /// as they are, with `LeafEncoding::Raw`. `range_bits` should not be more than 64. The commitments are as for
+    /// `gen_proof_of_leaf_membership`, verify with `verify_membership_with_range`.
+    pub fn prove_membership_with_range<R: RngCore + CryptoRng>(&self, idx: Scalar, range_bits: usize, rng: &mut R,
+                                                               transcript_label: &'static [u8], pc_gens: &PedersenGens,
+                                                               bp_gens: &BulletproofGens) -> Result<(R1CSProof, Vec<CompressedRistretto>), GadgetError> {
+        check_range_bits(range_bits)?;
+
+        let mut merkle_proof = Some(Vec::with_capacity(self.proof_len()));
+        let leaf = Secret::new(self.try_get(idx, &mut merkle_proof)?);
+        let merkle_proof = Secret::new(merkle_proof.unwrap());
+
+        let mut prover_transcript = Transcript::new(transcript_label);
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+        let mut committed = MerkleProofBuilder::new(self.key_position(&idx), *leaf, &merkle_proof)
+            .commit(&mut prover, rng);
+        let comms = committed.commitments.clone();
+
+        // If the leaf is out of range its lower 64 bits do not add up to it and the constraints are not satisfied
+        let leaf_quantity = AllocatedQuantity {
+            variable: committed.leaf.variable,
+            assignment: Some(scalar_to_u64_array(&leaf)[0])
+        };
+        positive_no_gadget(&mut prover, leaf_quantity, range_bits)?;
+        committed_path_constraints(&mut prover, &self.root, self.hash_params, &mut committed)?;
+
+        let proof = prover.prove(&bp_gens)?;
+
+        Ok((proof, comms))
+    }
+
+    fn update_db_with_key_val(&mut self, key: Scalar, val: DBVal) {
+        self.db.put(TreeNode::to_bytes(&key), val);
+    }
+
+    /// Hash function of the internal nodes
+    fn hasher(&self) -> PoseidonHash<'a> {
+        PoseidonHash(self.hash_params)
+    }
+}
+
+
+/// Trees are equal if they have the same depth and root, equal roots imply equal leaves so the nodes are not compared
+impl<'a, 'b, S: NodeStore, T: NodeStore> PartialEq<VanillaSparseMerkleTree<'b, T>> for VanillaSparseMerkleTree<'a, S> {
+    fn eq(&self, other: &VanillaSparseMerkleTree<'b, T>) -> bool {
+        self.depth == other.depth && self.root == other.root
+    }
+}
+
+impl<'a, S: NodeStore> Eq for VanillaSparseMerkleTree<'a, S> {}
+
+/// Prints the depth and hex of the root, the nodes are not printed
+impl<'a, S: NodeStore> fmt::Debug for VanillaSparseMerkleTree<'a, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VanillaSparseMerkleTree")
+            .field("depth", &self.depth)
+            .field("root", &scalar_to_hex(&self.root))
+            .finish()
+    }
+}
+
+/// Sparse merkle tree like `VanillaSparseMerkleTree` that shares its hash parameters through an `Arc` rather than
+/// borrowing them, so it can be cloned, kept in long-lived structs and moved across threads. Keys and leaves are raw,
+/// use `VanillaSparseMerkleTree` for hashed keys or leaves. Clones and snapshots from `read_snapshot` share the nodes
+/// and updates after them add new nodes without copying the shared ones.
+#[derive(Clone)]
+pub struct OwnedSparseMerkleTree {
+    pub depth: usize,
+    empty_tree_hashes: Vec<Scalar>,
+    db: SharedNodes,
+    hash_params: Arc<PoseidonParams>,
+    root: Scalar,
+}
+
+impl OwnedSparseMerkleTree {
+    pub fn new(hash_params: Arc<PoseidonParams>) -> OwnedSparseMerkleTree {
+        Self::new_with_depth(TreeDepth, hash_params)
+    }
+
+    /// Create a tree of the given depth. `depth` should not be more than `TreeDepth`.
+    pub fn new_with_depth(depth: usize, hash_params: Arc<PoseidonParams>) -> OwnedSparseMerkleTree {
+        let (empty_tree_hashes, db, root) = {
+            let tree = VanillaSparseMerkleTree::new_with_depth(depth, &hash_params);
+            (tree.empty_tree_hashes, tree.db, tree.root)
+        };
+        OwnedSparseMerkleTree {
+            depth,
+            empty_tree_hashes,
+            db: SharedNodes { frozen: vec![], latest: Arc::new(db) },
+            hash_params,
+            root
+        }
+    }
+
+    /// Tree as of now for readers, e.g. threads serving proofs while this tree is updated. Updates of this tree are
+    /// not seen by the snapshot. Nodes are shared so taking a snapshot is cheap.
+    pub fn read_snapshot(&self) -> Arc<ReadOnlyTree> {
+        Arc::new(ReadOnlyTree {
+            depth: self.depth,
+            db: self.db.clone(),
+            hash_params: self.hash_params.clone(),
+            root: self.root
+        })
+    }
+
+    pub fn hash_params(&self) -> &Arc<PoseidonParams> {
+        &self.hash_params
+    }
+
+    pub fn root(&self) -> MerkleRoot {
+        MerkleRoot(self.root)
+    }
+
+    pub fn raw_root(&self) -> Scalar {
+        self.root
+    }
+
+    /// Update the tree and return the new root. Panics if `idx` does not fit in the tree.
+    pub fn update(&mut self, idx: Scalar, val: Scalar) -> Scalar {
+        self.update_with_proof(idx, val, &mut None)
+    }
+
+    /// Update the tree and if `proof` is not None, populate `proof` with the merkle proof of the updated leaf.
+    pub fn update_with_proof(&mut self, idx: Scalar, val: Scalar, proof: &mut Option<Vec<Scalar>>) -> Scalar {
+        self.with_tree(|tree| tree.update_with_proof(idx, val, proof))
+    }
+
+    /// Get a value from tree, if `proof` is not None, populate `proof` with the merkle proof.
+    pub fn get(&self, idx: Scalar, proof: &mut Option<Vec<Scalar>>) -> Scalar {
+        get_from_nodes(&self.db, self.root, self.depth, idx, proof)
+    }
+
+    /// Verify a merkle proof, if `root` is None, use the current root else use given root. An index that does not fit
+    /// in the tree fails.
+    pub fn verify_proof(&self, idx: Scalar, val: Scalar, proof: &[Scalar], root: Option<&MerkleRoot>) -> bool {
+        if !index_fits(&idx, self.depth) {
+            return false;
+        }
+        let root = match root {
+            Some(r) => r.0,
+            None => self.root
+        };
+        verify_merkle_proof(idx, val, proof, &root, &self.hash_params, self.depth)
+    }
+
+    /// Run `f` on a `VanillaSparseMerkleTree` over the nodes of this tree and keep the root it leaves.
+    fn with_tree<T, F: FnOnce(&mut VanillaSparseMerkleTree<&mut SharedNodes>) -> T>(&mut self, f: F) -> T {
+        self.db.merge_unshared();
+        let mut tree = VanillaSparseMerkleTree {
+            depth: self.depth,
+            empty_tree_hashes: mem::replace(&mut self.empty_tree_hashes, Vec::new()),
+            db: &mut self.db,
+            hash_params: &self.hash_params,
+            leaf_hash_params: &self.hash_params,
+            root: self.root,
+            hashed_keys: false,
+            leaf_keys: HashMap::new(),
+            leaf_encoding: LeafEncoding::Raw,
+            path_cache: HashMap::new(),
+            last_update: None
+        };
+        let res = f(&mut tree);
+        let (root, empty_tree_hashes) = (tree.root, tree.empty_tree_hashes);
+        self.root = root;
+        self.empty_tree_hashes = empty_tree_hashes;
+        res
+    }
+}
+
+/// Tree that can only be read, taken from `OwnedSparseMerkleTree::read_snapshot`. It is `Send` and `Sync` so it can be
+/// shared by many readers.
+pub struct ReadOnlyTree {
+    pub depth: usize,
+    db: SharedNodes,
+    hash_params: Arc<PoseidonParams>,
+    root: Scalar,
+}
+
+impl ReadOnlyTree {
+    pub fn root(&self) -> MerkleRoot {
+        MerkleRoot(self.root)
+    }
+
+    pub fn raw_root(&self) -> Scalar {
+        self.root
+    }
+
+    /// Get a value from tree, if `proof` is not None, populate `proof` with the merkle proof.
+    pub fn get(&self, idx: Scalar, proof: &mut Option<Vec<Scalar>>) -> Scalar {
+        get_from_nodes(&self.db, self.root, self.depth, idx, proof)
+    }
+
+    /// Verify a merkle proof, if `root` is None, use the root of the snapshot else use given root. An index that does
+    /// not fit in the tree fails.
+    pub fn verify_proof(&self, idx: Scalar, val: Scalar, proof: &[Scalar], root: Option<&MerkleRoot>) -> bool {
+        if !index_fits(&idx, self.depth) {
+            return false;
+        }
+        let root = match root {
+            Some(r) => r.0,
+            None => self.root
+        };
+        verify_merkle_proof(idx, val, proof, &root, &self.hash_params, self.depth)
+    }
+}
+
+/// Leaf at `idx` in the tree with the given `root` and nodes in `db`, the proof is populated as by
+/// `VanillaSparseMerkleTree::get`
+fn get_from_nodes<S: NodeStore>(db: &S, root: Scalar, depth: usize, idx: Scalar,
+                                 proof: &mut Option<Vec<Scalar>>) -> Scalar {
+    assert!(index_fits(&idx, depth), "index does not fit in a tree of depth {}", depth);
+    traverse_path(root, &index_path(&idx, depth), |n| db.get(&TreeNode::to_bytes(n)).unwrap(), proof)
+}
+
+/// Prints the depth and hex of the root, the nodes are not printed
+impl fmt::Debug for OwnedSparseMerkleTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OwnedSparseMerkleTree")
+            .field("depth", &self.depth)
+            .field("root", &scalar_to_hex(&self.root))
+            .finish()
+    }
+}
+
+/// Leaf for `val` at `position` in trees with `LeafEncoding::Hashed`
+pub fn hashed_leaf(position: Scalar, val: Scalar, params: &PoseidonParams) -> Scalar {
+    Poseidon_hash_2(position, val, params, &SboxType::Inverse)
+}
+
+/// Root of the tree of the given `depth` with the leaves `sorted`, without keeping the tree. `sorted` has pairs of index
+/// and leaf in increasing order of index, otherwise `GadgetError::UnsortedLeaves` is returned. Only the roots of the
+/// subtrees that are not complete yet are kept, so at most `depth` nodes are held at a time. Trees with hashed keys or
+/// hashed leaves are not supported, use the position and the encoded leaf for those.
+pub fn build_streaming<I: Iterator<Item=(Scalar, Scalar)>>(hash_params: &PoseidonParams, depth: usize,
+                                                           sorted: I) -> Result<Scalar, GadgetError> {
+    let empty_tree_hashes = empty_tree_hashes(&PoseidonHash(hash_params), depth);
+    // Roots of incomplete subtrees with their height and path from the root, each the left sibling of an ancestor of
+    // the subtree above it
+    let mut stack: Vec<(usize, Scalar, Vec<u8>)> = vec![];
+    let mut prev_path: Option<Vec<u8>> = None;
+
+    for (idx, val) in sorted {
+        // Binary path from the root, i.e. bits of the index MSB first
+        let mut path = get_bits(&idx.reduce(), depth);
+        path.reverse();
+
+        if let Some(prev) = prev_path {
+            // Paths of increasing indices are increasing in lexicographic order
+            if path <= prev {
+                return Err(GadgetError::UnsortedLeaves);
+            }
+            // Complete the subtree of the previous leaf up to the child of the common ancestor with this leaf
+            let common = path.iter().zip(prev.iter()).take_while(|(a, b)| a == b).count();
+            raise_streamed_subtree(&mut stack, depth - common - 1, depth, &empty_tree_hashes, hash_params);
+        }
+
+        stack.push((0, val, path.clone()));
+        prev_path = Some(path);
+    }
+
+    if stack.is_empty() {
+        return Ok(empty_tree_hashes[depth]);
+    }
+    raise_streamed_subtree(&mut stack, depth, depth, &empty_tree_hashes, hash_params);
+    Ok(stack[0].1)
+}
+
+/// Hash the subtree on the top of `stack` up to `height`, with its left siblings from the stack or empty subtrees.
+fn raise_streamed_subtree(stack: &mut Vec<(usize, Scalar, Vec<u8>)>, height: usize, depth: usize,
+                          empty_tree_hashes: &[Scalar], hash_params: &PoseidonParams) {
+    let (mut h, mut node, mut path) = stack.pop().unwrap();
+    while h < height {
+        let child = path.pop().unwrap();
+        node = if child == 1 {
+            let left = match stack.last() {
+                Some((left_h, _, left_path)) if *left_h == h && left_path[..depth - h - 1] == path[..] => stack.pop().unwrap().1,
+                _ => empty_tree_hashes[h]
+            };
+            Poseidon_hash_2(left, node, hash_params, &SboxType::Inverse)
+        } else {
+            Poseidon_hash_2(node, empty_tree_hashes[h], hash_params, &SboxType::Inverse)
+        };
+        h += 1;
+    }
+    stack.push((h, node, path));
+}
+
+/// Compute the root of the tree from the leaf `val` at index `idx` and its merkle proof. `proof` is as returned by
+/// `VanillaSparseMerkleTree::get`, i.e. the proof node for the root's level first. For trees with hashed keys, `idx` is
+/// the position of the leaf as returned by `key_position`. None if `proof` does not have `depth` nodes.
+pub fn compute_root_from_proof(idx: Scalar, val: Scalar, proof: &[Scalar], params: &PoseidonParams, depth: usize) -> Option<Scalar> {
+    if proof.len() != depth {
+        return None;
+    }
+    Some(merkle_root_from_path(&PoseidonHash(params), &idx, val, proof, depth))
+}
+
+/// Verify a merkle proof without the tree. `proof` and `idx` are as for `compute_root_from_proof`. A proof that does
+/// not have `depth` nodes is rejected.
+pub fn verify_merkle_proof(idx: Scalar, val: Scalar, proof: &[Scalar], root: &Scalar, params: &PoseidonParams, depth: usize) -> bool {
+    compute_root_from_proof(idx, val, proof, params, depth) == Some(*root)
+}
+
+/// Check that there is one leaf index bit and one proof node for each level of the tree.
+fn check_path_length<N>(depth: usize, leaf_index_bits: &[AllocatedScalar], proof_nodes: &[N]) -> Result<(), GadgetError> {
+    if depth == 0 {
+        return Err(GadgetError::InvalidDepth(depth));
+    }
+    if leaf_index_bits.len() != depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: depth, found: leaf_index_bits.len() });
+    }
+    if proof_nodes.len() != depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: depth, found: proof_nodes.len() });
+    }
+    Ok(())
+}
+
+/// Proof nodes as linear combinations of their variables, as taken by `vanilla_merkle_merkle_tree_root_constraints`
+pub fn proof_node_lcs(proof_nodes: &[AllocatedScalar]) -> Vec<LinearCombination> {
+    proof_nodes.iter().map(|n| n.variable.into()).collect()
+}
+
+/// Enforce leaf_side * (1-leaf_side) = 0 for each leaf_side in `leaf_index_bits`, so each of them is a bit.
+/// Without this a prover could use any other value for leaf_side and mix the current node with the proof node.
+pub fn constrain_leaf_index_bits<CS: ConstraintSystem>(
+    cs: &mut CS,
+    leaf_index_bits: &[AllocatedScalar]
+) -> Result<(), R1CSError> {
+    for b in leaf_index_bits {
+        let one_minus_leaf_side: LinearCombination = Variable::One() - b.variable;
+        let (_, _, bit_check) = cs.multiply(b.variable.into(), one_minus_leaf_side);
+        cs.constrain(bit_check.into());
+    }
+    Ok(())
+}
+
+/// S-boxes of the hashes computed by the merkle path gadgets. The hash of the leaf with its sibling can use a different
+/// S-box than the hashes of internal nodes, e.g. for domain separation of the leaf level. The default uses the inverse
+/// S-box everywhere, as `VanillaSparseMerkleTree` does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HashSpec {
+    /// S-box for the hash at the lowest level, of the leaf and its sibling
+    pub leaf_sbox: SboxType,
+    /// S-box for the hashes at all other levels
+    pub node_sbox: SboxType,
+}
+
+impl Default for HashSpec {
+    fn default() -> Self {
+        HashSpec {
+            leaf_sbox: SboxType::Inverse,
+            node_sbox: SboxType::Inverse,
+        }
+    }
+}
+
+/// Enforce that the index given by `index_bits` (LSB first) is odd if `expect_odd` else even, i.e. the leaf is a right or a
+/// left child. Pass the bits given to the membership gadget so they are not committed again, which also checks that
+/// they are bits.
+pub fn index_parity_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    index_bits: &[AllocatedScalar],
+    expect_odd: bool
+) -> Result<(), GadgetError> {
+    let lsb = index_bits.first().ok_or(GadgetError::InvalidDepth(0))?;
+    let expected = if expect_odd { Scalar::one() } else { Scalar::zero() };
+    constrain_lc_with_scalar::<CS>(cs, lsb.variable.into(), &expected);
+    Ok(())
+}
+
+/// Enforce that the indices given by `bits_a` and `bits_b` (LSB first) are different, e.g. to show that 2 leaves proven
+/// to be in the tree are distinct entries. This proves that the indices differ, not the values of the leaves. The
+/// difference of the indices is shown to be non-zero by multiplying it with its inverse, allocated here. Pass the bits
+/// given to the membership gadgets, which checks that they are bits. With more than 252 bits the difference could wrap
+/// around the group order so `GadgetError::InvalidDepth` is returned.
+pub fn distinct_indices_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    bits_a: &[AllocatedScalar],
+    bits_b: &[AllocatedScalar]
+) -> Result<(), GadgetError> {
+    if bits_a.len() != bits_b.len() {
+        return Err(GadgetError::ProofLengthMismatch { expected: bits_a.len(), found: bits_b.len() });
+    }
+    if bits_a.len() > 252 {
+        return Err(GadgetError::InvalidDepth(bits_a.len()));
+    }
+
+    let mut diff_lc = LinearCombination::default();
+    let mut diff = Some(Scalar::zero());
+    let mut exp_2 = Scalar::one();
+    for (a, b) in bits_a.iter().zip(bits_b.iter()) {
+        diff_lc = diff_lc + (a.variable - b.variable) * exp_2;
+        diff = match (diff, a.assignment, b.assignment) {
+            (Some(d), Some(a_v), Some(b_v)) => Some(d + (a_v - b_v) * exp_2),
+            _ => None
+        };
+        exp_2 = exp_2 + exp_2;
+    }
+
+    // diff * inv = 1 can only be satisfied when diff is not 0
+    let (l, _, o) = cs.allocate_multiplier(diff.map(|d| (d, d.invert())))?;
+    cs.constrain(l - diff_lc);
+    cs.constrain(o - Variable::One());
+
+    Ok(())
+}
+
+/// Enforce that the indices given by `bits_a` and `bits_b` (LSB first) differ in exactly `expected_distance` bits, i.e.
+/// the Hamming weight of their XOR, e.g. to show that 2 leaves are close in the tree. For bits, `a XOR b` is
+/// `a + b - 2ab` so this needs a multiplication per bit. Pass the bits given to the membership gadgets or constrain them
+/// with `constrain_leaf_index_bits` first, the XOR is not correct for other values.
+pub fn index_hamming_distance_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    bits_a: &[AllocatedScalar],
+    bits_b: &[AllocatedScalar],
+    expected_distance: u64
+) -> Result<(), GadgetError> {
+    if bits_a.len() != bits_b.len() {
+        return Err(GadgetError::ProofLengthMismatch { expected: bits_a.len(), found: bits_b.len() });
+    }
+
+    let mut distance_lc = LinearCombination::default();
+    for (a, b) in bits_a.iter().zip(bits_b.iter()) {
+        let (_, _, ab) = cs.multiply(a.variable.into(), b.variable.into());
+        distance_lc = distance_lc + a.variable + b.variable - ab * Scalar::from(2u8);
+    }
+    constrain_lc_with_scalar::<CS>(cs, distance_lc, &Scalar::from(expected_distance));
+
+    Ok(())
+}
+
+/// Prove that `q` is not a leaf of a tree whose leaves are sorted, i.e. the leaf at each position is less than the one
+/// at the next position, by showing 2 leaves `prev_leaf` and `next_leaf` at adjacent positions with
+/// `prev_leaf < q < next_leaf`. The index bits (LSB first) and proof nodes (from leaf to root) of both leaves are as for
+/// `vanilla_merkle_merkle_tree_verif_gadget`. That the tree is sorted is not proven here and has to be ensured by
+/// whoever builds the tree. Keep the smallest and the largest values as leaves so that every absent value has
+/// neighbours. The leaves and `q` should be less than 2^`n_bits` - 1 and `n_bits` not more than 64.
+pub fn sorted_non_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    q: AllocatedScalar,
+    prev_leaf: AllocatedScalar,
+    next_leaf: AllocatedScalar,
+    prev_index_bits: Vec<AllocatedScalar>,
+    prev_proof_nodes: Vec<AllocatedScalar>,
+    next_index_bits: Vec<AllocatedScalar>,
+    next_proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams,
+    n_bits: usize
+) -> Result<(), GadgetError> {
+    check_path_length(depth, &prev_index_bits, &prev_proof_nodes)?;
+    check_path_length(depth, &next_index_bits, &next_proof_nodes)?;
+
+    // Positions are adjacent, next - prev = 1. Positions are less than 2^depth so the difference cannot wrap around.
+    let mut diff_lc = LinearCombination::default();
+    let mut exp_2 = Scalar::one();
+    for (p, n) in prev_index_bits.iter().zip(next_index_bits.iter()) {
+        diff_lc = diff_lc + (n.variable - p.variable) * exp_2;
+        exp_2 = exp_2 + exp_2;
+    }
+    constrain_lc_with_scalar::<CS>(cs, diff_lc, &Scalar::one());
+
+    // prev_leaf + 1 <= q and q + 1 <= next_leaf
+    let (prev_plus_one, q_plus_one) = {
+        let mut plus_one = |v: AllocatedScalar| -> Result<AllocatedScalar, GadgetError> {
+            let assignment = v.assignment.map(|a| a + Scalar::one());
+            let variable = cs.allocate(assignment)?;
+            cs.constrain(variable - v.variable - Variable::One());
+            Ok(AllocatedScalar { variable, assignment })
+        };
+        (plus_one(prev_leaf)?, plus_one(q)?)
+    };
+    leaf_ordering_gadget(cs, prev_plus_one, q, n_bits)?;
+    leaf_ordering_gadget(cs, q_plus_one, next_leaf, n_bits)?;
+
+    vanilla_merkle_merkle_tree_verif_gadget(cs, depth, root, prev_leaf, prev_index_bits, prev_proof_nodes,
+                                            statics.clone(), poseidon_params)?;
+    vanilla_merkle_merkle_tree_verif_gadget(cs, depth, root, next_leaf, next_index_bits, next_proof_nodes,
+                                            statics, poseidon_params)
+}
+
+/// Returns the root of the tree as a linear combination given the leaf and the proof nodes.
+/// The proof nodes are linear combinations so that they can be committed variables, see `proof_node_lcs`, or constants.
+/// Does not check that `leaf_index_bits` are bits, use `constrain_leaf_index_bits` for that.
+/// left = (1-leaf_side) * leaf + (leaf_side * proof_node)
+/// right = leaf_side * leaf + ((1-leaf_side) * proof_node))
+/// which is computed with a single multiplication as
+/// mux = leaf_side * (proof_node - leaf)
+/// left = leaf + mux
+/// right = proof_node - mux
+pub fn vanilla_merkle_merkle_tree_root_constraints<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    leaf_val: LinearCombination,
+    leaf_index_bits: &[AllocatedScalar],
+    proof_nodes: &[LinearCombination],
+    statics: Vec<LinearCombination>,
+    poseidon_params: &PoseidonParams
+) -> Result<LinearCombination, GadgetError> {
+    root_constraints_with_hash_spec(cs, depth, leaf_val, leaf_index_bits, proof_nodes, statics, poseidon_params,
+                                    HashSpec::default())
+}
+
+/// Same as `vanilla_merkle_merkle_tree_root_constraints` with the S-boxes given by `hash_spec`.
+fn root_constraints_with_hash_spec<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    leaf_val: LinearCombination,
+    leaf_index_bits: &[AllocatedScalar],
+    proof_nodes: &[LinearCombination],
+    statics: Vec<LinearCombination>,
+    poseidon_params: &PoseidonParams,
+    hash_spec: HashSpec
+) -> Result<LinearCombination, GadgetError> {
+
+    check_path_length(depth, leaf_index_bits, proof_nodes)?;
+
+    let mut prev_hash = leaf_val;
+
+    for i in 0..depth {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("vsmt_gadget_level", height = i).entered();
+
+        let leaf_val_lc = prev_hash.clone();
+        let proof_node_lc = proof_nodes[i].clone();
+
+        let (_, _, mux) = cs.multiply(leaf_index_bits[i].variable.into(), proof_node_lc.clone() - leaf_val_lc.clone());
+        let left = leaf_val_lc + mux;
+        let right = proof_node_lc - mux;
+
+        let sbox = if i == 0 { &hash_spec.leaf_sbox } else { &hash_spec.node_sbox };
+        // prev_hash = mimc_hash_2::<CS>(cs, left, right, mimc_rounds, mimc_constants)?;
+        prev_hash = Poseidon_hash_2_constraints::<CS>(cs, left, right, statics.clone(), poseidon_params, sbox)?;
+
+        // Only the prover has the values
+        #[cfg(feature = "tracing")]
+        tracing::trace!(node = ?cs.evaluate_lc(&prev_hash).map(|h| scalar_to_hex(&h)));
+    }
+
+    Ok(prev_hash)
+}
+
+/// left = (1-leaf_side) * leaf + (leaf_side * proof_node)
+/// right = leaf_side * leaf + ((1-leaf_side) * proof_node))
+/// leaf_side * (1-leaf_side) = 0
+pub fn vanilla_merkle_merkle_tree_verif_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    leaf_val: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    vanilla_merkle_merkle_tree_verif_gadget_with_hash_spec(cs, depth, root, leaf_val, leaf_index_bits, proof_nodes,
+                                                           statics, poseidon_params, HashSpec::default())
+}
+
+/// Same as `vanilla_merkle_merkle_tree_verif_gadget` for trees hashed with the S-boxes given by `hash_spec`.
+pub fn vanilla_merkle_merkle_tree_verif_gadget_with_hash_spec<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    leaf_val: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams,
+    hash_spec: HashSpec
+) -> Result<(), GadgetError> {
+    path_verif_constraints(cs, depth, root, &leaf_val, &leaf_index_bits, &proof_nodes, &statics, poseidon_params,
+                           hash_spec)
+}
+
+/// Constraints of `vanilla_merkle_merkle_tree_verif_gadget_with_hash_spec` over borrowed variables, so that the proving
+/// helpers can wipe the assignments once the constraints are added.
+fn path_verif_constraints<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    leaf_val: &AllocatedScalar,
+    leaf_index_bits: &[AllocatedScalar],
+    proof_nodes: &[AllocatedScalar],
+    statics: &[AllocatedScalar],
+    poseidon_params: &PoseidonParams,
+    hash_spec: HashSpec
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, leaf_index_bits, proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, leaf_index_bits)?;
+
+    let computed_root = root_constraints_with_hash_spec(cs, depth, leaf_val.variable.into(), leaf_index_bits,
+                                                        &proof_node_lcs(proof_nodes), statics, poseidon_params, hash_spec)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Same as `vanilla_merkle_merkle_tree_verif_gadget` but `leaf_val` is public, so only the position of a known value
+/// is hidden and the leaf needs no commitment. The leaf is a variable allocated here and constrained to `leaf_val`.
+pub fn public_leaf_verif_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    leaf_val: &Scalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    // The value is known to both prover and verifier
+    let leaf = cs.allocate(Some(*leaf_val))?;
+    constrain_lc_with_scalar::<CS>(cs, leaf.into(), leaf_val);
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf.into(), &leaf_index_bits,
+                                                                     &proof_node_lcs(&proof_nodes), statics, poseidon_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Proof node of a level for `mixed_visibility_verif_gadget`
+#[derive(Copy, Clone, Debug)]
+pub enum SiblingKind {
+    /// Proof node known only to the prover
+    Committed(AllocatedScalar),
+    /// Proof node known to the verifier, e.g. from a published subtree root, so it needs no commitment
+    Public(Scalar),
+}
+
+/// Same as `vanilla_merkle_merkle_tree_verif_gadget` but each proof node (from leaf to root) is either committed or
+/// public as given by `proof_nodes`. Public proof nodes are constants of the circuit, which saves their commitments.
+/// The index bits are committed for all levels, so which sibling is taken at a level with a public proof node is
+/// still hidden.
+pub fn mixed_visibility_verif_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    leaf_val: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<SiblingKind>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let proof_nodes: Vec<LinearCombination> = proof_nodes.iter().map(|n| match n {
+        SiblingKind::Committed(node) => LinearCombination::from(node.variable),
+        SiblingKind::Public(node) => LinearCombination::from(*node),
+    }).collect();
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf_val.variable.into(), &leaf_index_bits,
+                                                                     &proof_nodes, statics, poseidon_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Same as `vanilla_merkle_merkle_tree_verif_gadget` but the root is a committed variable rather than public, so the
+/// verifier only needs a commitment to the root.
+pub fn vanilla_merkle_tree_verif_gadget_hidden_root<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: AllocatedScalar,
+    leaf_val: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf_val.variable.into(),
+                                                                     &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, poseidon_params)?;
+
+    cs.constrain(computed_root - root.variable);
+
+    Ok(())
+}
+
+/// Same as `vanilla_merkle_merkle_tree_verif_gadget` for a tree of depth `D` known at compile time. The leaf index bits
+/// and proof nodes are arrays so a path of the wrong length does not compile.
+pub fn vanilla_merkle_tree_verif_gadget_const<CS: ConstraintSystem, const D: usize>(
+    cs: &mut CS,
+    root: &Scalar,
+    leaf_val: AllocatedScalar,
+    leaf_index_bits: [AllocatedScalar; D],
+    proof_nodes: [AllocatedScalar; D],
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    if D == 0 {
+        return Err(GadgetError::InvalidDepth(D));
+    }
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, D, leaf_val.variable.into(),
+                                                                     &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, poseidon_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove that replacing `old_leaf` with `new_leaf` at the index given by `idx_bits` changes the root of the tree
+/// from `old_root` to `new_root`. Both leaves use the same proof nodes as the siblings do not change on update.
+pub fn merkle_update_transition_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    old_root: &Scalar,
+    new_root: &Scalar,
+    idx_bits: Vec<AllocatedScalar>,
+    old_leaf: AllocatedScalar,
+    new_leaf: AllocatedScalar,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &idx_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &idx_bits)?;
+
+    let computed_old_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, old_leaf.variable.into(),
+                                                                         &idx_bits, &proof_node_lcs(&proof_nodes),
+                                                                         statics.clone(), poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_old_root, old_root);
+
+    let computed_new_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, new_leaf.variable.into(),
+                                                                         &idx_bits, &proof_node_lcs(&proof_nodes),
+                                                                         statics, poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_new_root, new_root);
+
+    Ok(())
+}
+
+/// Prove the transition of `merkle_update_transition_gadget` where the leaf only increases, i.e. `old_leaf <= new_leaf`,
+/// as for append-only counters. Both leaves must be less than 2^`n_bits`, which should not be more than 64.
+pub fn monotonic_update_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    old_root: &Scalar,
+    new_root: &Scalar,
+    idx_bits: Vec<AllocatedScalar>,
+    old_leaf: AllocatedScalar,
+    new_leaf: AllocatedScalar,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams,
+    n_bits: usize
+) -> Result<(), GadgetError> {
+
+    leaf_ordering_gadget(cs, old_leaf, new_leaf, n_bits)?;
+    // The ordering only bounds `old_leaf` and `new_leaf - old_leaf`, which allows `new_leaf` up to 2^(n_bits+1) - 2
+    let new_leaf_quantity = AllocatedQuantity {
+        variable: new_leaf.variable,
+        assignment: new_leaf.assignment.map(|v| scalar_to_u64_array(&v)[0])
+    };
+    positive_no_gadget(cs, new_leaf_quantity, n_bits)?;
+
+    merkle_update_transition_gadget(cs, depth, old_root, new_root, idx_bits, old_leaf, new_leaf, proof_nodes, statics,
+                                    poseidon_params)
+}
+
+/// Prove a transfer of an amount between 2 balances of the tree, which changes its root from `old_root` to `new_root`.
+/// The sender's balance at `sender_idx_bits` goes from `sender_old` to `sender_new`, which changes the root to an
+/// intermediate root, then the receiver's balance at `recv_idx_bits` goes from `recv_old` to `recv_new` in the tree
+/// with the intermediate root. So `sender_proof_nodes` are the proof nodes (from leaf to root) of the sender in the
+/// old tree and `recv_proof_nodes` the ones of the receiver after the sender's update. The intermediate root is not
+/// revealed. The amount `sender_old - sender_new` must equal `recv_new - recv_old` and not be negative, and
+/// `sender_new` and `recv_old` must be less than 2^`n_bits`, so no balance becomes negative. `n_bits` should be less
+/// than 64 so that the receiver's new balance cannot overflow.
+pub fn transfer_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    old_root: &Scalar,
+    new_root: &Scalar,
+    sender_idx_bits: Vec<AllocatedScalar>,
+    sender_old: AllocatedScalar,
+    sender_new: AllocatedScalar,
+    sender_proof_nodes: Vec<AllocatedScalar>,
+    recv_idx_bits: Vec<AllocatedScalar>,
+    recv_old: AllocatedScalar,
+    recv_new: AllocatedScalar,
+    recv_proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams,
+    n_bits: usize
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &sender_idx_bits, &sender_proof_nodes)?;
+    check_path_length(depth, &recv_idx_bits, &recv_proof_nodes)?;
+
+    // sender_old - sender_new = recv_new - recv_old
+    cs.constrain(sender_old.variable - sender_new.variable - recv_new.variable + recv_old.variable);
+    // The amount is not negative and no balance goes below 0
+    leaf_ordering_gadget(cs, sender_new, sender_old, n_bits)?;
+    leaf_ordering_gadget(cs, recv_old, recv_new, n_bits)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &sender_idx_bits)?;
+    constrain_leaf_index_bits(cs, &recv_idx_bits)?;
+
+    let computed_old_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, sender_old.variable.into(),
+                                                                         &sender_idx_bits, &proof_node_lcs(&sender_proof_nodes),
+                                                                         statics.clone(), poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_old_root, old_root);
+
+    let intermediate_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, sender_new.variable.into(),
+                                                                         &sender_idx_bits, &proof_node_lcs(&sender_proof_nodes),
+                                                                         statics.clone(), poseidon_params)?;
+    let recv_old_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, recv_old.variable.into(),
+                                                                     &recv_idx_bits, &proof_node_lcs(&recv_proof_nodes),
+                                                                     statics.clone(), poseidon_params)?;
+    cs.constrain(intermediate_root - recv_old_root);
+
+    let computed_new_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, recv_new.variable.into(),
+                                                                         &recv_idx_bits, &proof_node_lcs(&recv_proof_nodes),
+                                                                         statics, poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_new_root, new_root);
+
+    Ok(())
+}
+
+/// Prove knowledge of a byte string of `num_bytes` bytes set with `update_bytes` at the position given by
+/// `leaf_index_bits`. `chunks` are the scalars of the bytes as returned by `bytes_to_scalars`. The leaf is computed with
+/// `leaf_hash_params` and the internal nodes with `hash_params`.
+pub fn bytes_leaf_verif_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    num_bytes: usize,
+    chunks: Vec<AllocatedScalar>,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    leaf_hash_params: &PoseidonParams,
+    hash_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let chunks: Vec<LinearCombination> = chunks.iter().map(|c| c.variable.into()).collect();
+    let leaf = Poseidon_hash_bytes_constraints::<CS>(cs, num_bytes, chunks, leaf_hash_params, &SboxType::Inverse)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf,
+                                                                     &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, hash_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove knowledge of the opening (`value`, `blinding`) of a leaf created with `leaf_commitment`. The leaf is computed
+/// with `leaf_hash_params` and `leaf_statics`, the internal nodes with `hash_params` and `statics`.
+pub fn committed_leaf_verif_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    value: AllocatedScalar,
+    blinding: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    leaf_statics: Vec<AllocatedScalar>,
+    leaf_hash_params: &PoseidonParams,
+    hash_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let leaf = poseidon_commit_gadget(cs, value, blinding, leaf_hash_params, leaf_statics)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf,
+                                                                     &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, hash_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove membership of the leaf `Poseidon_hash_2(value, blinding)` without revealing `value`, `blinding` or the leaf,
+/// so the value stays hidden under Poseidon for composing with other circuits. Same as `committed_leaf_verif_gadget`
+/// for trees whose leaves and nodes are hashed with the same `poseidon_params`.
+pub fn poseidon_hiding_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    value: AllocatedScalar,
+    blinding: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    committed_leaf_verif_gadget(cs, depth, root, value, blinding, leaf_index_bits, proof_nodes, statics.clone(),
+                                statics, poseidon_params, poseidon_params)
+}
+
+/// Prove that `leaf` is at the same index, given by `index_bits`, in two trees with roots `root_a` and `root_b`.
+/// `proof_nodes_a` and `proof_nodes_b` are the proof nodes of the leaf in each tree. Both trees have the same depth
+/// and are hashed with the same parameters.
+pub fn cross_tree_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root_a: &Scalar,
+    root_b: &Scalar,
+    leaf: AllocatedScalar,
+    index_bits: Vec<AllocatedScalar>,
+    proof_nodes_a: Vec<AllocatedScalar>,
+    proof_nodes_b: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &index_bits, &proof_nodes_a)?;
+    check_path_length(depth, &index_bits, &proof_nodes_b)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &index_bits)?;
+
+    let computed_root_a = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf.variable.into(),
+                                                                       &index_bits, &proof_node_lcs(&proof_nodes_a),
+                                                                       statics.clone(), poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_root_a, root_a);
+
+    let computed_root_b = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf.variable.into(),
+                                                                       &index_bits, &proof_node_lcs(&proof_nodes_b),
+                                                                       statics, poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_root_b, root_b);
+
+    Ok(())
+}
+
+/// Prove that `leaf` is at the index given by `leaf_index_bits` (LSB first) if `is_present` is 1, or that the index is
+/// empty, i.e. has leaf 0, if `is_present` is 0, without revealing which. The leaf on the path is `is_present * leaf`,
+/// so a single path is proven for both cases. `is_present` is constrained to be a bit.
+pub fn optional_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    is_present: AllocatedScalar,
+    leaf: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+    constrain_leaf_index_bits(cs, &[is_present])?;
+
+    let (_, _, path_leaf) = cs.multiply(is_present.variable.into(), leaf.variable.into());
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, path_leaf.into(),
+                                                                     &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove that `value` is a leaf of both trees with roots `root_a` and `root_b`, at independent indices given by
+/// `index_bits_a` and `index_bits_b`, i.e. that it is in the intersection of the trees. Unlike
+/// `cross_tree_membership_gadget`, the indices may differ and neither is revealed. Both trees have the same depth
+/// and are hashed with the same parameters.
+pub fn dual_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root_a: &Scalar,
+    root_b: &Scalar,
+    value: AllocatedScalar,
+    index_bits_a: Vec<AllocatedScalar>,
+    proof_nodes_a: Vec<AllocatedScalar>,
+    index_bits_b: Vec<AllocatedScalar>,
+    proof_nodes_b: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &index_bits_a, &proof_nodes_a)?;
+    check_path_length(depth, &index_bits_b, &proof_nodes_b)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &index_bits_a)?;
+    constrain_leaf_index_bits(cs, &index_bits_b)?;
+
+    let computed_root_a = vanilla_merkle_merkle_tree_root_constraints(cs, depth, value.variable.into(),
+                                                                       &index_bits_a, &proof_node_lcs(&proof_nodes_a),
+                                                                       statics.clone(), poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_root_a, root_a);
+
+    let computed_root_b = vanilla_merkle_merkle_tree_root_constraints(cs, depth, value.variable.into(),
+                                                                       &index_bits_b, &proof_node_lcs(&proof_nodes_b),
+                                                                       statics, poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_root_b, root_b);
+
+    Ok(())
+}
+
+/// Prove that `leaf` was in the tree as of the version given by `version_bits` (LSB first), where the roots of the tree
+/// after each version are kept as leaves of a root-history tree with root `history_root`. The root of the tree is
+/// computed from `leaf`, `leaf_index_bits` and `proof_nodes` as for `vanilla_merkle_merkle_tree_verif_gadget` and is
+/// then proven to be the leaf at the version in the history tree, whose proof nodes (from leaf to root) are
+/// `history_proof_nodes`. Neither the root of the tree, the index nor the version is revealed. Both trees are hashed
+/// with the same parameters.
+pub fn historical_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    history_depth: usize,
+    history_root: &Scalar,
+    leaf: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    version_bits: Vec<AllocatedScalar>,
+    history_proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+    check_path_length(history_depth, &version_bits, &history_proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+    constrain_leaf_index_bits(cs, &version_bits)?;
+
+    let root_at_version = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf.variable.into(),
+                                                                       &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                       statics.clone(), poseidon_params)?;
+
+    let computed_history_root = vanilla_merkle_merkle_tree_root_constraints(cs, history_depth, root_at_version,
+                                                                             &version_bits, &proof_node_lcs(&history_proof_nodes),
+                                                                             statics, poseidon_params)?;
+    constrain_lc_with_scalar::<CS>(cs, computed_history_root, history_root);
+
+    Ok(())
+}
+
+/// Prove that `left_leaf` and `right_leaf` are the children of the same node, i.e. they are at indices 2k and 2k+1.
+/// `shared_index_bits` are the bits of k (LSB first), i.e. the leaf index bits except the first, and `proof_nodes`
+/// are the proof nodes of the parent, hence both have `depth - 1` elements.
+pub fn sibling_leaves_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    left_leaf: AllocatedScalar,
+    right_leaf: AllocatedScalar,
+    shared_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    if depth < 2 {
+        return Err(GadgetError::InvalidDepth(depth));
+    }
+    check_path_length(depth - 1, &shared_index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &shared_index_bits)?;
+
+    // The lowest level hashes both leaves rather than a leaf and a proof node
+    let parent = Poseidon_hash_2_constraints::<CS>(cs, left_leaf.variable.into(), right_leaf.variable.into(),
+                                                   statics.clone(), poseidon_params, &SboxType::Inverse)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth - 1, parent,
+                                                                     &shared_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, poseidon_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove that the leaf at the index given by `leaf_index_bits` is in the tree and is nonzero, i.e. the slot is
+/// occupied, without revealing the leaf.
+pub fn occupied_leaf_verif_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    leaf_val: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    leaf_nonzero_gadget(cs, leaf_val)?;
+
+    vanilla_merkle_merkle_tree_verif_gadget(cs, depth, root, leaf_val, leaf_index_bits, proof_nodes, statics, poseidon_params)
+}
+
+/// Returns the leaf for `val` at the position given by `leaf_index_bits` as a linear combination, i.e.
+/// `leaf = Poseidon_hash_2(position, val)` as in trees with `LeafEncoding::Hashed`.
+/// Does not check that `leaf_index_bits` are bits, use `constrain_leaf_index_bits` for that.
+pub fn hashed_leaf_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    leaf_index_bits: &[AllocatedScalar],
+    val: AllocatedScalar,
+    statics: Vec<LinearCombination>,
+    leaf_hash_params: &PoseidonParams
+) -> Result<LinearCombination, R1CSError> {
+    let mut position = LinearCombination::default();
+    let mut exp_2 = Scalar::one();
+    for b in leaf_index_bits {
+        position = position + b.variable * exp_2;
+        exp_2 = exp_2 + exp_2;
+    }
+
+    Poseidon_hash_2_constraints::<CS>(cs, position, val.variable.into(), statics, leaf_hash_params, &SboxType::Inverse)
+}
+
+/// Prove knowledge of the value `val` in a tree with `LeafEncoding::Hashed` without revealing the value or the index.
+/// The leaf is computed with `leaf_hash_params` and `leaf_statics`, the internal nodes with `hash_params` and
+/// `statics`. The position is taken to be the index bits, so trees with hashed keys are not supported.
+pub fn hashed_leaf_verif_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    val: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    leaf_statics: Vec<AllocatedScalar>,
+    leaf_hash_params: &PoseidonParams,
+    hash_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+    let leaf_statics: Vec<LinearCombination> = leaf_statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let leaf = hashed_leaf_gadget(cs, &leaf_index_bits, val, leaf_statics, leaf_hash_params)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf,
+                                                                     &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, hash_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove that the committed index `idx` maps to the committed value `val` in a key-value tree where the leaf at index
+/// `idx` is `Poseidon_hash_2(idx, val)`, i.e. a tree with `LeafEncoding::Hashed` and same `params` for leaves and nodes.
+/// Unlike `hashed_leaf_verif_gadget`, `idx` is a committed variable and `index_bits` are constrained to be its bits,
+/// so the index the leaf is hashed with cannot differ from the position the leaf is proven at.
+pub fn kv_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    idx: AllocatedScalar,
+    val: AllocatedScalar,
+    index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &index_bits)?;
+
+    // idx = Sum(index_bits[i] * 2^i)
+    let mut idx_lc: LinearCombination = LinearCombination::default() - idx.variable;
+    let mut exp_2 = Scalar::one();
+    for b in &index_bits {
+        idx_lc = idx_lc + b.variable * exp_2;
+        exp_2 = exp_2 + exp_2;
+    }
+    cs.constrain(idx_lc);
+
+    let leaf = Poseidon_hash_2_constraints::<CS>(cs, idx.variable.into(), val.variable.into(), statics.clone(),
+                                                 params, &SboxType::Inverse)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf,
+                                                                     &index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove knowledge of `secret` for a leaf `Poseidon_hash_2(secret, secret)` in the tree without revealing the secret
+/// or the leaf, as for coins or nullifiers committed in a tree.
+pub fn preimage_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    secret: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let leaf = Poseidon_hash_2_constraints::<CS>(cs, secret.variable.into(), secret.variable.into(), statics.clone(),
+                                                 poseidon_params, &SboxType::Inverse)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf,
+                                                                     &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, poseidon_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove knowledge of `secret` for a leaf `Poseidon_hash_2(public_epoch, secret)` in the tree without revealing the
+/// secret or the leaf, as for nullifiers derived per epoch. `public_epoch` is a constant of the circuit so a proof for
+/// one epoch does not verify for another.
+pub fn nullifier_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    public_epoch: Scalar,
+    secret: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &leaf_index_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let leaf = Poseidon_hash_2_constraints::<CS>(cs, LinearCombination::from(public_epoch), secret.variable.into(),
+                                                 statics.clone(), poseidon_params, &SboxType::Inverse)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf,
+                                                                     &leaf_index_bits, &proof_node_lcs(&proof_nodes),
+                                                                     statics, poseidon_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove that `leaf` is `Poseidon_hash_2(a, b)` so that a leaf of the tree can be a node of another structure, like a
+/// hash chain, proven in the same circuit. Uses the same hash as the nodes of the tree.
+pub fn leaf_is_hash_of_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    leaf: AllocatedScalar,
+    a: AllocatedScalar,
+    b: AllocatedScalar,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+    let hash = Poseidon_hash_2_constraints::<CS>(cs, a.variable.into(), b.variable.into(), statics,
+                                                 poseidon_params, &SboxType::Inverse)?;
+    cs.constrain(hash - leaf.variable);
+    Ok(())
+}
+
+/// Prove that the leaf at the public `public_index` is not `v`, e.g. that a slot does not hold a forbidden value.
+/// `actual_leaf` is proven to be the leaf at `public_index` under `root` and `actual_leaf - v` is proven non-zero by
+/// its inverse. As the index is public, the side of each node is a constant and the path needs no multiplications.
+pub fn leaf_not_equal_at_index_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    public_index: &Scalar,
+    v: AllocatedScalar,
+    actual_leaf: AllocatedScalar,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    if depth == 0 {
+        return Err(GadgetError::InvalidDepth(depth));
+    }
+    if proof_nodes.len() != depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: depth, found: proof_nodes.len() });
+    }
+    if !index_fits(public_index, depth) {
+        return Err(GadgetError::IndexOutOfRange { depth });
+    }
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    let index_bits = get_bits(public_index, depth);
+    let mut prev_hash = LinearCombination::from(actual_leaf.variable);
+    for i in 0..depth {
+        let proof_node_lc = LinearCombination::from(proof_nodes[i].variable);
+        let (left, right) = if index_bits[i] == 1 {
+            (proof_node_lc, prev_hash)
+        } else {
+            (prev_hash, proof_node_lc)
+        };
+        prev_hash = Poseidon_hash_2_constraints::<CS>(cs, left, right, statics.clone(), poseidon_params, &SboxType::Inverse)?;
+    }
+    constrain_lc_with_scalar::<CS>(cs, prev_hash, root);
+
+    // (actual_leaf - v) * inv = 1
+    let diff = actual_leaf.assignment.and_then(|l| v.assignment.map(|v| l - v));
+    let (d, _, o) = cs.allocate_multiplier(diff.map(|d| (d, d.invert())))?;
+    cs.constrain(d - actual_leaf.variable + v.variable);
+    cs.constrain(o - Variable::One());
+
+    Ok(())
+}
+
+/// Root of a subtree of the given `height` where all leaves are empty, i.e. 0.
+pub fn empty_subtree_root(height: usize, params: &PoseidonParams) -> Scalar {
+    let mut cur = Scalar::zero();
+    for _ in 0..height {
+        cur = Poseidon_hash_2(cur.clone(), cur.clone(), params, &SboxType::Inverse);
+    }
+    cur
+}
+
+/// Prove that all leaves at indices in `[a, b)` are empty without revealing `a` or `b`.
+/// Only ranges that are exactly a subtree are supported, i.e. `b - a` is `2^h` and `a` is a multiple of `2^h`. The
+/// range is then empty if the root of the subtree is the root of an empty subtree of height `h`, so the proof costs
+/// about as much as a single non-membership proof. `h` is given by the number of `proof_nodes`, which are the proof
+/// nodes of the subtree root (from the subtree to the root of the tree), so `depth - h` of them; hence `h` is public.
+/// `low_bits` are the bits of `a` and `high_bits` the bits of the last index in the range `b - 1` so that both fit in
+/// `depth` bits (LSB first).
+pub fn empty_range_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    low_bits: Vec<AllocatedScalar>,
+    high_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    if low_bits.len() != depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: depth, found: low_bits.len() });
+    }
+    if high_bits.len() != depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: depth, found: high_bits.len() });
+    }
+    if proof_nodes.len() > depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: depth, found: proof_nodes.len() });
+    }
+    let height = depth - proof_nodes.len();
+    check_path_length(depth - height, &low_bits[height..], &proof_nodes)?;
+    // Number of leaves in the subtree, the range cannot be that large if it does not fit in a u64
+    let subtree_size = 1u64.checked_shl(height as u32).ok_or(GadgetError::InvalidDepth(depth))?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &low_bits)?;
+    constrain_leaf_index_bits(cs, &high_bits)?;
+
+    // a is a multiple of 2^h
+    for b in &low_bits[..height] {
+        cs.constrain(b.variable.into());
+    }
+
+    // (b - 1) - a = 2^h - 1. As the lower h bits of a are 0, this makes the lower h bits of b - 1 all 1 and the
+    // remaining bits same as a's, so the range is exactly the subtree.
+    let mut diff = LinearCombination::default();
+    let mut exp_2 = Scalar::one();
+    for i in 0..depth {
+        diff = diff + (high_bits[i].variable * exp_2) - (low_bits[i].variable * exp_2);
+        exp_2 = exp_2 + exp_2;
+    }
+    constrain_lc_with_scalar::<CS>(cs, diff, &Scalar::from(subtree_size - 1));
+
+    let subtree_root = LinearCombination::from(empty_subtree_root(height, poseidon_params));
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth - height, subtree_root,
+                                                                     &low_bits[height..], &proof_node_lcs(&proof_nodes),
+                                                                     statics, poseidon_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Prove knowledge of the leaf at index `idx` of `tree` without revealing the leaf or the index. The depth, root
+/// and hash parameters are taken from `tree`. Returns the proof and the commitments to the leaf, the bits of the
+/// leaf index (LSB first) and the proof nodes (from leaf to root), in that order. `transcript_label` separates the
+/// proofs of different protocols, see `DefaultTranscriptLabel`, and should be the same when verifying.
+pub fn gen_proof_of_leaf_membership<R: RngCore + CryptoRng, S: NodeStore>(tree: &VanillaSparseMerkleTree<S>, idx: Scalar, rng: &mut R,
+                                                            transcript_label: &'static [u8], pc_gens: &PedersenGens,
+                                                            bp_gens: &BulletproofGens) -> Result<(R1CSProof, Vec<CompressedRistretto>), GadgetError> {
+    let mut prover_transcript = Transcript::new(transcript_label);
+    let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+    let comms = leaf_membership_constraints(&mut prover, tree, idx, rng)?;
+
+    let proof = prover.prove(&bp_gens)?;
+
+    Ok((proof, comms))
+}
+
+/// Like `gen_proof_of_leaf_membership` but the proof is bound to `public_signal`, e.g. the address of a recipient, so it
+/// cannot be replayed in another context. The signal is added to the transcript and to the circuit as a variable
+/// constrained to it. Verify with `verify_proof_of_leaf_membership_with_signal` and the same signal.
+pub fn gen_proof_of_leaf_membership_with_signal<R: RngCore + CryptoRng, S: NodeStore>(tree: &VanillaSparseMerkleTree<S>, idx: Scalar,
+                                                                        public_signal: &Scalar, rng: &mut R,
+                                                                        transcript_label: &'static [u8], pc_gens: &PedersenGens,
+                                                                        bp_gens: &BulletproofGens) -> Result<(R1CSProof, Vec<CompressedRistretto>), GadgetError> {
+    let mut prover_transcript = Transcript::new(transcript_label);
+    prover_transcript.append_message(b"public_signal", public_signal.as_bytes());
+    let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+    public_signal_constraint(&mut prover, public_signal, Some(*public_signal))?;
+    let comms = leaf_membership_constraints(&mut prover, tree, idx, rng)?;
+
+    let proof = prover.prove(&bp_gens)?;
+
+    Ok((proof, comms))
+}
+
+/// Allocate a variable for `public_signal` and constrain it to the signal. `assignment` is the signal for the prover
+/// and None for the verifier.
+fn public_signal_constraint<CS: ConstraintSystem>(cs: &mut CS, public_signal: &Scalar,
+                                                  assignment: Option<Scalar>) -> Result<(), GadgetError> {
+    let var = cs.allocate(assignment)?;
+    constrain_lc_with_scalar::<CS>(cs, var.into(), public_signal);
+    Ok(())
+}
+
+/// Like `gen_proof_of_leaf_membership` but without the tree, only the `root`, the `leaf`, its `position` and its
+/// `merkle_proof` as returned by `VanillaSparseMerkleTree::get` are needed. The depth is the length of `merkle_proof`.
+pub fn gen_proof_of_leaf_membership_from_path<R: RngCore + CryptoRng>(root: &Scalar, hash_params: &PoseidonParams, position: Scalar,
+                                                                      leaf: Scalar, merkle_proof: &[Scalar], rng: &mut R,
+                                                                      transcript_label: &'static [u8], pc_gens: &PedersenGens,
+                                                                      bp_gens: &BulletproofGens) -> Result<(R1CSProof, Vec<CompressedRistretto>), GadgetError> {
+    let mut prover_transcript = Transcript::new(transcript_label);
+    let mut prover = MerkleProver::new(pc_gens, &mut prover_transcript, *root, hash_params);
+
+    let comms = prover.commit_phase(position, leaf, merkle_proof, rng)?;
+
+    let proof = prover.prove_phase(bp_gens)?;
+
+    Ok((proof, comms))
+}
+
+/// Commit to the leaf for `idx`, its index bits and merkle proof and add the membership constraints to `prover`.
+/// Returns the commitments in the order expected by `verify_proof_of_leaf_membership`.
+fn leaf_membership_constraints<R: RngCore + CryptoRng, S: NodeStore>(prover: &mut Prover, tree: &VanillaSparseMerkleTree<S>, idx: Scalar,
+                                                      rng: &mut R) -> Result<Vec<CompressedRistretto>, GadgetError> {
+    let mut merkle_proof = Some(Vec::<Scalar>::new());
+    let leaf = Secret::new(tree.try_get(idx, &mut merkle_proof)?);
+    let merkle_proof = Secret::new(merkle_proof.unwrap());
+
+    path_membership_constraints(prover, &tree.raw_root(), tree.hash_params, tree.key_position(&idx), *leaf,
+                                &merkle_proof, rng)
+}
+
+/// Commit to `leaf`, the bits of `position` and `merkle_proof` and add the membership constraints to `prover`.
+/// Returns the commitments in the order expected by `verify_proof_of_leaf_membership`.
+fn path_membership_constraints<R: RngCore + CryptoRng>(prover: &mut Prover, root: &Scalar, hash_params: &PoseidonParams,
+                                                       position: Scalar, leaf: Scalar, merkle_proof: &[Scalar],
+                                                       rng: &mut R) -> Result<Vec<CompressedRistretto>, GadgetError> {
+    let mut committed = MerkleProofBuilder::new(position, leaf, merkle_proof).commit(prover, rng);
+    let comms = committed.commitments.clone();
+    committed_path_constraints(prover, root, hash_params, &mut committed)?;
+    Ok(comms)
+}
+
+/// Variables for the leaf, the leaf index bits (LSB first) and the proof nodes (from the leaf to the root) as passed to
+/// `vanilla_merkle_merkle_tree_verif_gadget`, along with their commitments. With the `zeroize` feature, the proving
+/// helpers of this module wipe the assignments and the blindings of the commitments once the constraints are added.
+#[derive(Clone, Debug)]
+pub struct CommittedMerkleProof {
+    pub leaf: AllocatedScalar,
+    pub leaf_index_bits: Vec<AllocatedScalar>,
+    pub proof_nodes: Vec<AllocatedScalar>,
+    /// Commitments to the leaf, the leaf index bits and the proof nodes, in the order expected by
+    /// `verify_proof_of_leaf_membership`
+    pub commitments: Vec<CompressedRistretto>
+}
+
+/// Wipes the assignments, the commitments are public and kept
+#[cfg(feature = "zeroize")]
+impl Zeroize for CommittedMerkleProof {
+    fn zeroize(&mut self) {
+        self.leaf.zeroize();
+        self.leaf_index_bits.iter_mut().for_each(|b| b.zeroize());
+        self.proof_nodes.iter_mut().for_each(|n| n.zeroize());
+    }
+}
+
+/// Commits to a leaf, the bits of its position and its merkle proof for the prover, or allocates the variables for the
+/// same commitments for the verifier, so that callers do not keep the commitments and variables in parallel vectors.
+pub struct MerkleProofBuilder<'a> {
+    position: Scalar,
+    leaf: Scalar,
+    merkle_proof: &'a [Scalar]
+}
+
+impl<'a> MerkleProofBuilder<'a> {
+    /// `merkle_proof` is as returned by `VanillaSparseMerkleTree::get` and `position` is the index of the leaf, or the
+    /// position returned by `key_position` for trees with hashed keys.
+    pub fn new(position: Scalar, leaf: Scalar, merkle_proof: &'a [Scalar]) -> MerkleProofBuilder<'a> {
+        MerkleProofBuilder {
+            position,
+            leaf,
+            merkle_proof
+        }
+    }
+
+    /// Commit to the leaf, then each leaf index bit and then each proof node from the leaf to the root, each with
+    /// randomness from `rng`.
+    pub fn commit<R: RngCore + CryptoRng>(&self, prover: &mut Prover, mut rng: &mut R) -> CommittedMerkleProof {
+        let depth = self.merkle_proof.len();
+
+        let mut comms = vec![];
+
+        let mut commit = |prover: &mut Prover, val: Scalar| {
+            let blinding = Secret::new(Scalar::random(&mut rng));
+            prover.commit(val, *blinding)
+        };
+
+        let (com_leaf, var_leaf) = commit(prover, self.leaf);
+        let leaf_alloc_scalar = AllocatedScalar {
+            variable: var_leaf,
+            assignment: Some(self.leaf),
+        };
+        comms.push(com_leaf);
+
+        let mut leaf_index_alloc_scalars = vec![];
+        for b in get_bits(&self.position, depth).iter() {
+            let val: Scalar = Scalar::from(*b as u8);
+            let (c, v) = commit(prover, val.clone());
+            comms.push(c);
+            leaf_index_alloc_scalars.push(AllocatedScalar {
+                variable: v,
+                assignment: Some(val),
+            });
+        }
+
+        let mut proof_alloc_scalars = vec![];
+        for p in self.merkle_proof.iter().rev() {
+            let (c, v) = commit(prover, *p);
+            comms.push(c);
+            proof_alloc_scalars.push(AllocatedScalar {
+                variable: v,
+                assignment: Some(*p),
+            });
+        }
+
+        CommittedMerkleProof {
+            leaf: leaf_alloc_scalar,
+            leaf_index_bits: leaf_index_alloc_scalars,
+            proof_nodes: proof_alloc_scalars,
+            commitments: comms
+        }
+    }
+
+    /// Allocate the variables for `commitments` made with `commit` for a tree of the given `depth`.
+    pub fn verifier_view(verifier: &mut Verifier, depth: usize,
+                         commitments: &[CompressedRistretto]) -> Result<CommittedMerkleProof, GadgetError> {
+        // Commitment to leaf and one commitment each for the leaf index bit and proof node of each level
+        if commitments.len() != 1 + 2*depth {
+            return Err(GadgetError::ProofLengthMismatch { expected: 1 + 2*depth, found: commitments.len() });
+        }
+
+        let mut vars: Vec<AllocatedScalar> = commitments.iter().map(|c| {
+            AllocatedScalar {
+                variable: verifier.commit(*c),
+                assignment: None,
+            }
+        }).collect();
+        let proof_nodes = vars.split_off(1 + depth);
+        let leaf_index_bits = vars.split_off(1);
+
+        Ok(CommittedMerkleProof {
+            leaf: vars[0],
+            leaf_index_bits,
+            proof_nodes,
+            commitments: commitments.to_vec()
+        })
+    }
+}
+
+/// Add the membership constraints for a path committed with `MerkleProofBuilder::commit` and wipe the assignments of
+/// `committed`, whether the constraints could be added or not.
+fn committed_path_constraints(prover: &mut Prover, root: &Scalar, hash_params: &PoseidonParams,
+                              committed: &mut CommittedMerkleProof) -> Result<(), GadgetError> {
+    let depth = committed.proof_nodes.len();
+
+    let num_statics = num_required_statics(hash_params);
+    let statics = allocate_statics_for_prover(prover, num_statics);
+
+    let res = path_verif_constraints(prover, depth, root, &committed.leaf, &committed.leaf_index_bits,
+                                     &committed.proof_nodes, &statics, hash_params, HashSpec::default());
+    wipe(committed);
+    res
+}
+
+/// Wipe `secret` from memory with the `zeroize` feature, does nothing otherwise. This is best-effort as copies of the
+/// witness are kept by the prover of bulletproofs until it is dropped.
+#[cfg(feature = "zeroize")]
+fn wipe<Z: Zeroize>(secret: &mut Z) {
+    secret.zeroize();
+}
+
+#[cfg(not(feature = "zeroize"))]
+fn wipe<Z>(_secret: &mut Z) {}
+
+/// Storage for a secret of the prover that is wiped when dropped with the `zeroize` feature, so it is wiped on early
+/// returns too. Without the feature it holds the secret as is.
+#[cfg(feature = "zeroize")]
+type Secret<T> = Zeroizing<T>;
+
+#[cfg(not(feature = "zeroize"))]
+struct Secret<T>(T);
+
+#[cfg(not(feature = "zeroize"))]
+impl<T> Secret<T> {
+    fn new(secret: T) -> Secret<T> {
+        Secret(secret)
+    }
+}
+
+#[cfg(not(feature = "zeroize"))]
+impl<T> std::ops::Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Commitments to the leaf, the leaf index bits and the proof nodes, in the order expected by
+/// `verify_proof_of_leaf_membership`
+pub type Commitments = Vec<CompressedRistretto>;
+
+/// Proves membership of a leaf like `gen_proof_of_leaf_membership_from_path` but in 2 steps so the caller controls
+/// when each happens. `commit_phase` commits to the witness and returns the commitments, which can be published before
+/// `prove_phase` creates the proof, e.g. in interactive protocols where commitments are posted before a challenge.
+/// The proof is verified with `verify_proof_of_leaf_membership` using the transcript label of `transcript`.
+pub struct MerkleProver<'t, 'g, 'p> {
+    prover: Prover<'t, 'g>,
+    root: Scalar,
+    hash_params: &'p PoseidonParams,
+    committed: Option<CommittedMerkleProof>
+}
+
+impl<'t, 'g, 'p> MerkleProver<'t, 'g, 'p> {
+    pub fn new(pc_gens: &'g PedersenGens, transcript: &'t mut Transcript, root: Scalar,
+               hash_params: &'p PoseidonParams) -> MerkleProver<'t, 'g, 'p> {
+        MerkleProver {
+            prover: Prover::new(pc_gens, transcript),
+            root,
+            hash_params,
+            committed: None
+        }
+    }
+
+    /// Commit to `leaf` at `position` and its `merkle_proof` as returned by `VanillaSparseMerkleTree::get`. Can only
+    /// be called once.
+    pub fn commit_phase<R: RngCore + CryptoRng>(&mut self, position: Scalar, leaf: Scalar, merkle_proof: &[Scalar],
+                                                 rng: &mut R) -> Result<Commitments, GadgetError> {
+        if self.committed.is_some() {
+            return Err(GadgetError::AlreadyCommitted);
+        }
+        let committed = MerkleProofBuilder::new(position, leaf, merkle_proof).commit(&mut self.prover, rng);
+        let comms = committed.commitments.clone();
+        self.committed = Some(committed);
+        Ok(comms)
+    }
+
+    /// Add the membership constraints over the committed witness and create the proof. `commit_phase` must have been
+    /// called before.
+    pub fn prove_phase(mut self, bp_gens: &BulletproofGens) -> Result<R1CSProof, GadgetError> {
+        let mut committed = self.committed.take().ok_or(GadgetError::MissingAssignment)?;
+        committed_path_constraints(&mut self.prover, &self.root, self.hash_params, &mut committed)?;
+        Ok(self.prover.prove(bp_gens)?)
+    }
+}
+
+/// Verify a proof created with `gen_proof_of_leaf_membership` for a tree of the given `depth` and `root`. Fails if
+/// `transcript_label` is not the one the proof was created with.
+pub fn verify_proof_of_leaf_membership(depth: usize, root: &Scalar, hash_params: &PoseidonParams,
+                                       proof: R1CSProof, commitments: Vec<CompressedRistretto>,
+                                       transcript_label: &'static [u8], pc_gens: &PedersenGens,
+                                       bp_gens: &BulletproofGens) -> Result<(), GadgetError> {
+    let mut verifier_transcript = Transcript::new(transcript_label);
+    verify_leaf_membership_with(depth, root, hash_params, proof, commitments, &mut verifier_transcript, pc_gens,
+                                bp_gens, |_, _| Ok(()))
+}
+
+/// Verify a proof created with `gen_proof_of_leaf_membership_with_signal`. Fails if `public_signal` is not the one the
+/// proof was created with.
+pub fn verify_proof_of_leaf_membership_with_signal(depth: usize, root: &Scalar, hash_params: &PoseidonParams,
+                                                   public_signal: &Scalar, proof: R1CSProof,
+                                                   commitments: Vec<CompressedRistretto>,
+                                                   transcript_label: &'static [u8], pc_gens: &PedersenGens,
+                                                   bp_gens: &BulletproofGens) -> Result<(), GadgetError> {
+    let mut verifier_transcript = Transcript::new(transcript_label);
+    verifier_transcript.append_message(b"public_signal", public_signal.as_bytes());
+    verify_leaf_membership_with(depth, root, hash_params, proof, commitments, &mut verifier_transcript, pc_gens,
+                                bp_gens, |verifier, _| public_signal_constraint(verifier, public_signal, None))
+}
+
+/// Verify a proof created with `VanillaSparseMerkleTree::prove_membership_with_range` for a tree of the given `depth`
+/// and `root` and the same `range_bits`.
+pub fn verify_membership_with_range(depth: usize, root: &Scalar, hash_params: &PoseidonParams, range_bits: usize,
+                                    proof: R1CSProof, commitments: Vec<CompressedRistretto>,
+                                    transcript_label: &'static [u8], pc_gens: &PedersenGens,
+                                    bp_gens: &BulletproofGens) -> Result<(), GadgetError> {
+    check_range_bits(range_bits)?;
+    let mut verifier_transcript = Transcript::new(transcript_label);
+    verify_leaf_membership_with(depth, root, hash_params, proof, commitments, &mut verifier_transcript, pc_gens,
+                                bp_gens, |verifier, committed| {
+        let leaf = AllocatedQuantity { variable: committed.leaf.variable, assignment: None };
+        Ok(positive_no_gadget(verifier, leaf, range_bits)?)
+    })
+}
+
+/// Verify a membership proof over `verifier_transcript`. `constraints` adds the constraints of the variant of the proof
+/// once the variables for the commitments are allocated and before the membership constraints.
+fn verify_leaf_membership_with<F>(depth: usize, root: &Scalar, hash_params: &PoseidonParams, proof: R1CSProof,
+                                  commitments: Vec<CompressedRistretto>, verifier_transcript: &mut Transcript,
+                                  pc_gens: &PedersenGens, bp_gens: &BulletproofGens,
+                                  constraints: F) -> Result<(), GadgetError>
+    where F: FnOnce(&mut Verifier, &CommittedMerkleProof) -> Result<(), GadgetError> {
+    if commitments.len() != 1 + 2*depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: 1 + 2*depth, found: commitments.len() });
+    }
+
+    let mut verifier = Verifier::new(verifier_transcript);
+
+    let committed = MerkleProofBuilder::verifier_view(&mut verifier, depth, &commitments)?;
+
+    constraints(&mut verifier, &committed)?;
+
+    let num_statics = num_required_statics(hash_params);
+    let statics = allocate_statics_for_verifier(&mut verifier, num_statics, &pc_gens);
+
+    vanilla_merkle_merkle_tree_verif_gadget(
+        &mut verifier,
+        depth,
+        root,
+        committed.leaf,
+        committed.leaf_index_bits,
+        committed.proof_nodes,
+        statics,
+        hash_params)?;
+
+    Ok(verifier.verify(&proof, &pc_gens, &bp_gens)?)
+}
+
+fn check_range_bits(range_bits: usize) -> Result<(), GadgetError> {
+    if range_bits > 64 {
+        return Err(GadgetError::ConstraintSystem(R1CSError::GadgetError {
+            description: format!("at most 64 bits are supported but {} given", range_bits)
+        }));
+    }
+    Ok(())
+}
+
+/// Verify each of the proofs created with `gen_proof_of_leaf_membership` against the same `root`, one after another.
+/// All proofs must be created with the same `transcript_label`, depth and hash parameters. This is not batched
+/// verification as the version of bulletproofs used has none for R1CS proofs, so it costs as much as calling
+/// `verify_proof_of_leaf_membership` for each proof. The number of commitments of all proofs is checked before
+/// verifying any so a malformed proof fails early.
+pub fn verify_each_proof_of_leaf_membership(depth: usize, root: &Scalar, hash_params: &PoseidonParams,
+                                            proofs_and_commitments: &[(R1CSProof, Vec<CompressedRistretto>)],
+                                            transcript_label: &'static [u8], pc_gens: &PedersenGens,
+                                            bp_gens: &BulletproofGens) -> Result<(), GadgetError> {
+    for (_, commitments) in proofs_and_commitments {
+        if commitments.len() != 1 + 2*depth {
+            return Err(GadgetError::ProofLengthMismatch { expected: 1 + 2*depth, found: commitments.len() });
+        }
+    }
+    for (proof, commitments) in proofs_and_commitments {
+        verify_proof_of_leaf_membership(depth, root, hash_params, proof.clone(), commitments.clone(),
+                                        transcript_label, pc_gens, bp_gens)?;
+    }
+    Ok(())
+}
+
+/// Generators sufficient for proving membership with `vanilla_merkle_merkle_tree_verif_gadget` in a tree of the given
+/// `depth` hashed with `params`. The number of multipliers is counted by synthesizing the circuit once with dummy
+/// values and the capacity is the next power of 2.
+pub fn recommended_bp_gens(depth: usize, params: &PoseidonParams) -> BulletproofGens {
+    BulletproofGens::new(num_membership_multipliers(depth, params).next_power_of_two(), 1)
+}
+
+/// Number of multipliers of `vanilla_merkle_merkle_tree_verif_gadget` for a tree of the given `depth` hashed with
+/// `params`, counted by synthesizing the circuit once with dummy values.
+pub fn num_membership_multipliers(depth: usize, params: &PoseidonParams) -> usize {
+    assert!(depth > 0);
+    let pc_gens = PedersenGens::default();
+    let mut transcript = Transcript::new(b"VSMTGensSizing");
+    let mut prover = Prover::new(&pc_gens, &mut transcript);
+
+    // Leaf, leaf index bits and proof nodes
+    let mut vars = vec![];
+    for _ in 0..1 + 2*depth {
+        let (_, v) = prover.commit(Scalar::zero(), Scalar::zero());
+        vars.push(AllocatedScalar { variable: v, assignment: Some(Scalar::zero()) });
+    }
+    let proof_nodes = vars.split_off(1 + depth);
+    let leaf_index_bits = vars.split_off(1);
+    let leaf = vars[0];
+    let num_statics = num_required_statics(params);
+    let statics = allocate_statics_for_prover(&mut prover, num_statics);
+
+    // Only the number of multipliers is needed so the root does not matter
+    vanilla_merkle_merkle_tree_verif_gadget(&mut prover, depth, &Scalar::zero(), leaf, leaf_index_bits, proof_nodes,
+                                            statics, params).unwrap();
+
+    prover.num_multipliers()
+}
+
+/// Depth and arity of the cheapest to use tree that has room for `n` leaves and whose membership proof needs at most
+/// `max_constraints` multipliers, None if no tree does. The binary tree is preferred as its depth can be chosen and
+/// it has the most gadgets, then the 4-ary and the 8-ary trees which have fewer levels but a fixed depth. So a higher
+/// arity is only returned when the budget is too small for the binary tree of depth log2(n) or that depth is more than
+/// `TreeDepth`.
+pub fn recommend_tree_shape(n: u64, max_constraints: usize, params: &PoseidonParams) -> Option<(usize, usize)> {
+    // Smallest depth with 2^depth >= n
+    let depth_2 = (64 - (n.max(2) - 1).leading_zeros()) as usize;
+    if depth_2 <= TreeDepth && num_membership_multipliers(depth_2, params) <= max_constraints {
+        return Some((depth_2, 2));
+    }
+
+    // The 4:1 hash needs 4 inputs and a static in the permutation
+    let fits_4 = n as u128 <= 1u128 << (2 * gadget_vsmt_4::TreeDepth);
+    if params.width > 4 && fits_4 && gadget_vsmt_4::num_membership_multipliers_4(params) <= max_constraints {
+        return Some((gadget_vsmt_4::TreeDepth, 4));
+    }
+
+    let fits_8 = n as u128 <= 1u128 << gadget_vsmt_8::LeafIndexBits;
+    if fits_8 && gadget_vsmt_8::num_membership_multipliers_8(gadget_vsmt_8::TreeDepth, params) <= max_constraints {
+        return Some((gadget_vsmt_8::TreeDepth, 8));
+    }
+
+    None
+}
+
+/// Size of the serialized `proof` in bytes. The size grows logarithmically with the number of multipliers so it
+/// changes little with the depth of the tree.
+pub fn merkle_proof_size_bytes(proof: &R1CSProof) -> usize {
+    proof.to_bytes().len()
+}
+
+/// `count` random indices that fit in a tree of the given `depth` and random leaves to fill the tree with. Pass a
+/// seeded rng like `StdRng::from_seed` to get the same pairs on every run, e.g. to replay a failing test.
+pub fn random_key_values<R: RngCore + CryptoRng>(rng: &mut R, count: usize, depth: usize) -> Vec<(Scalar, Scalar)> {
+    (0..count).map(|_| (bits_to_scalar(&get_bits(&Scalar::random(rng), depth)), Scalar::random(rng))).collect()
+}
+
+/// Timings and sizes reported by `bench_merkle_membership`
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    pub depth: usize,
+    pub num_multipliers: usize,
+    pub num_constraints: usize,
+    /// Time to create the tree, insert leaves, synthesize the circuit once to count the multipliers and create the generators
+    pub setup_time: Duration,
+    pub prove_time: Duration,
+    pub verify_time: Duration,
+    pub proof_size_bytes: usize,
+}
+
+/// Benchmark proving and verifying membership of a leaf in a tree of the given `depth` hashed with `hash_params`.
+/// Meant to be called from a benchmark or a binary to reproduce the numbers for a choice of parameters.
+pub fn bench_merkle_membership<R: RngCore + CryptoRng>(depth: usize, hash_params: &PoseidonParams,
+                                                       rng: &mut R) -> Result<BenchResult, GadgetError> {
+    let label = b"VSMTBench";
+
+    let start = Instant::now();
+    let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, hash_params);
+    for i in 1..=10 {
+        let s = Scalar::from(i as u32);
+        tree.update(s, s);
+    }
+    let k = Scalar::from(7u32);
+
+    let pc_gens = PedersenGens::default();
+    let (num_multipliers, num_constraints) = {
+        let mut transcript = Transcript::new(label);
+        let mut prover = Prover::new(&pc_gens, &mut transcript);
+        leaf_membership_constraints(&mut prover, &tree, k, rng)?;
+        (prover.num_multipliers(), prover.num_constraints())
+    };
+    let bp_gens = BulletproofGens::new(num_multipliers.next_power_of_two(), 1);
+    let setup_time = start.elapsed();
+
+    let start = Instant::now();
+    let (proof, commitments) = gen_proof_of_leaf_membership(&tree, k, rng, label, &pc_gens, &bp_gens)?;
+    let prove_time = start.elapsed();
+
+    let proof_size_bytes = merkle_proof_size_bytes(&proof);
+
+    let start = Instant::now();
+    verify_proof_of_leaf_membership(depth, &tree.raw_root(), hash_params, proof, commitments, label, &pc_gens, &bp_gens)?;
+    let verify_time = start.elapsed();
+
+    Ok(BenchResult {
+        depth,
+        num_multipliers,
+        num_constraints,
+        setup_time,
+        prove_time,
+        verify_time,
+        proof_size_bytes,
+    })
+}
+
+/// Number of multiplications in the circuit proven once to calibrate `estimate_proving_time`
+const CalibrationMultipliers: usize = 256;
+
+/// Proving time per constraint, measured the first time it is needed by proving a chain of `CalibrationMultipliers`
+/// multiplications.
+static ProvingTimePerConstraint: Lazy<Duration> = Lazy::new(|| {
+    let pc_gens = PedersenGens::default();
+    let bp_gens = BulletproofGens::new(CalibrationMultipliers, 1);
+    let mut rng: rand::rngs::StdRng = SeedableRng::from_seed([0u8; 32]);
+
+    let mut transcript = Transcript::new(b"ProvingTimeCalibration");
+    let mut prover = Prover::new(&pc_gens, &mut transcript);
+    let x = Scalar::random(&mut rng);
+    let (_, x_var) = prover.commit(x, Scalar::random(&mut rng));
+    let mut cur: LinearCombination = x_var.into();
+    for _ in 0..CalibrationMultipliers {
+        let (_, _, o) = prover.multiply(cur, x_var.into());
+        cur = o.into();
+    }
+    let num_constraints = prover.num_constraints();
+
+    let start = Instant::now();
+    prover.prove(&bp_gens).expect("calibration circuit should be satisfied");
+    start.elapsed() / num_constraints.max(1) as u32
+});
+
+/// Rough estimate of the time to prove a circuit with `num_constraints` constraints, as reported by
+/// `Prover::num_constraints`, on this machine. This is a heuristic for capacity planning: the time of proving a small
+/// calibration circuit, measured once per process, is scaled linearly. It ignores the creation of the generators and
+/// the synthesis of the circuit, and actual times depend on the ratio of multipliers to constraints and on the load of
+/// the machine. None if the estimate does not fit in a `Duration`.
+pub fn estimate_proving_time(num_constraints: usize) -> Option<Duration> {
+    u32::try_from(num_constraints).ok().and_then(|n| ProvingTimePerConstraint.checked_mul(n))
+}
+
+/// Prove that the leaf position given by `position_bits` (LSB first) is the lower bits of `Poseidon_hash_2(idx, idx)`.
+/// The remaining bits of the hash are allocated here. `position_bits` should also be passed to
+/// `vanilla_merkle_merkle_tree_verif_gadget` which ensures that they are bits. Leaves of trees with hashed keys commit
+/// to the whole hash, use `hashed_key_membership_gadget` to prove membership in them.
+pub fn hashed_key_position_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    idx: AllocatedScalar,
+    position_bits: &[AllocatedScalar],
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), R1CSError> {
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+    hashed_key_position_constraints(cs, idx, position_bits, statics, poseidon_params)?;
+    Ok(())
+}
+
+/// Prove that `val` is stored for key `idx` in a tree created with `new_hashed_keys`, i.e. the leaf
+/// `Poseidon_hash_2(Poseidon_hash_2(idx, idx), val)` is at the position given by `position_bits`, the lower bits of
+/// `Poseidon_hash_2(idx, idx)`. `proof_nodes` are from leaf to root. Leaves and nodes are hashed with `poseidon_params`.
+pub fn hashed_key_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    idx: AllocatedScalar,
+    val: AllocatedScalar,
+    position_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+
+    check_path_length(depth, &position_bits, &proof_nodes)?;
+
+    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+    constrain_leaf_index_bits(cs, &position_bits)?;
+
+    let key_hash = hashed_key_position_constraints(cs, idx, &position_bits, statics.clone(), poseidon_params)?;
+    let leaf = Poseidon_hash_2_constraints::<CS>(cs, key_hash, val.variable.into(), statics.clone(), poseidon_params,
+                                                 &SboxType::Inverse)?;
+
+    let computed_root = vanilla_merkle_merkle_tree_root_constraints(cs, depth, leaf, &position_bits,
+                                                                     &proof_node_lcs(&proof_nodes), statics, poseidon_params)?;
+
+    constrain_lc_with_scalar::<CS>(cs, computed_root, root);
+
+    Ok(())
+}
+
+/// Constraints of `hashed_key_position_gadget`, returns `Poseidon_hash_2(idx, idx)`
+fn hashed_key_position_constraints<CS: ConstraintSystem>(
+    cs: &mut CS,
+    idx: AllocatedScalar,
+    position_bits: &[AllocatedScalar],
+    statics: Vec<LinearCombination>,
+    poseidon_params: &PoseidonParams
+) -> Result<LinearCombination, R1CSError> {
+    let hash = Poseidon_hash_2_constraints::<CS>(cs, idx.variable.into(), idx.variable.into(), statics, poseidon_params, &SboxType::Inverse)?;
+
+    let hash_bits = cs.evaluate_lc(&hash).map(|h| get_bits(&h, HashedKeyBits));
+
+    let mut bits_lc = LinearCombination::default();
+    let mut exp_2 = Scalar::one();
+    for b in position_bits {
+        bits_lc = bits_lc + b.variable * exp_2;
+        exp_2 = exp_2 + exp_2;
+    }
+
+    for i in position_bits.len()..HashedKeyBits {
+        let (a, b, o) = cs.allocate_multiplier(hash_bits.as_ref().map(|bits| {
+            let bit = bits[i] as u64;
+            ((1 - bit).into(), bit.into())
+        }))?;
+
+        // Enforce a * b = 0, so one of (a,b) is zero
+        cs.constrain(o.into());
+
+        // Enforce that a = 1 - b, so they both are 1 or 0.
+        cs.constrain(a + (b - 1u64));
+
+        bits_lc = bits_lc + b * exp_2;
+        exp_2 = exp_2 + exp_2;
+    }
+
+    // Enforce that Sum(b_i * 2^i, i = 0..HashedKeyBits-1) - hash = 0
+    cs.constrain(bits_lc - hash.clone());
+
+    Ok(hash)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merlin::Transcript;
+    use curve25519_dalek::constants::BASEPOINT_ORDER;
+    use crate::scalar_utils::{get_scalar_from_hex, bytes_to_scalars};
+    use crate::r1cs_utils::{pedersen_open_gadget_for_prover, pedersen_open_gadget_for_verifier};
+    use rand::SeedableRng;
+    use super::rand::rngs::StdRng;
+    use std::cell::Cell;
+    use std::convert::TryInto;
+    // For benchmarking
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_vanilla_sparse_merkle_tree() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        // Generate the MiMC round constants
+        /*let constants = (0..MIMC_ROUNDS).map(|_| Scalar::random(&mut test_rng)).collect::<Vec<_>>();
+        let mut tree = VanillaSparseMerkleTree::new(&constants);*/
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            assert_eq!(s, tree.get(s, &mut None));
+            let mut proof_vec = Vec::<Scalar>::new();
+            let mut proof = Some(proof_vec);
+            assert_eq!(s, tree.get(s, &mut proof));
+            proof_vec = proof.unwrap();
+            assert!(tree.verify_proof(s, s, &proof_vec, None));
+            assert!(tree.verify_proof(s, s, &proof_vec, Some(&tree.root())));
+        }
+
+        let kvs = random_key_values(&mut test_rng, 100, tree.proof_len());
+        for i in 0..kvs.len() {
+            tree.update(kvs[i].0, kvs[i].1);
+        }
+
+        for i in 0..kvs.len() {
+            assert_eq!(kvs[i].1, tree.get(kvs[i].0, &mut None));
+        }
+    }
+
+    #[test]
+    fn test_vanilla_sparse_merkle_tree_custom_store() {
+        // Store that counts the reads and writes made by the tree
+        struct CountingStore {
+            nodes: HashMap<ScalarBytes, DBVal>,
+            reads: Cell<usize>,
+            writes: usize,
+        }
+
+        impl NodeStore for CountingStore {
+            fn get(&self, key: &ScalarBytes) -> Option<DBVal> {
+                self.reads.set(self.reads.get() + 1);
+                self.nodes.get(key).cloned()
+            }
+
+            fn put(&mut self, key: ScalarBytes, val: DBVal) {
+                self.writes += 1;
+                self.nodes.insert(key, val);
+            }
+        }
+
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let depth = 16;
+        let store = CountingStore { nodes: HashMap::new(), reads: Cell::new(0), writes: 0 };
+        let mut tree = VanillaSparseMerkleTree::new_with_store(depth, &p_params, store);
+
+        // One node for each level of the empty tree
+        assert_eq!(tree.db.writes, depth);
+        assert_eq!(tree.db.reads.get(), 0);
+
+        let s = Scalar::from(5u32);
+        tree.update(s, s);
+        // Update reads the path to the leaf and writes a node at each level
+        assert_eq!(tree.db.reads.get(), depth);
+        assert_eq!(tree.db.writes, 2*depth);
+
+        let mut proof = Some(Vec::<Scalar>::new());
+        assert_eq!(s, tree.get(s, &mut proof));
+        assert_eq!(tree.db.reads.get(), 2*depth);
+        assert!(tree.verify_proof(s, s, &proof.unwrap(), None));
+
+        // Same root as the default in-memory store
+        let mut default_tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        default_tree.update(s, s);
+        assert_eq!(default_tree.root(), tree.root());
+    }
+
+    #[test]
+    fn test_owned_sparse_merkle_tree_clone() {
+        let p_params = Arc::new(PoseidonParams::new(6, 8, 8, 105));
+        let mut tree = OwnedSparseMerkleTree::new_with_depth(16, p_params.clone());
+        let mut borrowed_tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            let mut proof = Some(Vec::<Scalar>::new());
+            let root = tree.update_with_proof(s, s, &mut proof);
+            assert!(tree.verify_proof(s, s, &proof.unwrap(), Some(&MerkleRoot(root))));
+            borrowed_tree.update(s, s);
+        }
+        assert_eq!(tree.raw_root(), borrowed_tree.raw_root());
+
+        let clone = tree.clone();
+        assert!(Arc::ptr_eq(tree.hash_params(), clone.hash_params()));
+
+        // Clone is usable on another thread and agrees with the original
+        let handle = std::thread::spawn(move || {
+            let mut proofs = vec![];
+            for i in 1..10 {
+                let s = Scalar::from(i as u32);
+                let mut proof = Some(Vec::<Scalar>::new());
+                assert_eq!(clone.get(s, &mut proof), s);
+                let proof_vec = proof.unwrap();
+                assert!(clone.verify_proof(s, s, &proof_vec, None));
+                proofs.push(proof_vec);
+            }
+            (clone, proofs)
+        });
+        let (mut clone, proofs) = handle.join().unwrap();
+        assert_eq!(tree.root(), clone.root());
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            assert!(tree.verify_proof(s, s, &proofs[i - 1], None));
+        }
+
+        // Nodes are not shared so updating the clone leaves the original as it was
+        let old_root = tree.raw_root();
+        clone.update(Scalar::from(3u32), Scalar::from(30u32));
+        assert_ne!(tree.root(), clone.root());
+        assert_eq!(tree.raw_root(), old_root);
+        assert_eq!(tree.get(Scalar::from(3u32), &mut None), Scalar::from(3u32));
+    }
+
+    #[test]
+    fn test_read_snapshot() {
+        let p_params = Arc::new(PoseidonParams::new(6, 8, 8, 105));
+        let mut tree = OwnedSparseMerkleTree::new_with_depth(16, p_params);
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        let snapshot = tree.read_snapshot();
+        assert_eq!(snapshot.root(), tree.root());
+
+        let readers: Vec<_> = (0..4).map(|_| {
+            let snapshot = snapshot.clone();
+            std::thread::spawn(move || {
+                for _ in 0..5 {
+                    for i in 1..10 {
+                        let s = Scalar::from(i as u32);
+                        let mut proof = Some(Vec::<Scalar>::new());
+                        assert_eq!(snapshot.get(s, &mut proof), s);
+                        assert!(snapshot.verify_proof(s, s, &proof.unwrap(), None));
+                    }
+                }
+            })
+        }).collect();
+
+        // Writer updates while the readers read the snapshot
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s + Scalar::one());
+        }
+        for r in readers {
+            r.join().unwrap();
+        }
+
+        assert_ne!(snapshot.root(), tree.root());
+        assert_eq!(snapshot.get(Scalar::from(4u32), &mut None), Scalar::from(4u32));
+        assert_eq!(tree.get(Scalar::from(4u32), &mut None), Scalar::from(5u32));
+        let new_snapshot = tree.read_snapshot();
+        let mut proof = Some(Vec::<Scalar>::new());
+        assert_eq!(new_snapshot.get(Scalar::from(4u32), &mut proof), Scalar::from(5u32));
+        assert!(tree.verify_proof(Scalar::from(4u32), Scalar::from(5u32), &proof.unwrap(), None));
+    }
+
+    #[test]
+    fn test_update_after_snapshot_shares_nodes() {
+        let p_params = Arc::new(PoseidonParams::new(6, 8, 8, 105));
+        let depth = 16;
+        let mut tree = OwnedSparseMerkleTree::new_with_depth(depth, p_params);
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        let snapshot = tree.read_snapshot();
+        let nodes_before = snapshot.db.latest.len();
+
+        // The nodes of the snapshot are frozen rather than copied and the update only stores the nodes on its path
+        tree.update(Scalar::from(4u32), Scalar::from(5u32));
+        assert_eq!(tree.db.frozen.len(), 1);
+        assert!(Arc::ptr_eq(&tree.db.frozen[0], &snapshot.db.latest));
+        assert_eq!(snapshot.db.latest.len(), nodes_before);
+        assert_eq!(tree.db.latest.len(), depth);
+        assert_eq!(snapshot.get(Scalar::from(4u32), &mut None), Scalar::from(4u32));
+        assert_eq!(tree.get(Scalar::from(4u32), &mut None), Scalar::from(5u32));
+
+        // Layers are merged once no snapshot holds them
+        let second = tree.read_snapshot();
+        drop(snapshot);
+        tree.update(Scalar::from(5u32), Scalar::from(6u32));
+        assert_eq!(tree.db.frozen.len(), 2);
+        drop(second);
+        tree.update(Scalar::from(6u32), Scalar::from(7u32));
+        assert!(tree.db.frozen.is_empty());
+        for i in 1..10 {
+            assert!(tree.get(Scalar::from(i as u32), &mut None) != Scalar::zero());
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+        let stats = tree.stats();
+        assert_eq!(stats.num_leaves, 0);
+        assert_eq!(stats.db_nodes, TreeDepth);
+        assert_eq!(stats.fill_ratio, 0.0);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.num_leaves, 10);
+        assert_eq!(stats.depth, 32);
+        assert_eq!(stats.fill_ratio, 10.0 / 4294967296.0);
+        // Each update adds at most a node per level
+        assert!(stats.db_nodes > TreeDepth && stats.db_nodes <= TreeDepth + 10 * TreeDepth);
+
+        // Overwriting a leaf keeps the old nodes
+        tree.update(Scalar::from(3u32), Scalar::from(30u32));
+        let new_stats = tree.stats();
+        assert_eq!(new_stats.num_leaves, 10);
+        assert!(new_stats.db_nodes > stats.db_nodes);
+
+        // Clearing a leaf
+        tree.update(Scalar::from(3u32), Scalar::zero());
+        assert_eq!(tree.stats().num_leaves, 9);
+    }
+
+    #[test]
+    fn test_last_update_proof() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        assert!(tree.last_update_proof().is_none());
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            let val = Scalar::from(i as u32 + 100);
+            tree.update(s, val);
+            let (idx, last_val, proof) = tree.last_update_proof().unwrap();
+            assert_eq!(idx, s);
+            assert_eq!(last_val, val);
+
+            let mut get_proof = Some(Vec::<Scalar>::new());
+            assert_eq!(tree.get(s, &mut get_proof), val);
+            assert_eq!(proof, get_proof.unwrap());
+            assert!(tree.verify_proof(s, val, &proof, None));
+        }
+
+        // Proof given to `update_with_proof` is the same
+        let mut proof = Some(Vec::<Scalar>::new());
+        tree.update_with_proof(Scalar::from(3u32), Scalar::from(33u32), &mut proof);
+        assert_eq!(tree.last_update_proof().unwrap().2, proof.unwrap());
+
+        // Other changes invalidate it
+        let mut path = get_bits(&Scalar::from(5u32), 16);
+        path.reverse();
+        tree.update_at_path(&path, Scalar::from(55u32)).unwrap();
+        assert!(tree.last_update_proof().is_none());
+    }
+
+    #[test]
+    fn test_proof_len() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        for depth in [1, 8, 16, TreeDepth].iter() {
+            let mut tree = VanillaSparseMerkleTree::new_with_depth(*depth, &p_params);
+            tree.update(Scalar::one(), Scalar::from(10u32));
+            for i in 0..2u32 {
+                let mut proof = Some(Vec::<Scalar>::new());
+                tree.get(Scalar::from(i), &mut proof);
+                assert_eq!(tree.proof_len(), proof.unwrap().len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_vanilla_sparse_merkle_tree_eq() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree_1 = VanillaSparseMerkleTree::new(&p_params);
+        let mut tree_2 = VanillaSparseMerkleTree::new(&p_params);
+        assert_eq!(tree_1, tree_2);
+
+        // Order of updates does not matter
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree_1.update(s, s);
+        }
+        for i in (1..10).rev() {
+            let s = Scalar::from(i as u32);
+            tree_2.update(s, s);
+        }
+        assert_eq!(tree_1, tree_2);
+
+        tree_2.update(Scalar::from(5u32), Scalar::from(50u32));
+        assert_ne!(tree_1, tree_2);
+
+        // Empty trees of different depth
+        assert_ne!(VanillaSparseMerkleTree::new_with_depth(8, &p_params), VanillaSparseMerkleTree::new_with_depth(9, &p_params));
+
+        let debug = format!("{:?}", tree_1);
+        assert!(debug.contains(&format!("depth: {}", tree_1.depth)));
+        assert!(debug.contains(&scalar_to_hex(&tree_1.raw_root())));
+    }
+
+    #[test]
+    fn test_vanilla_sparse_merkle_tree_with_empty_hashes() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+        let empty_hashes = tree.empty_tree_hashes().to_vec();
+        assert_eq!(empty_hashes.len(), tree.proof_len() + 1);
+
+        let mut cached_tree = VanillaSparseMerkleTree::with_empty_hashes(&p_params, empty_hashes.clone(), tree.proof_len()).unwrap();
+        assert_eq!(cached_tree, tree);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+            cached_tree.update(s, s);
+        }
+        assert_eq!(cached_tree, tree);
+
+        for i in 0..12 {
+            let s = Scalar::from(i as u32);
+            let mut proof = Some(Vec::<Scalar>::new());
+            let mut cached_proof = Some(Vec::<Scalar>::new());
+            assert_eq!(tree.get(s, &mut proof), cached_tree.get(s, &mut cached_proof));
+            assert_eq!(proof, cached_proof);
+        }
+
+        // Prefix of the hashes for a smaller tree
+        let small_tree = VanillaSparseMerkleTree::with_empty_hashes(&p_params, empty_hashes[..9].to_vec(), 8).unwrap();
+        assert_eq!(small_tree, VanillaSparseMerkleTree::new_with_depth(8, &p_params));
+
+        assert!(VanillaSparseMerkleTree::with_empty_hashes(&p_params, empty_hashes[..9].to_vec(), 9).is_err());
+        let mut bad_hashes = empty_hashes.clone();
+        bad_hashes[0] = Scalar::one();
+        assert!(VanillaSparseMerkleTree::with_empty_hashes(&p_params, bad_hashes, tree.proof_len()).is_err());
+    }
+
+    #[test]
+    fn test_verify_merkle_proof_without_tree() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let depth = tree.proof_len();
+        let root = tree.raw_root();
+        let proofs: Vec<Vec<Scalar>> = (1..10).map(|i| {
+            let mut proof = Some(Vec::<Scalar>::new());
+            tree.get(Scalar::from(i as u32), &mut proof);
+            proof.unwrap()
+        }).collect();
+        drop(tree);
+
+        // Only the root, the proof and the hash parameters are needed
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            assert!(verify_merkle_proof(s, s, &proofs[i-1], &root, &p_params, depth));
+            assert!(!verify_merkle_proof(s, s + Scalar::one(), &proofs[i-1], &root, &p_params, depth));
+        }
+    }
+
+    #[test]
+    fn test_compute_root_from_proof() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let s = Scalar::from(4u32);
+        let mut proof = Some(Vec::<Scalar>::new());
+        tree.get(s, &mut proof);
+        let mut proof_vec = proof.unwrap();
+
+        assert_eq!(compute_root_from_proof(s, s, &proof_vec, &p_params, tree.proof_len()), Some(tree.raw_root()));
+
+        // Tampering with any proof node changes the computed root
+        proof_vec[tree.proof_len() / 2] += Scalar::one();
+        assert_ne!(compute_root_from_proof(s, s, &proof_vec, &p_params, tree.proof_len()), Some(tree.raw_root()));
+
+        // A proof with a missing node has no root
+        assert_eq!(compute_root_from_proof(s, s, &proof_vec[1..], &p_params, tree.proof_len()), None);
+    }
+
+    #[test]
+    fn test_verify_proofs_batch() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 8;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=5u32 {
+            tree.update(Scalar::from(i), Scalar::from(i + 10));
+        }
+
+        let proof_of = |i: u32| -> Vec<Scalar> {
+            let mut proof = Some(Vec::with_capacity(tree.proof_len()));
+            tree.get(Scalar::from(i), &mut proof);
+            proof.unwrap()
+        };
+
+        let mut short_proof = proof_of(3);
+        short_proof.pop();
+        let items = vec![
+            (Scalar::from(1u32), Scalar::from(11u32), proof_of(1)),
+            // Wrong value
+            (Scalar::from(2u32), Scalar::from(13u32), proof_of(2)),
+            (Scalar::from(3u32), Scalar::from(13u32), proof_of(3)),
+            // Proof of another index
+            (Scalar::from(4u32), Scalar::from(14u32), proof_of(5)),
+            (Scalar::from(3u32), Scalar::from(13u32), short_proof),
+            // Empty leaf
+            (Scalar::from(100u32), Scalar::zero(), proof_of(100)),
+            // Index beyond the tree whose lower bits are of a valid leaf
+            (Scalar::from(3u32 + (1 << depth)), Scalar::from(13u32), proof_of(3)),
+        ];
+        assert_eq!(tree.verify_proofs_batch(&items), vec![true, false, true, false, false, true, false]);
+        assert!(tree.verify_proofs_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_proofs_for() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        // Includes indices that were never set
+        let indices: Vec<Scalar> = (0..15).map(|i| Scalar::from(i as u32)).collect();
+        let mut count = 0;
+        for (idx, (val, proof_vec)) in indices.iter().zip(tree.proofs_for(&indices)) {
+            let mut proof = Some(Vec::<Scalar>::new());
+            assert_eq!(val, tree.get(*idx, &mut proof));
+            assert_eq!(proof_vec, proof.unwrap());
+            assert!(tree.verify_proof(*idx, val, &proof_vec, None));
+            count += 1;
+        }
+        assert_eq!(count, indices.len());
+    }
+
+    #[test]
+    fn test_subtree_partial_proof() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+
+        for i in 1..40 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        // Leaves 0 to 15 are under the subtree with prefix of 12 zero bits
+        let prefix_len = 12;
+        let prefix_bits = vec![0u8; prefix_len];
+        let subtree_root = tree.subtree_root(&prefix_bits);
+
+        // Subtree root is verified once against the main root
+        let subtree_proof = tree.subtree_proof(&prefix_bits);
+        assert_eq!(subtree_proof.len(), prefix_len);
+        assert_eq!(compute_root_from_proof(Scalar::zero(), subtree_root, &subtree_proof, &p_params, prefix_len), Some(tree.raw_root()));
+
+        for i in 0..16 {
+            let s = Scalar::from(i as u32);
+            let val = tree.get(s, &mut None);
+            let partial_proof = tree.partial_proof(s, prefix_len);
+            assert_eq!(partial_proof.len(), tree.proof_len() - prefix_len);
+
+            let mut full_proof = Some(Vec::<Scalar>::new());
+            tree.get(s, &mut full_proof);
+            let full_proof = full_proof.unwrap();
+            assert_eq!(&full_proof[prefix_len..], &partial_proof[..]);
+
+            // Verifying against the subtree root agrees with full verification
+            let partial_ok = compute_root_from_proof(s, val, &partial_proof, &p_params, tree.proof_len() - prefix_len) == Some(subtree_root);
+            assert_eq!(partial_ok, tree.verify_proof(s, val, &full_proof, None));
+            assert!(partial_ok);
+            assert_ne!(compute_root_from_proof(s, val + Scalar::one(), &partial_proof, &p_params, tree.proof_len() - prefix_len), Some(subtree_root));
+        }
+
+        // Subtree with prefix 0..01 has leaves 16 to 31
+        let mut prefix_bits = vec![0u8; prefix_len];
+        prefix_bits[prefix_len - 1] = 1;
+        let subtree_root = tree.subtree_root(&prefix_bits);
+        let s = Scalar::from(20u32);
+        assert_eq!(compute_root_from_proof(s, s, &tree.partial_proof(s, prefix_len), &p_params, tree.proof_len() - prefix_len), Some(subtree_root));
+        assert_eq!(compute_root_from_proof(Scalar::one(), subtree_root, &tree.subtree_proof(&prefix_bits), &p_params, prefix_len), Some(tree.raw_root()));
+
+        // Full prefix is the leaf itself
+        assert_eq!(tree.subtree_root(&get_bits(&s, tree.proof_len()).into_iter().rev().collect::<Vec<u8>>()), s);
+    }
+
+    #[cfg(feature = "constant-time")]
+    #[test]
+    fn test_verify_proof_ct() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let old_root = MerkleRoot(Scalar::from(12345u32));
+        for i in 0..12 {
+            let s = Scalar::from(i as u32);
+            let mut proof = Some(Vec::<Scalar>::new());
+            let val = tree.get(s, &mut proof);
+            let proof_vec = proof.unwrap();
+            for v in vec![val, val + Scalar::one()] {
+                assert_eq!(tree.verify_proof_ct(s, v, &proof_vec, None), tree.verify_proof(s, v, &proof_vec, None));
+                assert_eq!(tree.verify_proof_ct(s, v, &proof_vec, Some(&old_root)), tree.verify_proof(s, v, &proof_vec, Some(&old_root)));
+            }
+            assert!(tree.verify_proof_ct(s, val, &proof_vec, None));
+            // Index with the same lower bits as `s`
+            let aliased = s + Scalar::from(1u64 << tree.proof_len());
+            assert!(!tree.verify_proof_ct(aliased, val, &proof_vec, None));
+        }
+    }
+
+    #[test]
+    fn test_update_at_path() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        let mut path_tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+
+        for i in vec![1u32, 5, 9, 300, 65535] {
+            let s = Scalar::from(i);
+            tree.update(s, s);
+
+            let mut path = get_bits(&s, 16);
+            path.reverse();
+            assert_eq!(path_tree.update_at_path(&path, s).unwrap(), tree.raw_root());
+            assert_eq!(path_tree, tree);
+            assert_eq!(path_tree.subtree_root(&path), s);
+        }
+
+        // Path must have one element for each level and each element must be a child of a binary tree
+        assert_eq!(path_tree.update_at_path(&[0u8; 15], Scalar::one()),
+                   Err(GadgetError::ProofLengthMismatch { expected: 16, found: 15 }));
+        let mut path = vec![0u8; 16];
+        path[3] = 2;
+        assert_eq!(path_tree.update_at_path(&path, Scalar::one()), Err(GadgetError::InvalidPath { level: 3, child: 2 }));
+        assert_eq!(path_tree, tree);
+    }
+
+    #[test]
+    fn test_authentication_path() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i * 3), Scalar::from(i + 50));
+        }
+
+        let root = tree.raw_root();
+        for i in [0u32, 3, 6, 7, 30, 65535].iter() {
+            let idx = Scalar::from(*i);
+            let path = tree.authentication_path(idx);
+            assert_eq!(path.siblings.len(), tree.proof_len());
+
+            let mut proof = Some(Vec::<Scalar>::new());
+            let val = tree.get(idx, &mut proof);
+            let proof = proof.unwrap();
+            let siblings: Vec<Scalar> = path.siblings.iter().rev().map(|(s, _)| *s).collect();
+            assert_eq!(siblings, proof);
+            assert_eq!(path.siblings[0].1, if i & 1 == 1 { Side::Left } else { Side::Right });
+
+            // Agrees with verify_proof
+            assert!(tree.verify_proof(idx, val, &proof, None));
+            assert!(path.verify(val, &root, &p_params));
+            let wrong_val = val + Scalar::one();
+            assert_eq!(path.verify(wrong_val, &root, &p_params), tree.verify_proof(idx, wrong_val, &proof, None));
+            assert!(!path.verify(wrong_val, &root, &p_params));
+
+            // Side of a sibling matters unless it equals the leaf, as for 2 empty leaves
+            let mut flipped = path.clone();
+            flipped.siblings[0].1 = if flipped.siblings[0].1 == Side::Left { Side::Right } else { Side::Left };
+            assert_eq!(flipped.verify(val, &root, &p_params), flipped.siblings[0].0 == val);
+        }
+    }
+
+    #[test]
+    fn test_compressed_proof() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        for i in 0..12 {
+            let s = Scalar::from(i as u32);
+            let mut proof = Some(Vec::<Scalar>::new());
+            let val = tree.get(s, &mut proof);
+            let proof_vec = proof.unwrap();
+
+            let (c_val, compressed) = tree.get_compressed(s);
+            assert_eq!(c_val, val);
+            // Only the lowest 4 levels have leaves, the rest of the siblings are empty
+            assert!(compressed.nodes.len() <= 4);
+            assert_eq!(compressed.bitmap.count_ones() as usize, compressed.nodes.len());
+            assert_eq!(compressed.decompress(tree.empty_tree_hashes()).unwrap(), proof_vec);
+            assert_eq!(CompressedProof::compress(&proof_vec, tree.empty_tree_hashes()).unwrap(), compressed);
+
+            assert!(tree.verify_compressed(s, val, &compressed, None));
+            assert!(!tree.verify_compressed(s, val + Scalar::one(), &compressed, None));
+        }
+
+        // Compressing a proof of an empty tree leaves no nodes
+        let empty_tree = VanillaSparseMerkleTree::new(&p_params);
+        let (_, compressed) = empty_tree.get_compressed(Scalar::from(5u32));
+        assert_eq!(compressed, CompressedProof { bitmap: 0, nodes: vec![] });
+
+        // Malformed proofs
+        let (val, mut compressed) = tree.get_compressed(Scalar::from(3u32));
+        compressed.nodes.pop();
+        assert!(compressed.decompress(tree.empty_tree_hashes()).is_err());
+        assert!(!tree.verify_compressed(Scalar::from(3u32), val, &compressed, None));
+        let small_tree = VanillaSparseMerkleTree::new_with_depth(8, &p_params);
+        let bad = CompressedProof { bitmap: 1 << 10, nodes: vec![Scalar::one()] };
+        assert!(bad.decompress(small_tree.empty_tree_hashes()).is_err());
+
+        // No empty tree hashes
+        assert_eq!(CompressedProof::compress(&[], &[]), Err(GadgetError::InvalidDepth(0)));
+        assert_eq!(compressed.decompress(&[]), Err(GadgetError::InvalidDepth(0)));
+    }
+
+    #[test]
+    fn test_verify_proof_wrong_length() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let k = Scalar::from(7u32);
+        let mut proof = Some(Vec::<Scalar>::new());
+        tree.get(k, &mut proof);
+        let mut proof_vec = proof.unwrap();
+        assert!(tree.verify_proof(k, k, &proof_vec, None));
+
+        // One element short
+        let last = proof_vec.pop().unwrap();
+        assert!(!tree.verify_proof(k, k, &proof_vec, None));
+        assert!(!verify_merkle_proof(k, k, &proof_vec, &tree.root, &p_params, tree.proof_len()));
+        assert!(!tree.verify_proof(k, k, &[], None));
+
+        // One element too many
+        proof_vec.push(last);
+        proof_vec.push(last);
+        assert!(!tree.verify_proof(k, k, &proof_vec, None));
+    }
+
+    #[test]
+    fn test_update_with_proof() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            let mut proof = Some(Vec::<Scalar>::new());
+            let root = tree.update_with_proof(s, s, &mut proof);
+            let proof_vec = proof.unwrap();
+            assert_eq!(tree.proof_len(), proof_vec.len());
+            assert!(tree.verify_proof(s, s, &proof_vec, Some(&MerkleRoot(root))));
+
+            let mut get_proof = Some(Vec::<Scalar>::new());
+            tree.get(s, &mut get_proof);
+            assert_eq!(proof_vec, get_proof.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_build_streaming() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let depth = 12;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+
+        let empty_root = tree.raw_root();
+        assert_eq!(build_streaming(&p_params, depth, Vec::<(Scalar, Scalar)>::new().into_iter()).unwrap(), empty_root);
+
+        let mut rng: StdRng = SeedableRng::from_seed([5u8; 32]);
+        let mut indices: Vec<u32> = (0..50).map(|_| rng.next_u32() % (1 << depth)).collect();
+        indices.extend(vec![0, 1, 2, 3, (1 << depth) - 1, 1 << (depth - 1), (1 << (depth - 1)) - 1]);
+        indices.sort();
+        indices.dedup();
+        let leaves: Vec<(Scalar, Scalar)> = indices.iter().map(|i| (Scalar::from(*i), Scalar::from(*i + 1000))).collect();
+        for (i, l) in &leaves {
+            tree.update(*i, *l);
+        }
+        assert_eq!(build_streaming(&p_params, depth, leaves.clone().into_iter()).unwrap(), tree.raw_root());
+
+        // Single leaf
+        let mut single = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        single.update(Scalar::from(77u32), Scalar::one());
+        assert_eq!(build_streaming(&p_params, depth, vec![(Scalar::from(77u32), Scalar::one())].into_iter()).unwrap(),
+                   single.raw_root());
+
+        // Not sorted or duplicate index
+        let mut unsorted = leaves.clone();
+        unsorted.swap(3, 4);
+        assert_eq!(build_streaming(&p_params, depth, unsorted.into_iter()), Err(GadgetError::UnsortedLeaves));
+        let mut duplicate = leaves.clone();
+        duplicate.insert(5, leaves[5]);
+        assert_eq!(build_streaming(&p_params, depth, duplicate.into_iter()), Err(GadgetError::UnsortedLeaves));
+    }
+
+    #[test]
+    fn test_to_flat() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let depth = 6;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+
+        let flat = tree.to_flat();
+        assert_eq!(flat.len(), (1 << depth) - 1);
+        assert!(flat.iter().all(|n| n.is_none()));
+        assert_eq!(VanillaSparseMerkleTree::from_flat(&flat, &p_params).unwrap().raw_root(), tree.raw_root());
+
+        for i in [1u32, 2, 9, 33, 63].iter() {
+            let s = Scalar::from(*i);
+            tree.update(s, s + Scalar::one());
+        }
+        let flat = tree.to_flat();
+        assert_eq!(flat[0], Some(NodeStore::get(&tree.db, &tree.raw_root().to_bytes()).unwrap()));
+        // Leaves 1 and 2 are below the nodes at index 31 and 32 of the level above the leaves
+        assert_eq!(flat[31], Some((Scalar::zero(), Scalar::from(2u32))));
+        assert_eq!(flat[32], Some((Scalar::from(3u32), Scalar::zero())));
+        assert_eq!(flat[33], None);
+
+        let restored = VanillaSparseMerkleTree::from_flat(&flat, &p_params).unwrap();
+        assert_eq!(restored.raw_root(), tree.raw_root());
+        assert_eq!(restored.depth, depth);
+        for i in 0..64u32 {
+            let s = Scalar::from(i);
+            assert_eq!(restored.get(s, &mut None), tree.get(s, &mut None));
+        }
+
+        // Wrong number of nodes or a node whose children do not match
+        assert!(VanillaSparseMerkleTree::from_flat(&flat[1..], &p_params).is_err());
+        let mut corrupt = flat.clone();
+        corrupt[31] = Some((Scalar::zero(), Scalar::from(5u32)));
+        assert!(VanillaSparseMerkleTree::from_flat(&corrupt, &p_params).is_err());
+        let mut missing = flat.clone();
+        missing[31] = None;
+        assert!(VanillaSparseMerkleTree::from_flat(&missing, &p_params).is_err());
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        assert!(tree.verify_integrity().is_ok());
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        assert!(tree.verify_integrity().is_ok());
+
+        // Corrupt the left child of the root, whose subtree has all the leaves
+        let (left, _) = NodeStore::get(&tree.db, &tree.root.to_bytes()).unwrap();
+        let (ll, lr) = NodeStore::get(&tree.db, &left.to_bytes()).unwrap();
+        tree.db.put(left.to_bytes(), (ll, lr + Scalar::one()));
+        assert_eq!(tree.verify_integrity(), Err(GadgetError::CorruptNode(left)));
+
+        // Missing node
+        tree.db.put(left.to_bytes(), (ll, lr));
+        assert!(tree.verify_integrity().is_ok());
+        tree.db.remove(&ll.to_bytes());
+        assert_eq!(tree.verify_integrity(), Err(GadgetError::CorruptNode(ll)));
+
+        // Empty subtrees are checked too
+        let mut empty_tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        let node = empty_tree.empty_tree_hashes[3];
+        empty_tree.db.put(node.to_bytes(), (Scalar::one(), Scalar::one()));
+        assert_eq!(empty_tree.verify_integrity(), Err(GadgetError::CorruptNode(node)));
+    }
+
+    #[test]
+    fn test_diff() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+
+        let empty_root = tree.root;
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        assert!(tree.diff(&tree.root.clone()).is_empty());
+
+        let old_root = tree.root;
+        tree.update(Scalar::from(5u32), Scalar::from(50u32));
+        let diff = tree.diff(&old_root);
+        assert!(diff.len() <= tree.proof_len());
+        // Updating a leaf changes the node at each level above it
+        assert_eq!(diff.len(), tree.proof_len());
+        assert_eq!(diff[0].0, tree.root);
+        for (node, (left, right)) in &diff {
+            assert_eq!(*node, Poseidon_hash_2(*left, *right, &p_params, &SboxType::Inverse));
+        }
+
+        // From the empty tree, only the non-empty subtrees are sent
+        let diff = tree.diff(&empty_root);
+        assert!(diff.len() > tree.proof_len());
+        assert!(diff.len() < 9 * tree.proof_len());
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        let p_params = PoseidonParams::new(6, 4, 4, 140);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(8, &p_params);
+        tree.update(Scalar::from(3u32), Scalar::from(30u32));
+        let root = tree.raw_root();
+
+        // 259 has bit 8 set and would be taken for 3
+        let large = Scalar::from(256u32 + 3);
+        assert_eq!(tree.try_get(large, &mut None), Err(GadgetError::IndexOutOfRange { depth: 8 }));
+        assert_eq!(tree.try_update(large, Scalar::one()), Err(GadgetError::IndexOutOfRange { depth: 8 }));
+        assert_eq!(tree.raw_root(), root);
+        assert_eq!(tree.get(Scalar::from(3u32), &mut None), Scalar::from(30u32));
+
+        // The proof of 3 is not accepted for 259
+        let mut proof = Some(Vec::new());
+        tree.get(Scalar::from(3u32), &mut proof);
+        let proof = proof.unwrap();
+        assert!(tree.verify_proof(Scalar::from(3u32), Scalar::from(30u32), &proof, None));
+        assert!(!tree.verify_proof(large, Scalar::from(30u32), &proof, None));
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 13, 1);
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        assert_eq!(gen_proof_of_leaf_membership(&tree, large, &mut test_rng, b"VSMTRange", &pc_gens, &bp_gens).err(),
+                   Some(GadgetError::IndexOutOfRange { depth: 8 }));
+        assert_eq!(tree.prove_membership_with_range(large, 32, &mut test_rng, b"VSMTRange", &pc_gens, &bp_gens).err(),
+                   Some(GadgetError::IndexOutOfRange { depth: 8 }));
+
+        assert_eq!(tree.try_get(Scalar::from(255u32), &mut None), Ok(Scalar::zero()));
+        assert!(tree.try_update(Scalar::from(255u32), Scalar::one()).is_ok());
+        assert_eq!(tree.try_get(Scalar::from(255u32), &mut None), Ok(Scalar::one()));
+
+        // Positions of hashed keys are truncated so any key is accepted
+        let mut hashed_tree = VanillaSparseMerkleTree::new_hashed_keys(&p_params);
+        let key = -Scalar::one();
+        assert!(hashed_tree.try_update(key, Scalar::one()).is_ok());
+        assert_eq!(hashed_tree.try_get(key, &mut None), Ok(hashed_tree.encode_leaf(&key, Scalar::one())));
+    }
+
+    #[test]
+    fn test_hashed_keys_collision() {
+        let p_params = PoseidonParams::new(6, 4, 4, 140);
+        // Small tree so that keys collide
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(4, &p_params);
+        tree.hashed_keys = true;
+        tree.leaf_encoding = LeafEncoding::Hashed;
+
+        let slot = |k: u32| get_bits(&tree.key_position(&Scalar::from(k)), 4);
+        let first = 1u32;
+        let second = (2..100u32).find(|k| slot(*k) == slot(first)).unwrap();
+        let (first, second) = (Scalar::from(first), Scalar::from(second));
+
+        tree.update(first, Scalar::from(10u32));
+        let root = tree.raw_root();
+        assert_eq!(tree.try_update(second, Scalar::from(20u32)), Err(GadgetError::KeyCollision));
+        assert_eq!(tree.raw_root(), root);
+        assert_eq!(tree.get_index_at_position(&tree.key_position(&first)), Some((first, Scalar::from(10u32))));
+        let mut proof = Some(Vec::new());
+        tree.get(first, &mut proof);
+        assert!(tree.verify_proof(first, Scalar::from(10u32), &proof.clone().unwrap(), None));
+        // The leaf commits to the key so the proof is not one for the other key
+        assert!(!tree.verify_proof(second, Scalar::from(10u32), &proof.unwrap(), None));
+
+        // Same key can be updated
+        assert!(tree.try_update(first, Scalar::from(11u32)).is_ok());
+        assert_eq!(tree.get_index_at_position(&tree.key_position(&first)), Some((first, Scalar::from(11u32))));
+    }
+
+    #[test]
+    fn test_rehash() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let old_params = PoseidonParams::new(6, 4, 4, 140);
+        let new_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 12;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &old_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i * 7), Scalar::from(i + 100));
+        }
+
+        let new_tree = tree.rehash(&new_params);
+        assert_eq!(new_tree.depth, depth);
+        assert_ne!(new_tree.raw_root(), tree.raw_root());
+        assert!(new_tree.verify_integrity().is_ok());
+
+        // Same as building the tree with the new parameters
+        let mut expected = VanillaSparseMerkleTree::new_with_depth(depth, &new_params);
+        for i in 1..=10u32 {
+            expected.update(Scalar::from(i * 7), Scalar::from(i + 100));
+        }
+        assert_eq!(new_tree.raw_root(), expected.raw_root());
+
+        let k = Scalar::from(21u32);
+        assert_eq!(new_tree.get(k, &mut None), Scalar::from(103u32));
+        let pc_gens = PedersenGens::default();
+        let bp_gens = recommended_bp_gens(depth, &new_params);
+        let (proof, commitments) = gen_proof_of_leaf_membership(&new_tree, k, &mut test_rng, b"VSMTRehash", &pc_gens, &bp_gens).unwrap();
+        assert!(verify_proof_of_leaf_membership(depth, &new_tree.raw_root(), &new_params, proof.clone(), commitments.clone(),
+                                                b"VSMTRehash", &pc_gens, &bp_gens).is_ok());
+        // Not a proof for the old root
+        assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &new_params, proof, commitments,
+                                                b"VSMTRehash", &pc_gens, &bp_gens).is_err());
+
+        // Hashed keys are placed at positions computed with the new parameters
+        let mut hashed_tree = VanillaSparseMerkleTree::new_hashed_keys(&old_params);
+        let mut expected = VanillaSparseMerkleTree::new_hashed_keys(&new_params);
+        for i in 1..=5u32 {
+            hashed_tree.update(Scalar::from(i), Scalar::from(i + 100));
+            expected.update(Scalar::from(i), Scalar::from(i + 100));
+        }
+        let new_hashed_tree = hashed_tree.rehash(&new_params);
+        assert_eq!(new_hashed_tree.raw_root(), expected.raw_root());
+        assert_eq!(new_hashed_tree.get(Scalar::from(3u32), &mut None),
+                   expected.encode_leaf(&Scalar::from(3u32), Scalar::from(103u32)));
+
+        // Hashed leaves keep the old leaf hash parameters so leaves set after rehashing are encoded like the copied ones
+        let mut encoded_tree = VanillaSparseMerkleTree::new_with_leaf_encoding(&old_params, LeafEncoding::Hashed);
+        for i in 1..=5u32 {
+            encoded_tree.update(Scalar::from(i), Scalar::from(i + 100));
+        }
+        let mut new_encoded_tree = encoded_tree.rehash(&new_params);
+        let k = Scalar::from(3u32);
+        assert_eq!(new_encoded_tree.get(k, &mut None), new_encoded_tree.encode_leaf(&k, Scalar::from(103u32)));
+        let new_k = Scalar::from(6u32);
+        new_encoded_tree.update(new_k, Scalar::from(106u32));
+        assert!(new_encoded_tree.verify_integrity().is_ok());
+        for (idx, val) in vec![(k, Scalar::from(103u32)), (new_k, Scalar::from(106u32))] {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            new_encoded_tree.get(idx, &mut merkle_proof);
+            assert!(new_encoded_tree.verify_proof(idx, val, &merkle_proof.unwrap(), None));
+        }
+        let depth = new_encoded_tree.proof_len();
+        let bp_gens = recommended_bp_gens(depth, &new_params);
+        for idx in vec![k, new_k] {
+            let (proof, commitments) = gen_proof_of_leaf_membership(&new_encoded_tree, idx, &mut test_rng, b"VSMTRehash",
+                                                                    &pc_gens, &bp_gens).unwrap();
+            assert!(verify_proof_of_leaf_membership(depth, &new_encoded_tree.raw_root(), &new_params, proof, commitments,
+                                                    b"VSMTRehash", &pc_gens, &bp_gens).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree_low = VanillaSparseMerkleTree::new_with_depth(8, &p_params);
+        let mut tree_high = VanillaSparseMerkleTree::new_with_depth(8, &p_params);
+        let mut tree_all = VanillaSparseMerkleTree::new_with_depth(8, &p_params);
+
+        for i in 0..16u32 {
+            let s = Scalar::from(i);
+            let v = Scalar::from(i + 100);
+            if i < 8 {
+                tree_low.update(s, v);
+            } else {
+                tree_high.update(s, v);
+            }
+            tree_all.update(s, v);
+        }
+
+        let root = tree_low.merge(&tree_high).unwrap();
+        assert_eq!(root, tree_all.root);
+        assert_eq!(tree_low, tree_all);
+        for i in 0..16u32 {
+            let s = Scalar::from(i);
+            let mut proof = Some(Vec::<Scalar>::new());
+            assert_eq!(tree_low.get(s, &mut proof), Scalar::from(i + 100));
+            assert!(tree_low.verify_proof(s, Scalar::from(i + 100), &proof.unwrap(), None));
+        }
+
+        // Merging a tree into itself or with an empty tree does not change it
+        assert_eq!(tree_low.merge(&tree_high).unwrap(), root);
+        assert_eq!(tree_low.merge(&VanillaSparseMerkleTree::new_with_depth(8, &p_params)).unwrap(), root);
+
+        // Conflicting values leave the tree unchanged
+        let mut conflicting = VanillaSparseMerkleTree::new_with_depth(8, &p_params);
+        conflicting.update(Scalar::from(20u32), Scalar::one());
+        conflicting.update(Scalar::from(3u32), Scalar::one());
+        assert_eq!(tree_low.merge(&conflicting), Err(GadgetError::ConflictingLeaf));
+        assert_eq!(tree_low.root, root);
+
+        assert_eq!(tree_low.merge(&VanillaSparseMerkleTree::new_with_depth(9, &p_params)), Err(GadgetError::InvalidDepth(9)));
+        let other_params = PoseidonParams::new(width, full_b, full_e, 120);
+        assert!(tree_low.merge(&VanillaSparseMerkleTree::new_with_depth(8, &other_params)).is_err());
+    }
+
+    #[test]
+    fn test_get_cached() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let check = |tree: &mut VanillaSparseMerkleTree| {
+            for i in 0..12 {
+                let s = Scalar::from(i as u32);
+                let mut proof = Some(Vec::<Scalar>::new());
+                let val = tree.get(s, &mut proof);
+                let proof_vec = proof.unwrap();
+                // Twice so that the second get is from the cache
+                for _ in 0..2 {
+                    let mut cached_proof = Some(Vec::<Scalar>::new());
+                    assert_eq!(tree.get_cached(s, &mut cached_proof), val);
+                    assert_eq!(cached_proof.unwrap(), proof_vec);
+                    assert!(tree.verify_proof(s, val, &proof_vec, None));
+                }
+            }
+        };
+        check(&mut tree);
+        assert!(!tree.path_cache.is_empty());
+
+        // Updating invalidates the cache, even for indices not updated whose proofs change
+        tree.update(Scalar::from(3u32), Scalar::from(30u32));
+        assert!(tree.path_cache.is_empty());
+        check(&mut tree);
+        assert_eq!(tree.get_cached(Scalar::from(3u32), &mut None), Scalar::from(30u32));
+    }
+
+    #[test]
+    fn test_update_returning_old() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        let idx = Scalar::from(7u32);
+        let (root, old) = tree.update_returning_old(idx, Scalar::from(100u32));
+        assert_eq!(old, Scalar::zero());
+        assert_eq!(root, tree.root);
+
+        let mut prev = Scalar::from(100u32);
+        for i in 1..5 {
+            let val = Scalar::from(100u32 + i as u32);
+            let (root, old) = tree.update_returning_old(idx, val);
+            assert_eq!(old, prev);
+            assert_eq!(root, tree.root);
+            assert_eq!(tree.get(idx, &mut None), val);
+            prev = val;
+        }
+
+        // Other indices are unaffected
+        let (_, old) = tree.update_returning_old(Scalar::from(8u32), Scalar::one());
+        assert_eq!(old, Scalar::zero());
+    }
+
+    #[test]
+    fn test_update_bytes() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        let values: Vec<Vec<u8>> = (1..10).map(|i| vec![i as u8; 64]).collect();
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update_bytes(s, &values[i-1]);
+        }
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            let mut proof = Some(Vec::<Scalar>::new());
+            assert_eq!(tree.leaf_from_bytes(&values[i-1]), tree.get(s, &mut proof));
+            let proof_vec = proof.unwrap();
+            assert!(tree.verify_proof_bytes(s, &values[i-1], &proof_vec, None));
+            assert!(!tree.verify_proof_bytes(s, &values[i-1][1..], &proof_vec, None));
+        }
+    }
+
+    #[test]
+    fn test_bytes_leaf_verif_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        let value: Vec<u8> = (0..64u8).collect();
+        let idx = Scalar::from(7u32);
+        tree.update_bytes(idx, &value);
+        tree.update_bytes(Scalar::from(8u32), &value[1..]);
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |bytes: &[u8], num_bytes: usize| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(idx, &mut merkle_proof);
+            let merkle_proof = merkle_proof.unwrap();
+            let chunks = bytes_to_scalars(bytes);
+
+            prove_and_verify!(b"VSMT_bytes_leaf", &p_params, &bp_gens, |cs, inputs, statics| {
+                let chunks = inputs.values(cs, &chunks);
+                let bits = inputs.bits(cs, &idx, depth);
+                let nodes = inputs.nodes(cs, &merkle_proof);
+                bytes_leaf_verif_gadget(cs, depth, &tree.raw_root(), num_bytes, chunks, bits, nodes, statics, &p_params,
+                                        &p_params)
+            })
+        };
+
+        assert!(prove(&value, value.len()));
+        let mut other = value.clone();
+        other[40] += 1;
+        assert!(!prove(&other, other.len()));
+        // Same scalars but a different length
+        let mut padded = value.clone();
+        padded.push(0);
+        assert!(!prove(&padded, padded.len()));
+        // Bytes of another leaf
+        assert!(!prove(&value[1..], value.len() - 1));
+
+        // Number of scalars must match the number of bytes
+        let mut prover_transcript = Transcript::new(b"VSMT_bytes_leaf");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        let (_, var) = prover.commit(Scalar::one(), Scalar::one());
+        let chunk = AllocatedScalar { variable: var, assignment: Some(Scalar::one()) };
+        assert!(Poseidon_hash_bytes_constraints(&mut prover, 64, vec![chunk.variable.into()], &p_params,
+                                                &SboxType::Inverse).is_err());
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+        tree.update(Scalar::from(5u32), Scalar::from(10u32));
+
+        let root = tree.root();
+        assert_eq!(root.0, tree.raw_root());
+        assert_eq!(Some(root), MerkleRoot::from_bytes(root.to_bytes()));
+        assert_eq!(root.0, get_scalar_from_hex(&format!("{}", root)).unwrap());
+
+        // Non canonical bytes
+        assert_eq!(None, MerkleRoot::from_bytes([255u8; 32]));
+    }
+
+    #[test]
+    fn test_VSMT_Verif() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        // Generate the MiMC round constants
+        /*let constants = (0..MIMC_ROUNDS).map(|_| Scalar::random(&mut test_rng)).collect::<Vec<_>>();
+        let mut tree = VanillaSparseMerkleTree::new(&constants);*/
+
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let mut merkle_proof_vec = Vec::<Scalar>::new();
+        let mut merkle_proof = Some(merkle_proof_vec);
+        let k =  Scalar::from(7u32);
+        assert_eq!(k, tree.get(k, &mut merkle_proof));
+        merkle_proof_vec = merkle_proof.unwrap();
+        assert!(tree.verify_proof(k, k, &merkle_proof_vec, None));
+        assert!(tree.verify_proof(k, k, &merkle_proof_vec, Some(&tree.root())));
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = recommended_bp_gens(tree.proof_len(), &p_params);
+
+        let (proof, commitments) = {
+            let mut prover_transcript = Transcript::new(b"VSMT");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+            let (com_leaf, var_leaf) = prover.commit(k, Scalar::random(&mut test_rng));
+            let leaf_alloc_scalar = AllocatedScalar {
+                variable: var_leaf,
+                assignment: Some(k),
+            };
+
+            let mut leaf_index_comms = vec![];
+            let mut leaf_index_vars = vec![];
+            let mut leaf_index_alloc_scalars = vec![];
+            for b in get_bits(&k, TreeDepth).iter().take(tree.proof_len()) {
+                let val: Scalar = Scalar::from(*b as u8);
+                let (c, v) = prover.commit(val.clone(), Scalar::random(&mut test_rng));
+                leaf_index_comms.push(c);
+                leaf_index_vars.push(v);
+                leaf_index_alloc_scalars.push(AllocatedScalar {
+                    variable: v,
+                    assignment: Some(val),
+                });
+            }
+
+            let mut proof_comms = vec![];
+            let mut proof_vars = vec![];
+            let mut proof_alloc_scalars = vec![];
+            for p in merkle_proof_vec.iter().rev() {
+                let (c, v) = prover.commit(*p, Scalar::random(&mut test_rng));
+                proof_comms.push(c);
+                proof_vars.push(v);
+                proof_alloc_scalars.push(AllocatedScalar {
+                    variable: v,
+                    assignment: Some(*p),
+                });
+            }
+
+            let num_statics = 4;
+            let statics = allocate_statics_for_prover(&mut prover, num_statics);
+
+            assert!(vanilla_merkle_merkle_tree_verif_gadget(
+                &mut prover,
+                tree.proof_len(),
+                &tree.raw_root(),
+                leaf_alloc_scalar,
+                leaf_index_alloc_scalars,
+                proof_alloc_scalars,
+                statics,
+                &p_params).is_ok());
+
+            let proof = prover.prove(&bp_gens).unwrap();
+
+            (proof, (com_leaf, leaf_index_comms, proof_comms))
+        };
+
+        let mut verifier_transcript = Transcript::new(b"VSMT");
+        let mut verifier = Verifier::new(&mut verifier_transcript);
+        let var_leaf = verifier.commit(commitments.0);
+        let leaf_alloc_scalar = AllocatedScalar {
+            variable: var_leaf,
+            assignment: None,
+        };
+
+        let mut leaf_index_alloc_scalars = vec![];
+        for l in commitments.1 {
+            let v = verifier.commit(l);
+            leaf_index_alloc_scalars.push(AllocatedScalar {
+                variable: v,
+                assignment: None,
+            });
+        }
+
+        let mut proof_alloc_scalars = vec![];
+        for p in commitments.2 {
+            let v = verifier.commit(p);
+            proof_alloc_scalars.push(AllocatedScalar {
+                variable: v,
+                assignment: None,
+            });
+        }
+
+        let num_statics = 4;
+        let statics = allocate_statics_for_verifier(&mut verifier, num_statics, &pc_gens);
+
+        assert!(vanilla_merkle_merkle_tree_verif_gadget(
+            &mut verifier,
+            tree.proof_len(),
+            &tree.raw_root(),
+            leaf_alloc_scalar,
+            leaf_index_alloc_scalars,
+            proof_alloc_scalars,
+            statics,
+            &p_params).is_ok());
+
+        assert!(verifier.verify(&proof, &pc_gens, &bp_gens).is_ok());
+    }
+
+    #[test]
+    fn test_recommended_bp_gens() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let pc_gens = PedersenGens::default();
+        let depth = 8;
+        let mut capacities = vec![];
+        for p_params in [PoseidonParams::new(6, 8, 8, 105), PoseidonParams::new(6, 4, 4, 140)].iter() {
+            let bp_gens = recommended_bp_gens(depth, p_params);
+            assert!(bp_gens.gens_capacity.is_power_of_two());
+            capacities.push(bp_gens.gens_capacity);
+
+            let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, p_params);
+            for i in 1..=10 {
+                let s = Scalar::from(i as u32);
+                tree.update(s, s);
+            }
+            let (proof, commitments) = gen_proof_of_leaf_membership(&tree, Scalar::from(7u32), &mut test_rng, b"VSMTGens", &pc_gens, &bp_gens).unwrap();
+            assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), p_params, proof, commitments, b"VSMTGens", &pc_gens, &bp_gens).is_ok());
+        }
+        println!("Generators for depth {}: {:?}", depth, capacities);
+
+        // More levels need at least as many generators
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        assert!(recommended_bp_gens(32, &p_params).gens_capacity >= recommended_bp_gens(depth, &p_params).gens_capacity);
+    }
+
+    #[test]
+    fn test_merkle_proof_builder() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        let k = Scalar::from(7u32);
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 13, 1);
+
+        // Commitments made one by one
+        let manual_comms = {
+            let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+            let mut prover_transcript = Transcript::new(b"VSMT_builder");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+            let (com_leaf, _) = prover.commit(leaf, Scalar::random(&mut test_rng));
+            let mut comms = vec![com_leaf];
+            for b in get_bits(&k, tree.proof_len()).iter() {
+                let (c, _) = prover.commit(Scalar::from(*b as u8), Scalar::random(&mut test_rng));
+                comms.push(c);
+            }
+            for p in merkle_proof_vec.iter().rev() {
+                let (c, _) = prover.commit(*p, Scalar::random(&mut test_rng));
+                comms.push(c);
+            }
+            comms
+        };
+
+        let (proof, commitments) = {
+            let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+            let mut prover_transcript = Transcript::new(b"VSMT_builder");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+            let committed = MerkleProofBuilder::new(k, leaf, &merkle_proof_vec).commit(&mut prover, &mut test_rng);
+            assert_eq!(committed.commitments, manual_comms);
+            assert_eq!(committed.leaf_index_bits.len(), tree.proof_len());
+            assert_eq!(committed.proof_nodes[0].assignment, Some(merkle_proof_vec[tree.proof_len() - 1]));
+
+            let num_statics = num_required_statics(&p_params);
+            let statics = allocate_statics_for_prover(&mut prover, num_statics);
+            assert!(vanilla_merkle_merkle_tree_verif_gadget(&mut prover, tree.proof_len(), &tree.raw_root(), committed.leaf,
+                                                             committed.leaf_index_bits, committed.proof_nodes, statics,
+                                                             &p_params).is_ok());
+            (prover.prove(&bp_gens).unwrap(), committed.commitments)
+        };
+
+        let mut verifier_transcript = Transcript::new(b"VSMT_builder");
+        let mut verifier = Verifier::new(&mut verifier_transcript);
+        assert!(MerkleProofBuilder::verifier_view(&mut verifier, tree.proof_len() + 1, &commitments).is_err());
+        let committed = MerkleProofBuilder::verifier_view(&mut verifier, tree.proof_len(), &commitments).unwrap();
+        assert_eq!(committed.leaf.assignment, None);
+        let num_statics = num_required_statics(&p_params);
+        let statics = allocate_statics_for_verifier(&mut verifier, num_statics, &pc_gens);
+        assert!(vanilla_merkle_merkle_tree_verif_gadget(&mut verifier, tree.proof_len(), &tree.raw_root(), committed.leaf,
+                                                         committed.leaf_index_bits, committed.proof_nodes, statics,
+                                                         &p_params).is_ok());
+        assert!(verifier.verify(&proof, &pc_gens, &bp_gens).is_ok());
+    }
+
+    #[test]
+    fn test_merkle_prover_phases() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        let k = Scalar::from(7u32);
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let merkle_proof = merkle_proof.unwrap();
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = recommended_bp_gens(tree.proof_len(), &p_params);
+        let label = b"VSMTPhases";
+
+        let mut prover_transcript = Transcript::new(label);
+        let mut prover = MerkleProver::new(&pc_gens, &mut prover_transcript, tree.raw_root(), &p_params);
+        let commitments = prover.commit_phase(k, leaf, &merkle_proof, &mut test_rng).unwrap();
+        assert_eq!(commitments.len(), 1 + 2 * tree.proof_len());
+        assert_eq!(prover.commit_phase(k, leaf, &merkle_proof, &mut test_rng), Err(GadgetError::AlreadyCommitted));
+        // Commitments are fixed before the proof is created
+        let proof = prover.prove_phase(&bp_gens).unwrap();
+
+        assert!(verify_proof_of_leaf_membership(tree.proof_len(), &tree.raw_root(), &p_params, proof.clone(), commitments.clone(),
+                                                label, &pc_gens, &bp_gens).is_ok());
+        assert!(verify_proof_of_leaf_membership(tree.proof_len(), &tree.raw_root(), &p_params, proof, commitments,
+                                                b"OtherLabel", &pc_gens, &bp_gens).is_err());
+
+        let mut prover_transcript = Transcript::new(label);
+        let prover = MerkleProver::new(&pc_gens, &mut prover_transcript, tree.raw_root(), &p_params);
+        assert_eq!(prover.prove_phase(&bp_gens).err(), Some(GadgetError::MissingAssignment));
+    }
+
+    #[test]
+    fn test_recommend_tree_shape() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let n = 1u64 << 32;
+        let m_2 = num_membership_multipliers(32, &p_params);
+        let m_4 = gadget_vsmt_4::num_membership_multipliers_4(&p_params);
+        let m_8 = gadget_vsmt_8::num_membership_multipliers_8(gadget_vsmt_8::TreeDepth, &p_params);
+        println!("Multipliers for 2^32 leaves, 2-ary: {}, 4-ary: {}, 8-ary: {}", m_2, m_4, m_8);
+
+        // Binary tree when the budget allows
+        assert_eq!(recommend_tree_shape(n, m_2, &p_params), Some((32, 2)));
+        assert_eq!(recommend_tree_shape(1000, m_2, &p_params), Some((10, 2)));
+        assert_eq!(recommend_tree_shape(1, m_2, &p_params), Some((1, 2)));
+
+        // Higher arity when the budget is tight and there are many leaves
+        assert!(m_4 < m_2);
+        assert_eq!(recommend_tree_shape(n, m_2 - 1, &p_params), Some((gadget_vsmt_4::TreeDepth, 4)));
+        assert_eq!(recommend_tree_shape(n, m_4, &p_params), Some((gadget_vsmt_4::TreeDepth, 4)));
+
+        // Neither the binary nor the 4-ary tree has room for more than 2^32 leaves, whatever the budget
+        assert_eq!(recommend_tree_shape(n + 1, usize::max_value(), &p_params), Some((gadget_vsmt_8::TreeDepth, 8)));
+        let n_8 = 1u64 << gadget_vsmt_8::LeafIndexBits;
+        assert_eq!(recommend_tree_shape(n_8, usize::max_value(), &p_params), Some((gadget_vsmt_8::TreeDepth, 8)));
+        assert_eq!(recommend_tree_shape(n_8 + 1, usize::max_value(), &p_params), None);
+
+        assert_eq!(recommend_tree_shape(n, 10, &p_params), None);
+        assert_eq!(recommend_tree_shape(u64::max_value(), m_8, &p_params), None);
+    }
+
+    #[test]
+    fn test_num_required_statics() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let pc_gens = PedersenGens::default();
+        let depth = 8;
+        for width in [5, 6, 7].iter() {
+            let p_params = PoseidonParams::new(*width, 4, 4, 60);
+            assert_eq!(num_required_statics(&p_params), width - 2);
+
+            let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+            for i in 1..=10 {
+                let s = Scalar::from(i as u32);
+                tree.update(s, s);
+            }
+            let bp_gens = recommended_bp_gens(depth, &p_params);
+            let (proof, commitments) = gen_proof_of_leaf_membership(&tree, Scalar::from(7u32), &mut test_rng, b"VSMTStatics", &pc_gens, &bp_gens).unwrap();
+            assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &p_params, proof, commitments, b"VSMTStatics", &pc_gens, &bp_gens).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_membership_with_range() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let range_bits = 32;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i), Scalar::from(i * 1000));
+        }
+        // Too large and negative amounts
+        tree.update(Scalar::from(11u32), Scalar::from(1u64 << 32));
+        tree.update(Scalar::from(12u32), -Scalar::from(5u32));
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |idx: u32, test_rng: &mut StdRng| -> bool {
+            match tree.prove_membership_with_range(Scalar::from(idx), range_bits, test_rng, DefaultTranscriptLabel,
+                                                   &pc_gens, &bp_gens) {
+                Ok((proof, commitments)) => verify_membership_with_range(depth, &tree.raw_root(), &p_params, range_bits,
+                                                                         proof, commitments, DefaultTranscriptLabel,
+                                                                         &pc_gens, &bp_gens).is_ok(),
+                Err(_) => false
+            }
+        };
+
+        assert!(prove(3, &mut test_rng));
+        assert!(prove(10, &mut test_rng));
+        assert!(!prove(11, &mut test_rng));
+        assert!(!prove(12, &mut test_rng));
+
+        // Range is part of the statement
+        let (proof, commitments) = tree.prove_membership_with_range(Scalar::from(3u32), range_bits, &mut test_rng,
+                                                                    DefaultTranscriptLabel, &pc_gens, &bp_gens).unwrap();
+        assert!(verify_membership_with_range(depth, &tree.raw_root(), &p_params, 8, proof, commitments,
+                                             DefaultTranscriptLabel, &pc_gens, &bp_gens).is_err());
+        assert!(tree.prove_membership_with_range(Scalar::from(3u32), 65, &mut test_rng, DefaultTranscriptLabel,
+                                                 &pc_gens, &bp_gens).is_err());
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize_witness() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i), Scalar::from(i));
+        }
+        let k = Scalar::from(7u32);
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let pc_gens = PedersenGens::default();
+        let bp_gens = recommended_bp_gens(depth, &p_params);
+
+        // The same steps `gen_proof_of_leaf_membership` takes to add the constraints
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let merkle_proof = merkle_proof.unwrap();
+        let mut transcript = Transcript::new(DefaultTranscriptLabel);
+        let mut prover = Prover::new(&pc_gens, &mut transcript);
+        let mut committed = MerkleProofBuilder::new(tree.key_position(&k), leaf, &merkle_proof)
+            .commit(&mut prover, &mut test_rng);
+        let comms = committed.commitments.clone();
+        committed_path_constraints(&mut prover, &tree.raw_root(), &p_params, &mut committed).unwrap();
+        assert!(committed.leaf.assignment.is_none());
+        assert!(committed.leaf_index_bits.iter().chain(committed.proof_nodes.iter()).all(|v| v.assignment.is_none()));
+        assert_eq!(committed.commitments, comms);
+
+        // Proofs are unaffected by wiping the witness after the constraints are added
+        let proof = prover.prove(&bp_gens).unwrap();
+        assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &p_params, proof, comms,
+                                                DefaultTranscriptLabel, &pc_gens, &bp_gens).is_ok());
+
+        let mut secret = Secret::new(Scalar::from(42u32));
+        secret.zeroize();
+        assert_eq!(*secret, Scalar::zero());
+    }
+
+    #[test]
+    fn test_proof_bound_to_public_signal() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i), Scalar::from(i));
+        }
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let pc_gens = PedersenGens::default();
+        let bp_gens = recommended_bp_gens(depth, &p_params);
+
+        let signal_a = Scalar::from(1111u32);
+        let signal_b = Scalar::from(2222u32);
+        let (proof, commitments) = gen_proof_of_leaf_membership_with_signal(&tree, Scalar::from(7u32), &signal_a,
+                                                                            &mut test_rng, DefaultTranscriptLabel,
+                                                                            &pc_gens, &bp_gens).unwrap();
+
+        assert!(verify_proof_of_leaf_membership_with_signal(depth, &tree.raw_root(), &p_params, &signal_a, proof.clone(),
+                                                            commitments.clone(), DefaultTranscriptLabel, &pc_gens,
+                                                            &bp_gens).is_ok());
+        assert!(verify_proof_of_leaf_membership_with_signal(depth, &tree.raw_root(), &p_params, &signal_b, proof.clone(),
+                                                            commitments.clone(), DefaultTranscriptLabel, &pc_gens,
+                                                            &bp_gens).is_err());
+        // Nor does it verify as a proof without a signal
+        assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &p_params, proof, commitments,
+                                                DefaultTranscriptLabel, &pc_gens, &bp_gens).is_err());
+    }
+
+    #[test]
+    fn test_transcript_label_domain_separation() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let pc_gens = PedersenGens::default();
+        let depth = 8;
+        let p_params = PoseidonParams::new(6, 4, 4, 60);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        let bp_gens = recommended_bp_gens(depth, &p_params);
+
+        let (proof, commitments) = gen_proof_of_leaf_membership(&tree, Scalar::from(7u32), &mut test_rng, DefaultTranscriptLabel, &pc_gens, &bp_gens).unwrap();
+        assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &p_params, proof.clone(), commitments.clone(), b"VSMT", &pc_gens, &bp_gens).is_ok());
+
+        // Proof of one protocol does not verify in another
+        let (proof_a, commitments_a) = gen_proof_of_leaf_membership(&tree, Scalar::from(7u32), &mut test_rng, b"ProtocolA", &pc_gens, &bp_gens).unwrap();
+        assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &p_params, proof_a.clone(), commitments_a.clone(), b"ProtocolA", &pc_gens, &bp_gens).is_ok());
+        assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &p_params, proof_a, commitments_a, b"ProtocolB", &pc_gens, &bp_gens).is_err());
+        assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &p_params, proof, commitments, b"ProtocolB", &pc_gens, &bp_gens).is_err());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_spans() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, AtomicU64, Ordering};
+        use tracing::{Subscriber, Metadata, Event};
+        use tracing::span::{Attributes, Id, Record};
+
+        /// Counts the entered spans
+        struct CountingSubscriber {
+            next_id: AtomicU64,
+            entered: Arc<AtomicUsize>
+        }
+
+        impl Subscriber for CountingSubscriber {
+            fn enabled(&self, _: &Metadata) -> bool { true }
+            fn new_span(&self, _: &Attributes) -> Id { Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst)) }
+            fn record(&self, _: &Id, _: &Record) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event) {}
+            fn enter(&self, _: &Id) { self.entered.fetch_add(1, Ordering::SeqCst); }
+            fn exit(&self, _: &Id) {}
+        }
+
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(32, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        let k = Scalar::from(7u32);
+
+        let entered = Arc::new(AtomicUsize::new(0));
+        let subscriber = CountingSubscriber { next_id: AtomicU64::new(1), entered: entered.clone() };
+        tracing::subscriber::with_default(subscriber, || {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            let leaf = tree.get(k, &mut merkle_proof);
+            assert_eq!(entered.load(Ordering::SeqCst), 1);
+
+            let pc_gens = PedersenGens::default();
+            let mut prover_transcript = Transcript::new(b"VSMTTracing");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+            let committed = MerkleProofBuilder::new(k, leaf, &merkle_proof.unwrap()).commit(&mut prover, &mut test_rng);
+            let statics = allocate_statics_for_prover(&mut prover, num_required_statics(&p_params));
+            vanilla_merkle_merkle_tree_verif_gadget(&mut prover, tree.proof_len(), &tree.raw_root(), committed.leaf,
+                                                    committed.leaf_index_bits, committed.proof_nodes, statics,
+                                                    &p_params).unwrap();
+            // A span for each level
+            assert_eq!(entered.load(Ordering::SeqCst), 1 + 32);
+        });
+    }
+
+    #[test]
+    fn test_merkle_proof_size_bytes() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+        assert_eq!(tree.depth, 32);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+        let (proof, _) = gen_proof_of_leaf_membership(&tree, Scalar::from(7u32), &mut test_rng, b"VSMTSize", &pc_gens, &bp_gens).unwrap();
+
+        let size = merkle_proof_size_bytes(&proof);
+        println!("Proof size for tree of depth {} is {} bytes", tree.proof_len(), size);
+        // The multipliers are padded to a power of 2, n, and the inner product proof has 2 points for each of the
+        // log2(n) rounds. The rest of the proof is 14 points and scalars of the constraint system and the final 2
+        // scalars of the inner product proof.
+        let log_n = num_membership_multipliers(tree.proof_len(), &p_params).next_power_of_two().trailing_zeros() as usize;
+        assert!(size >= 2 * log_n * 32);
+        assert!(size <= (2 * log_n + 16) * 32);
+    }
+
+    /// Tree of the given depth with `count` random leaves generated from `seed`
+    fn tree_from_seed(seed: [u8; 32], depth: usize, count: usize, p_params: &PoseidonParams) -> VanillaSparseMerkleTree {
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, p_params);
+        for (k, v) in random_key_values(&mut rng, count, depth) {
+            tree.update(k, v);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_random_key_values_seeded() {
+        let p_params = PoseidonParams::new(6, 4, 4, 140);
+        let tree_1 = tree_from_seed([7u8; 32], 32, 50, &p_params);
+        let tree_2 = tree_from_seed([7u8; 32], 32, 50, &p_params);
+        let tree_3 = tree_from_seed([8u8; 32], 32, 50, &p_params);
+        assert_eq!(tree_1.raw_root(), tree_2.raw_root());
+        assert_ne!(tree_1.raw_root(), tree_3.raw_root());
+    }
+
+    #[test]
+    fn test_bench_merkle_membership() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let results: Vec<BenchResult> = [2, 4, 6].iter().map(|d| bench_merkle_membership(*d, &p_params, &mut test_rng).unwrap()).collect();
+        for r in &results {
+            println!("{:?}", r);
+            assert!(r.proof_size_bytes > 0);
+        }
+
+        assert_eq!(results[1].depth, 4);
+        // Each level adds the same number of multipliers
+        assert_eq!(results[2].num_multipliers - results[1].num_multipliers, results[1].num_multipliers - results[0].num_multipliers);
+    }
+
+    #[test]
+    fn test_poseidon_hiding_membership_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let mut rng: StdRng = SeedableRng::from_seed([7u8; 32]);
+        let k = Scalar::from(7u32);
+        let value = Scalar::from(1000u32);
+        let blinding = Scalar::random(&mut rng);
+        let leaf = tree.leaf_commitment(value, blinding);
+        tree.update(k, leaf);
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
+
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |value: Scalar, blinding: Scalar| -> bool {
+            prove_and_verify!(b"VSMT_poseidon_hiding", &p_params, &bp_gens, |cs, inputs, statics| {
+                let value = inputs.value(cs, value);
+                let blinding = inputs.value(cs, blinding);
+                // The leaf is not committed, only its index bits and proof nodes
+                let bits = inputs.bits(cs, &k, tree.proof_len());
+                let nodes = inputs.nodes(cs, &merkle_proof_vec);
+                poseidon_hiding_membership_gadget(cs, tree.proof_len(), &tree.raw_root(), value, blinding, bits, nodes,
+                                                  statics, &p_params)
+            })
+        };
+
+        assert!(prove(value, blinding));
+        assert!(!prove(value + Scalar::one(), blinding));
+        assert!(!prove(value, blinding + Scalar::one()));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_poseidon_commitment_leaf() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        // Leaf is a commitment to the value
+        let k =  Scalar::from(7u32);
+        let value = Scalar::from(1000u32);
+        let blinding = Scalar::random(&mut test_rng);
+        let leaf = poseidon_commit(value, blinding, &p_params);
+        tree.update(k, leaf);
+
+        let mut merkle_proof_vec = Vec::<Scalar>::new();
+        let mut merkle_proof = Some(merkle_proof_vec);
+        assert_eq!(leaf, tree.get(k, &mut merkle_proof));
+        merkle_proof_vec = merkle_proof.unwrap();
+
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        assert!(prove_and_verify!(b"VSMT_commitment", &p_params, &bp_gens, |cs, inputs, statics| {
+            let value = inputs.value(cs, value);
+            let blinding = inputs.value(cs, blinding);
+            let bits = inputs.bits(cs, &k, tree.proof_len());
+            let nodes = inputs.nodes(cs, &merkle_proof_vec);
+            let statics_lc: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+
+            let leaf_lc = poseidon_commit_gadget(cs, value, blinding, &p_params, statics).unwrap();
+            constrain_leaf_index_bits(cs, &bits).unwrap();
+            let root_lc = vanilla_merkle_merkle_tree_root_constraints(cs, tree.proof_len(), leaf_lc, &bits,
+                                                                      &proof_node_lcs(&nodes), statics_lc,
+                                                                      &p_params).unwrap();
+            constrain_lc_with_scalar(cs, root_lc, &tree.raw_root());
+            Ok::<(), GadgetError>(())
+        }));
+    }
+
+    #[test]
+    fn test_VSMT_cross_tree_membership() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+
+        let mut tree_a = VanillaSparseMerkleTree::new(&p_params);
+        let mut tree_b = VanillaSparseMerkleTree::new(&p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree_a.update(s, s);
+            // Trees have different leaves except at indices 3 to 7
+            if i >= 3 && i <= 7 {
+                tree_b.update(s, s);
+            } else {
+                tree_b.update(s, s + Scalar::from(100u32));
+            }
+        }
+        assert_ne!(tree_a.root(), tree_b.root());
+
+        // Prove that the leaf of `tree_a` at index `k` is also in `tree_b`
+        fn prove_cross_tree(tree_a: &VanillaSparseMerkleTree, tree_b: &VanillaSparseMerkleTree, k: Scalar, p_params: &PoseidonParams) -> bool {
+            let mut merkle_proof_a = Some(Vec::<Scalar>::new());
+            let leaf = tree_a.get(k, &mut merkle_proof_a);
+            let merkle_proof_a = merkle_proof_a.unwrap();
+            let mut merkle_proof_b = Some(Vec::<Scalar>::new());
+            tree_b.get(k, &mut merkle_proof_b);
+            let merkle_proof_b = merkle_proof_b.unwrap();
+
+            let bp_gens = BulletproofGens::new(1 << 16, 1);
+
+            prove_and_verify!(b"VSMT_cross_tree", p_params, &bp_gens, |cs, inputs, statics| {
+                let leaf = inputs.value(cs, leaf);
+                let bits = inputs.bits(cs, &k, tree_a.proof_len());
+                let nodes_a = inputs.nodes(cs, &merkle_proof_a);
+                let nodes_b = inputs.nodes(cs, &merkle_proof_b);
+                cross_tree_membership_gadget(cs, tree_a.proof_len(), &tree_a.raw_root(), &tree_b.raw_root(), leaf, bits,
+                                             nodes_a, nodes_b, statics, p_params)
+            })
+        }
+
+        // Trees agree on the leaf at index 5 but not at index 9
+        assert!(prove_cross_tree(&tree_a, &tree_b, Scalar::from(5u32), &p_params));
+        assert!(!prove_cross_tree(&tree_a, &tree_b, Scalar::from(9u32), &p_params));
+    }
+
+    #[test]
+    fn test_historical_membership_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let history_depth = 4;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        let mut history = VanillaSparseMerkleTree::new_with_depth(history_depth, &p_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i), Scalar::from(i));
+        }
+        let old_value = Scalar::from(3u32);
+        let mut old_tree_proof = Some(Vec::<Scalar>::new());
+        tree.get(Scalar::from(3u32), &mut old_tree_proof);
+        history.update(Scalar::zero(), tree.raw_root());
+
+        let new_value = Scalar::from(333u32);
+        tree.update(Scalar::from(3u32), new_value);
+        let mut new_tree_proof = Some(Vec::<Scalar>::new());
+        tree.get(Scalar::from(3u32), &mut new_tree_proof);
+        history.update(Scalar::one(), tree.raw_root());
+
+        let (old_tree_proof, new_tree_proof) = (old_tree_proof.unwrap(), new_tree_proof.unwrap());
+
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |value: Scalar, tree_proof: &[Scalar], version: Scalar| -> bool {
+            let idx = Scalar::from(3u32);
+            let mut history_proof = Some(Vec::<Scalar>::new());
+            history.get(version, &mut history_proof);
+            let history_proof = history_proof.unwrap();
+
+            prove_and_verify!(b"VSMT_historical_membership", &p_params, &bp_gens, |cs, inputs, statics| {
+                let committed = inputs.path(cs, idx, value, tree_proof);
+                let version_bits = inputs.bits(cs, &version, history_depth);
+                let history_nodes = inputs.nodes(cs, &history_proof);
+                historical_membership_gadget(cs, depth, history_depth, &history.raw_root(), committed.leaf,
+                                             committed.leaf_index_bits, committed.proof_nodes, version_bits,
+                                             history_nodes, statics, &p_params)
+            })
+        };
+
+        // Old value as of the older version and the new value as of the newer one
+        assert!(prove(old_value, &old_tree_proof, Scalar::zero()));
+        assert!(prove(new_value, &new_tree_proof, Scalar::one()));
+        // Old value is no longer in the tree as of the newer version
+        assert!(!prove(old_value, &old_tree_proof, Scalar::one()));
+        assert!(!prove(old_value, &new_tree_proof, Scalar::one()));
+        assert!(!prove(new_value, &new_tree_proof, Scalar::zero()));
+    }
+
+    #[test]
+    fn test_optional_membership_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i), Scalar::from(i + 100));
+        }
+
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |is_present: bool, leaf: Scalar, idx: Scalar| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(idx, &mut merkle_proof);
+            let merkle_proof = merkle_proof.unwrap();
+
+            prove_and_verify!(b"VSMT_optional_membership", &p_params, &bp_gens, |cs, inputs, statics| {
+                let is_present = inputs.value(cs, if is_present { Scalar::one() } else { Scalar::zero() });
+                let committed = inputs.path(cs, idx, leaf, &merkle_proof);
+                optional_membership_gadget(cs, depth, &tree.raw_root(), is_present, committed.leaf,
+                                           committed.leaf_index_bits, committed.proof_nodes, statics, &p_params)
+            })
+        };
+
+        // Present
+        assert!(prove(true, Scalar::from(103u32), Scalar::from(3u32)));
+        assert!(!prove(true, Scalar::from(104u32), Scalar::from(3u32)));
+        assert!(!prove(true, Scalar::from(103u32), Scalar::from(30u32)));
+        // Absent, the leaf is ignored
+        assert!(prove(false, Scalar::from(103u32), Scalar::from(30u32)));
+        assert!(prove(false, Scalar::zero(), Scalar::from(30u32)));
+        assert!(!prove(false, Scalar::from(103u32), Scalar::from(3u32)));
+    }
+
+    #[test]
+    fn test_public_leaf_verif_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i), Scalar::from(i + 100));
+        }
+
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |public_leaf: Scalar, idx: Scalar| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(idx, &mut merkle_proof);
+            let merkle_proof = merkle_proof.unwrap();
+
+            prove_and_verify!(b"VSMT_public_leaf", &p_params, &bp_gens, |cs, inputs, statics| {
+                // Only the index bits and proof nodes are committed
+                let bits = inputs.bits(cs, &idx, depth);
+                let nodes = inputs.nodes(cs, &merkle_proof);
+                public_leaf_verif_gadget(cs, depth, &tree.raw_root(), &public_leaf, bits, nodes, statics, &p_params)
+            })
+        };
+
+        assert!(prove(Scalar::from(103u32), Scalar::from(3u32)));
+        assert!(prove(Scalar::from(110u32), Scalar::from(10u32)));
+        // Value is not at the index
+        assert!(!prove(Scalar::from(104u32), Scalar::from(3u32)));
+        assert!(!prove(Scalar::from(103u32), Scalar::from(30u32)));
+    }
+
+    #[test]
+    fn test_mixed_visibility_verif_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10u32 {
+            tree.update(Scalar::from(i), Scalar::from(i));
+        }
+        // Upper proof nodes are public
+        let num_public = 6;
+
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        // Prove with the public nodes of the leaf and verify with `public_nodes`
+        let prove = |leaf: Scalar, idx: Scalar, public_nodes: &[Scalar]| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(idx, &mut merkle_proof);
+            // From leaf to root
+            let proof_vec: Vec<Scalar> = merkle_proof.unwrap().into_iter().rev().collect();
+
+            prove_and_verify!(b"VSMT_mixed_visibility", &p_params, &bp_gens, |cs, inputs, statics| {
+                let leaf = inputs.value(cs, leaf);
+                let bits = inputs.bits(cs, &idx, depth);
+                // Only the lower proof nodes are committed
+                let mut nodes: Vec<SiblingKind> = inputs.values(cs, &proof_vec[..depth - num_public]).into_iter()
+                    .map(SiblingKind::Committed).collect();
+                let public_nodes = inputs.either(&proof_vec[depth - num_public..], public_nodes);
+                nodes.extend(public_nodes.iter().map(|p| SiblingKind::Public(*p)));
+                mixed_visibility_verif_gadget(cs, depth, &tree.raw_root(), leaf, bits, nodes, statics, &p_params)
+            })
+        };
+
+        let public_nodes_of = |idx: Scalar| -> Vec<Scalar> {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(idx, &mut merkle_proof);
+            merkle_proof.unwrap().into_iter().rev().skip(depth - num_public).collect()
+        };
+
+        assert!(prove(Scalar::from(7u32), Scalar::from(7u32), &public_nodes_of(Scalar::from(7u32))));
+        assert!(!prove(Scalar::from(8u32), Scalar::from(7u32), &public_nodes_of(Scalar::from(7u32))));
+        // Verifier has different public nodes
+        let mut wrong_public_nodes = public_nodes_of(Scalar::from(7u32));
+        wrong_public_nodes[0] += Scalar::one();
+        assert!(!prove(Scalar::from(7u32), Scalar::from(7u32), &wrong_public_nodes));
+    }
+
+    #[test]
+    fn test_dual_membership_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let value = Scalar::from(4242u32);
+        let mut tree_a = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        let mut tree_b = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10u32 {
+            tree_a.update(Scalar::from(i), Scalar::from(i));
+            tree_b.update(Scalar::from(i), Scalar::from(i + 100));
+        }
+        // Same value at different indices
+        tree_a.update(Scalar::from(3u32), value);
+        tree_b.update(Scalar::from(777u32), value);
+
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |value: Scalar, idx_a: Scalar, idx_b: Scalar| -> bool {
+            let mut merkle_proof_a = Some(Vec::<Scalar>::new());
+            tree_a.get(idx_a, &mut merkle_proof_a);
+            let merkle_proof_a = merkle_proof_a.unwrap();
+            let mut merkle_proof_b = Some(Vec::<Scalar>::new());
+            tree_b.get(idx_b, &mut merkle_proof_b);
+            let merkle_proof_b = merkle_proof_b.unwrap();
+
+            prove_and_verify!(b"VSMT_dual_membership", &p_params, &bp_gens, |cs, inputs, statics| {
+                let value = inputs.value(cs, value);
+                let bits_a = inputs.bits(cs, &idx_a, depth);
+                let nodes_a = inputs.nodes(cs, &merkle_proof_a);
+                let bits_b = inputs.bits(cs, &idx_b, depth);
+                let nodes_b = inputs.nodes(cs, &merkle_proof_b);
+                dual_membership_gadget(cs, depth, &tree_a.raw_root(), &tree_b.raw_root(), value, bits_a, nodes_a,
+                                       bits_b, nodes_b, statics, &p_params)
+            })
+        };
+
+        assert!(prove(value, Scalar::from(3u32), Scalar::from(777u32)));
+        // Value is in tree A but not at that index in tree B, or the other way around
+        assert!(!prove(value, Scalar::from(3u32), Scalar::from(3u32)));
+        assert!(!prove(value, Scalar::from(777u32), Scalar::from(777u32)));
+        // Value in neither tree
+        assert!(!prove(Scalar::from(9999u32), Scalar::from(3u32), Scalar::from(777u32)));
+    }
+
+    #[test]
+    fn test_VSMT_sibling_leaves() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s + Scalar::from(100u32));
+        }
+
+        // Leaves at indices 2k and 2k+1
+        let k = 3u32;
+        let left_idx = Scalar::from(2*k);
+        let right_idx = Scalar::from(2*k + 1);
+        let left_val = tree.get(left_idx, &mut None);
+
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let right_val = tree.get(right_idx, &mut merkle_proof);
+        let mut merkle_proof_vec = merkle_proof.unwrap();
+        // Last proof node is the sibling leaf
+        assert_eq!(left_val, merkle_proof_vec.pop().unwrap());
+
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        assert!(prove_and_verify!(b"VSMT_siblings", &p_params, &bp_gens, |cs, inputs, statics| {
+            let left = inputs.value(cs, left_val);
+            let right = inputs.value(cs, right_val);
+            let bits = inputs.bits(cs, &Scalar::from(k), tree.proof_len() - 1);
+            let nodes = inputs.nodes(cs, &merkle_proof_vec);
+            sibling_leaves_gadget(cs, tree.proof_len(), &tree.raw_root(), left, right, bits, nodes, statics, &p_params)
+        }));
+    }
+
+    #[test]
+    fn test_VSMT_occupied_leaf() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        let prove = |k: Scalar| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            let leaf = tree.get(k, &mut merkle_proof);
+            let merkle_proof = merkle_proof.unwrap();
+
+            prove_and_verify!(b"VSMT_occupied", &p_params, &bp_gens, |cs, inputs, statics| {
+                let committed = inputs.path(cs, k, leaf, &merkle_proof);
+                occupied_leaf_verif_gadget(cs, tree.proof_len(), &tree.raw_root(), committed.leaf,
+                                           committed.leaf_index_bits, committed.proof_nodes, statics, &p_params)
+            })
+        };
+
+        // Index 7 is occupied
+        assert_ne!(tree.get(Scalar::from(7u32), &mut None), Scalar::zero());
+        assert!(prove(Scalar::from(7u32)));
+
+        // Index 20 was never set so its leaf is 0 and has no inverse, the proof does not verify
+        assert_eq!(tree.get(Scalar::from(20u32), &mut None), Scalar::zero());
+        assert!(!prove(Scalar::from(20u32)));
+    }
+
+    #[test]
+    fn test_VSMT_empty_range() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        // Prove that the subtree of the given height containing index `low` is empty and verify the proof
+        fn prove_empty_range(tree: &VanillaSparseMerkleTree, p_params: &PoseidonParams, low: u32, height: usize) -> bool {
+            let high = low + (1 << height) - 1;
+
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(Scalar::from(low), &mut merkle_proof);
+            // Proof nodes above the subtree, from the subtree to the root
+            let subtree_proof: Vec<Scalar> = merkle_proof.unwrap()[..tree.proof_len() - height].iter().rev().cloned().collect();
+
+            let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+            prove_and_verify!(b"VSMT_empty_range", p_params, &bp_gens, |cs, inputs, statics| {
+                let low_bits = inputs.bits(cs, &Scalar::from(low), tree.proof_len());
+                let high_bits = inputs.bits(cs, &Scalar::from(high), tree.proof_len());
+                let nodes = inputs.values(cs, &subtree_proof);
+                empty_range_gadget(cs, tree.proof_len(), &tree.raw_root(), low_bits, high_bits, nodes, statics, p_params)
+            })
+        }
+
+        // [16, 32) and [64, 128) are empty
+        assert!(prove_empty_range(&tree, &p_params, 16, 4));
+        assert!(prove_empty_range(&tree, &p_params, 64, 6));
+        // [0, 16) and [8, 16) contain leaves
+        assert!(!prove_empty_range(&tree, &p_params, 0, 4));
+        assert!(!prove_empty_range(&tree, &p_params, 8, 3));
+        // [24, 56) is empty but is not aligned to a subtree
+        assert!(!prove_empty_range(&tree, &p_params, 24, 5));
+
+        // Subtree of 2^64 leaves
+        let pc_gens = PedersenGens::default();
+        let mut prover_transcript = Transcript::new(b"VSMT_empty_range");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        let bits: Vec<AllocatedScalar> = (0..64).map(|_| {
+            let (_, v) = prover.commit(Scalar::zero(), Scalar::one());
+            AllocatedScalar { variable: v, assignment: Some(Scalar::zero()) }
+        }).collect();
+        assert_eq!(empty_range_gadget(&mut prover, 64, &Scalar::zero(), bits.clone(), bits, vec![], vec![], &p_params),
+                   Err(GadgetError::InvalidDepth(64)));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_pedersen_opening() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let k = Scalar::from(7u32);
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        // Commitment to the leaf made outside of the proof, the verifier already has it
+        let blinding = Scalar::random(&mut test_rng);
+        let leaf_comm = pc_gens.commit(leaf, blinding).compress();
+
+        let (proof, commitments) = {
+            let mut prover_transcript = Transcript::new(b"VSMT_pedersen_opening");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+            // Opening for a different value is rejected
+            assert_eq!(pedersen_open_gadget_for_prover(&mut prover, leaf + Scalar::one(), blinding, &pc_gens, &leaf_comm).unwrap_err(),
+                       GadgetError::CommitmentMismatch);
+
+            let leaf_alloc_scalar = pedersen_open_gadget_for_prover(&mut prover, leaf, blinding, &pc_gens, &leaf_comm).unwrap();
+
+            let mut leaf_index_comms = vec![];
+            let mut leaf_index_alloc_scalars = vec![];
+            for b in get_bits(&k, tree.proof_len()).iter() {
+                let val: Scalar = Scalar::from(*b as u8);
+                let (c, v) = prover.commit(val.clone(), Scalar::random(&mut test_rng));
+                leaf_index_comms.push(c);
+                leaf_index_alloc_scalars.push(AllocatedScalar {
+                    variable: v,
+                    assignment: Some(val),
+                });
+            }
+
+            let mut proof_comms = vec![];
+            let mut proof_alloc_scalars = vec![];
+            for p in merkle_proof_vec.iter().rev() {
+                let (c, v) = prover.commit(*p, Scalar::random(&mut test_rng));
+                proof_comms.push(c);
+                proof_alloc_scalars.push(AllocatedScalar {
+                    variable: v,
+                    assignment: Some(*p),
+                });
+            }
+
+            let num_statics = num_required_statics(&p_params);
+            let statics = allocate_statics_for_prover(&mut prover, num_statics);
+
+            assert!(vanilla_merkle_merkle_tree_verif_gadget(
+                &mut prover,
+                tree.proof_len(),
+                &tree.raw_root(),
+                leaf_alloc_scalar,
+                leaf_index_alloc_scalars,
+                proof_alloc_scalars,
+                statics,
+                &p_params).is_ok());
+
+            let proof = prover.prove(&bp_gens).unwrap();
+
+            (proof, (leaf_index_comms, proof_comms))
+        };
+
+        // Verify against the expected commitment to the leaf
+        let verify = |expected_commitment: &CompressedRistretto| {
+            let mut verifier_transcript = Transcript::new(b"VSMT_pedersen_opening");
+            let mut verifier = Verifier::new(&mut verifier_transcript);
+            let leaf_alloc_scalar = pedersen_open_gadget_for_verifier(&mut verifier, expected_commitment);
+
+            let mut leaf_index_alloc_scalars = vec![];
+            for l in &commitments.0 {
+                let v = verifier.commit(*l);
+                leaf_index_alloc_scalars.push(AllocatedScalar {
+                    variable: v,
+                    assignment: None,
+                });
+            }
+
+            let mut proof_alloc_scalars = vec![];
+            for p in &commitments.1 {
+                let v = verifier.commit(*p);
+                proof_alloc_scalars.push(AllocatedScalar {
+                    variable: v,
+                    assignment: None,
+                });
+            }
+
+            let num_statics = num_required_statics(&p_params);
+            let statics = allocate_statics_for_verifier(&mut verifier, num_statics, &pc_gens);
+
+            assert!(vanilla_merkle_merkle_tree_verif_gadget(
+                &mut verifier,
+                tree.proof_len(),
+                &tree.raw_root(),
+                leaf_alloc_scalar,
+                leaf_index_alloc_scalars,
+                proof_alloc_scalars,
+                statics,
+                &p_params).is_ok());
+
+            verifier.verify(&proof, &pc_gens, &bp_gens).is_ok()
+        };
+
+        assert!(verify(&leaf_comm));
+        // Commitment to another value or with another blinding does not verify
+        assert!(!verify(&pc_gens.commit(leaf + Scalar::one(), blinding).compress()));
+        assert!(!verify(&pc_gens.commit(leaf, blinding + Scalar::one()).compress()));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_const_depth() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+        assert_eq!(tree.depth, 32);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let k = Scalar::from(7u32);
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
+
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        assert!(prove_and_verify!(b"VSMT_const", &p_params, &bp_gens, |cs, inputs, statics| {
+            let committed = inputs.path(cs, k, leaf, &merkle_proof_vec);
+            let leaf_index_arr: [AllocatedScalar; 32] = committed.leaf_index_bits.try_into().unwrap();
+            let proof_arr: [AllocatedScalar; 32] = committed.proof_nodes.try_into().unwrap();
+            vanilla_merkle_tree_verif_gadget_const(cs, &tree.raw_root(), committed.leaf, leaf_index_arr, proof_arr,
+                                                   statics, &p_params)
+        }));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_proof_length_mismatch() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let tree = VanillaSparseMerkleTree::new(&p_params);
+
+        let pc_gens = PedersenGens::default();
+        let mut prover_transcript = Transcript::new(b"VSMT");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+        let k = Scalar::from(7u32);
+        let (_, var_leaf) = prover.commit(k, Scalar::zero());
+        let leaf_alloc_scalar = AllocatedScalar {
+            variable: var_leaf,
+            assignment: Some(k),
+        };
+
+        let mut leaf_index_alloc_scalars = vec![];
+        for b in get_bits(&k, TreeDepth).iter() {
+            let val: Scalar = Scalar::from(*b as u8);
+            let (_, v) = prover.commit(val.clone(), Scalar::zero());
+            leaf_index_alloc_scalars.push(AllocatedScalar {
+                variable: v,
+                assignment: Some(val),
+            });
+        }
+
+        // One proof node less than the depth
+        let mut proof_alloc_scalars = vec![];
+        for _ in 0..tree.proof_len()-1 {
+            let (_, v) = prover.commit(Scalar::zero(), Scalar::zero());
+            proof_alloc_scalars.push(AllocatedScalar {
+                variable: v,
+                assignment: Some(Scalar::zero()),
+            });
+        }
+
+        let statics = allocate_statics_for_prover(&mut prover, 4);
+
+        assert_eq!(vanilla_merkle_merkle_tree_verif_gadget(
+            &mut prover,
+            tree.proof_len(),
+            &tree.raw_root(),
+            leaf_alloc_scalar,
+            leaf_index_alloc_scalars,
+            proof_alloc_scalars,
+            statics,
+            &p_params), Err(GadgetError::ProofLengthMismatch { expected: tree.proof_len(), found: tree.proof_len()-1 }));
+    }
+
+    #[test]
+    fn test_VSMT_leaf_membership() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new(&p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+        let label = b"VSMT_membership";
+
+        let k = Scalar::from(7u32);
+        let (proof, commitments) = gen_proof_of_leaf_membership(&tree, k, &mut test_rng, label, &pc_gens, &bp_gens).unwrap();
+        assert_eq!(commitments.len(), 1 + 2*tree.proof_len());
+
+        // Depth different from the tree's depth gives an error rather than indexing out of bounds
+        assert_eq!(verify_proof_of_leaf_membership(tree.proof_len() + 1, &tree.raw_root(), &p_params, proof.clone(), commitments.clone(),
+                                                   label, &pc_gens, &bp_gens),
+                   Err(GadgetError::ProofLengthMismatch { expected: 1 + 2*(tree.proof_len() + 1), found: 1 + 2*tree.proof_len() }));
+
+        assert!(verify_proof_of_leaf_membership(tree.proof_len(), &tree.raw_root(), &p_params, proof, commitments,
+                                                label, &pc_gens, &bp_gens).is_ok());
+    }
+
+    #[test]
+    fn test_VSMT_verify_each_leaf_membership() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+
+        for i in 1..=16 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+        let label = b"VSMT_membership_many";
+
+        let mut proofs = vec![];
+        for i in 1..=16 {
+            proofs.push(gen_proof_of_leaf_membership(&tree, Scalar::from(i as u32), &mut test_rng, label, &pc_gens, &bp_gens).unwrap());
+        }
+
+        let start = Instant::now();
+        assert!(verify_each_proof_of_leaf_membership(tree.proof_len(), &tree.raw_root(), &p_params, &proofs,
+                                                    label, &pc_gens, &bp_gens).is_ok());
+        println!("Verifying {} proofs took {:?}", proofs.len(), start.elapsed());
+
+        // Commitments of one proof used for another
+        let mut bad_proofs = proofs.clone();
+        bad_proofs[3].1 = proofs[4].1.clone();
+        assert!(verify_each_proof_of_leaf_membership(tree.proof_len(), &tree.raw_root(), &p_params, &bad_proofs,
+                                                    label, &pc_gens, &bp_gens).is_err());
+
+        // Malformed commitments are caught before verifying any proof
+        let mut bad_proofs = proofs.clone();
+        bad_proofs[15].1.pop();
+        assert_eq!(verify_each_proof_of_leaf_membership(tree.proof_len(), &tree.raw_root(), &p_params, &bad_proofs,
+                                                       label, &pc_gens, &bp_gens),
+                   Err(GadgetError::ProofLengthMismatch { expected: 1 + 2*tree.proof_len(), found: 2*tree.proof_len() }));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_depth_mismatch() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let tree = VanillaSparseMerkleTree::new(&p_params);
+
+        let pc_gens = PedersenGens::default();
+        let mut prover_transcript = Transcript::new(b"VSMT");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+        let k = Scalar::from(7u32);
+        let (_, var_leaf) = prover.commit(k, Scalar::zero());
+        let leaf_alloc_scalar = AllocatedScalar {
+            variable: var_leaf,
+            assignment: Some(k),
+        };
+
+        let mut leaf_index_alloc_scalars = vec![];
+        let mut proof_alloc_scalars = vec![];
+        for b in get_bits(&k, tree.proof_len()).iter() {
+            let val: Scalar = Scalar::from(*b as u8);
+            let (_, v) = prover.commit(val.clone(), Scalar::zero());
+            leaf_index_alloc_scalars.push(AllocatedScalar {
+                variable: v,
+                assignment: Some(val),
+            });
+            let (_, v) = prover.commit(Scalar::zero(), Scalar::zero());
+            proof_alloc_scalars.push(AllocatedScalar {
+                variable: v,
+                assignment: Some(Scalar::zero()),
+            });
+        }
+
+        let statics = allocate_statics_for_prover(&mut prover, 4);
+
+        // A depth greater than the number of leaf index bits and proof nodes used to index out of bounds
+        assert_eq!(vanilla_merkle_merkle_tree_verif_gadget(
+            &mut prover,
+            tree.proof_len() + 1,
+            &tree.raw_root(),
+            leaf_alloc_scalar,
+            leaf_index_alloc_scalars,
+            proof_alloc_scalars,
+            statics,
+            &p_params), Err(GadgetError::ProofLengthMismatch { expected: tree.proof_len() + 1, found: tree.proof_len() }));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_leaf_hash_params() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        // Leaves are hashed with a permutation of width 4 so they need 2 statics while the nodes need 4
+        let leaf_width = 4;
+        let (leaf_full_b, leaf_full_e, leaf_partial) = (4, 4, 60);
+        let leaf_round_keys = p_params.round_keys()[..(leaf_full_b + leaf_full_e + leaf_partial) * leaf_width].to_vec();
+        // Cauchy matrix 1/(x_i + y_j) with x_i = i and y_j = leaf_width + j
+        let leaf_mds: Vec<Vec<Scalar>> = (0..leaf_width).map(|i| {
+            (0..leaf_width).map(|j| Scalar::from((i + leaf_width + j) as u64).invert()).collect()
+        }).collect();
+        let leaf_p_params = PoseidonParams::from_constants(leaf_width, leaf_full_b, leaf_full_e, leaf_partial,
+                                                           leaf_round_keys, leaf_mds).unwrap();
+        assert_eq!(num_required_statics(&leaf_p_params), 2);
+        let mut tree = VanillaSparseMerkleTree::new_with_leaf_hash_params(&p_params, &leaf_p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let k =  Scalar::from(7u32);
+        let value = Scalar::from(1000u32);
+        let blinding = Scalar::random(&mut test_rng);
+        let leaf = tree.leaf_commitment(value, blinding);
+        assert_eq!(leaf, poseidon_commit(value, blinding, &leaf_p_params));
+        assert_ne!(leaf, poseidon_commit(value, blinding, &p_params));
+        tree.update(k, leaf);
+
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        assert_eq!(leaf, tree.get(k, &mut merkle_proof));
+        let merkle_proof_vec = merkle_proof.unwrap();
+
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        assert!(prove_and_verify!(b"VSMT_leaf_params", &p_params, &bp_gens, |cs, inputs, statics| {
+            let value = inputs.value(cs, value);
+            let blinding = inputs.value(cs, blinding);
+            let bits = inputs.bits(cs, &k, tree.proof_len());
+            let nodes = inputs.nodes(cs, &merkle_proof_vec);
+            let leaf_statics = inputs.statics(cs, num_required_statics(tree.leaf_hash_params()));
+            committed_leaf_verif_gadget(cs, tree.proof_len(), &tree.raw_root(), value, blinding, bits, nodes, statics,
+                                        leaf_statics, tree.leaf_hash_params(), &p_params)
+        }));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_hashed_leaf_encoding() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_leaf_encoding(&p_params, LeafEncoding::Hashed);
+        let mut raw_tree = VanillaSparseMerkleTree::new(&p_params);
+        assert_eq!(raw_tree.leaf_encoding(), LeafEncoding::Raw);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+            raw_tree.update(s, s);
+        }
+        assert_ne!(tree.root(), raw_tree.root());
+
+        let k = Scalar::from(7u32);
+        let value = k;
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
+        // The stored leaf is bound to the position
+        assert_eq!(leaf, hashed_leaf(k, value, &p_params));
+        assert_eq!(leaf, tree.encode_leaf(&k, value));
+        assert_ne!(leaf, hashed_leaf(Scalar::from(8u32), value, &p_params));
+        assert!(tree.verify_proof(k, value, &merkle_proof_vec, None));
+        assert!(!tree.verify_proof(k, leaf, &merkle_proof_vec, None));
+
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        assert!(prove_and_verify!(b"VSMT_hashed_leaf", &p_params, &bp_gens, |cs, inputs, statics| {
+            let value = inputs.value(cs, value);
+            let bits = inputs.bits(cs, &k, tree.proof_len());
+            let nodes = inputs.nodes(cs, &merkle_proof_vec);
+            let leaf_statics = inputs.statics(cs, num_required_statics(tree.leaf_hash_params()));
+            hashed_leaf_verif_gadget(cs, tree.proof_len(), &tree.raw_root(), value, bits, nodes, statics, leaf_statics,
+                                     tree.leaf_hash_params(), &p_params)
+        }));
+    }
+
+    #[test]
+    fn test_index_hamming_distance_gadget() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(128, 1);
+        let depth = 8;
+
+        let distance = |index_a: u32, index_b: u32, expected: u64| -> bool {
+            prove_and_verify!(b"VSMT_hamming", &bp_gens, |cs, inputs| {
+                let bits_a = inputs.bits(cs, &Scalar::from(index_a), depth);
+                constrain_leaf_index_bits(cs, &bits_a).unwrap();
+                let bits_b = inputs.bits(cs, &Scalar::from(index_b), depth);
+                constrain_leaf_index_bits(cs, &bits_b).unwrap();
+                index_hamming_distance_gadget(cs, &bits_a, &bits_b, expected)
+            })
+        };
+
+        assert!(distance(77, 77, 0));
+        assert!(!distance(77, 77, 1));
+        assert!(distance(76, 77, 1));
+        assert!(distance(0b1000_0000, 0, 1));
+        assert!(!distance(76, 77, 0));
+        assert!(!distance(76, 77, 2));
+        // All bits differ
+        assert!(distance(0b1010_1010, 0b0101_0101, depth as u64));
+        assert!(distance(255, 0, depth as u64));
+        assert!(!distance(255, 1, depth as u64));
+
+        let mut prover_transcript = Transcript::new(b"VSMT_hamming");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        assert_eq!(index_hamming_distance_gadget(&mut prover, &[], &[], 0), Ok(()));
+    }
+
+    #[test]
+    fn test_distinct_indices_gadget() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(128, 1);
+        let depth = 16;
+
+        let distinct = |index_a: u32, index_b: u32| -> bool {
+            prove_and_verify!(b"VSMT_distinct", &bp_gens, |cs, inputs| {
+                let bits_a = inputs.bits(cs, &Scalar::from(index_a), depth);
+                let bits_b = inputs.bits(cs, &Scalar::from(index_b), depth);
+                constrain_leaf_index_bits(cs, &[bits_a.clone(), bits_b.clone()].concat()).unwrap();
+                distinct_indices_gadget(cs, &bits_a, &bits_b)
+            })
+        };
+
+        assert!(distinct(7, 8));
+        assert!(distinct(0, 1 << 15));
+        assert!(distinct(65535, 0));
+        assert!(!distinct(7, 7));
+        assert!(!distinct(0, 0));
+
+        let mut prover_transcript = Transcript::new(b"VSMT_distinct");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        let (_, v) = prover.commit(Scalar::one(), Scalar::one());
+        let bit = AllocatedScalar { variable: v, assignment: Some(Scalar::one()) };
+        assert_eq!(distinct_indices_gadget(&mut prover, &[bit], &[bit, bit]),
+                   Err(GadgetError::ProofLengthMismatch { expected: 1, found: 2 }));
+        assert_eq!(distinct_indices_gadget(&mut prover, &vec![bit; 253], &vec![bit; 253]),
+                   Err(GadgetError::InvalidDepth(253)));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_hidden_root() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        let k = Scalar::from(7u32);
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
+
+        let bp_gens = BulletproofGens::new(1 << 13, 1);
+
+        let prove = |root: Scalar| -> bool {
+            prove_and_verify!(b"VSMT_hidden_root", &p_params, &bp_gens, |cs, inputs, statics| {
+                let root = inputs.value(cs, root);
+                let committed = inputs.path(cs, k, leaf, &merkle_proof_vec);
+                vanilla_merkle_tree_verif_gadget_hidden_root(cs, tree.proof_len(), root, committed.leaf,
+                                                             committed.leaf_index_bits, committed.proof_nodes, statics,
+                                                             &p_params)
+            })
+        };
+
+        assert!(prove(tree.raw_root()));
+        assert!(!prove(tree.raw_root() + Scalar::one()));
+        assert!(!prove(VanillaSparseMerkleTree::new_with_depth(16, &p_params).raw_root()));
+    }
+
+    #[test]
+    fn test_index_parity_gadget() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(128, 1);
+        let depth = 8;
+
+        let parity = |index: u32, expect_odd: bool| -> bool {
+            prove_and_verify!(b"VSMT_parity", &bp_gens, |cs, inputs| {
+                let bits = inputs.bits(cs, &Scalar::from(index), depth);
+                constrain_leaf_index_bits(cs, &bits).unwrap();
+                index_parity_gadget(cs, &bits, expect_odd)
+            })
+        };
+
+        assert!(parity(10, false));
+        assert!(parity(0, false));
+        assert!(parity(77, true));
+        assert!(!parity(10, true));
+        assert!(!parity(77, false));
+
+        let mut prover_transcript = Transcript::new(b"VSMT_parity");
+        let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        assert_eq!(index_parity_gadget(&mut prover, &[], true), Err(GadgetError::InvalidDepth(0)));
+    }
+
+    #[test]
+    fn test_VSMT_Verif_mixed_sbox() {
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 8;
+        let hash_spec = HashSpec { leaf_sbox: SboxType::Inverse, node_sbox: SboxType::Cube };
+        assert_ne!(hash_spec, HashSpec::default());
+
+        // Root of a path hashed with the inverse S-box at the leaf level and the cube S-box above
+        let leaf = Scalar::from(7u32);
+        let position = Scalar::from(77u32);
+        let proof_nodes: Vec<Scalar> = (0..depth).map(|_| Scalar::random(&mut test_rng)).collect();
+        let bits = get_bits(&position, depth);
+        let mut root = leaf;
+        for i in 0..depth {
+            let sbox = if i == 0 { SboxType::Inverse } else { SboxType::Cube };
+            root = if bits[i] == 1 {
+                Poseidon_hash_2(proof_nodes[i], root, &p_params, &sbox)
+            } else {
+                Poseidon_hash_2(root, proof_nodes[i], &p_params, &sbox)
+            };
+        }
+
+        let bp_gens = BulletproofGens::new(1 << 13, 1);
+
+        let prove = |spec: HashSpec| -> bool {
+            prove_and_verify!(b"VSMT_mixed_sbox", &p_params, &bp_gens, |cs, inputs, statics| {
+                let leaf = inputs.value(cs, leaf);
+                let bits = inputs.bits(cs, &position, depth);
+                let nodes = inputs.values(cs, &proof_nodes);
+                vanilla_merkle_merkle_tree_verif_gadget_with_hash_spec(cs, depth, &root, leaf, bits, nodes, statics,
+                                                                       &p_params, spec)
+            })
+        };
+
+        assert!(prove(hash_spec));
+        assert!(!prove(HashSpec::default()));
+        assert!(!prove(HashSpec { leaf_sbox: SboxType::Cube, node_sbox: SboxType::Inverse }));
+    }
+
+    #[test]
+    fn test_sorted_non_membership_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 8;
+        let n_bits = 32;
+        // Leaves sorted by position, with the smallest and largest values at the ends
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        let values = [1u32, 10, 20, 30, 40, 50, (1 << 30)];
+        for (i, v) in values.iter().enumerate() {
+            tree.update(Scalar::from(i as u32), Scalar::from(*v));
+        }
+
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |q: Scalar, prev_pos: u32, next_pos: u32| -> bool {
+            let (prev_pos, next_pos) = (Scalar::from(prev_pos), Scalar::from(next_pos));
+            let mut prev_proof = Some(Vec::<Scalar>::new());
+            let prev_leaf = tree.get(prev_pos, &mut prev_proof);
+            let prev_proof = prev_proof.unwrap();
+            let mut next_proof = Some(Vec::<Scalar>::new());
+            let next_leaf = tree.get(next_pos, &mut next_proof);
+            let next_proof = next_proof.unwrap();
+
+            prove_and_verify!(b"VSMT_sorted_non_member", &p_params, &bp_gens, |cs, inputs, statics| {
+                let q = inputs.value(cs, q);
+                let prev = inputs.path(cs, prev_pos, prev_leaf, &prev_proof);
+                let next = inputs.path(cs, next_pos, next_leaf, &next_proof);
+                sorted_non_membership_gadget(cs, depth, &tree.raw_root(), q, prev.leaf, next.leaf,
+                                             prev.leaf_index_bits, prev.proof_nodes, next.leaf_index_bits,
+                                             next.proof_nodes, statics, &p_params, n_bits)
+            })
+        };
+
+        // 25 is between the adjacent leaves 20 and 30
+        assert!(prove(Scalar::from(25u32), 2, 3));
+        assert!(prove(Scalar::from(21u32), 2, 3));
+        assert!(prove(Scalar::from(29u32), 2, 3));
+        assert!(prove(Scalar::from(1000u32), 5, 6));
+
+        // Present values
+        assert!(!prove(Scalar::from(20u32), 2, 3));
+        assert!(!prove(Scalar::from(30u32), 2, 3));
+        // Not in the gap
+        assert!(!prove(Scalar::from(35u32), 2, 3));
+        assert!(!prove(Scalar::from(15u32), 2, 3));
+        // Leaves that are not adjacent, 30 is between them
+        assert!(!prove(Scalar::from(25u32), 2, 4));
+        assert!(!prove(Scalar::from(25u32), 3, 2));
+    }
+
+    #[test]
+    fn test_estimate_proving_time() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 8;
+        let tree = tree_from_seed([5u8; 32], depth, 10, &p_params);
+        let k = Scalar::from(3u32);
+        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        let pc_gens = PedersenGens::default();
+        let num_constraints = {
+            let mut transcript = Transcript::new(b"VSMTEstimate");
+            let mut prover = Prover::new(&pc_gens, &mut transcript);
+            leaf_membership_constraints(&mut prover, &tree, k, &mut test_rng).unwrap();
+            prover.num_constraints()
+        };
+        let bp_gens = recommended_bp_gens(depth, &p_params);
+
+        let estimate = estimate_proving_time(num_constraints).unwrap();
+        // Calibrated once
+        assert_eq!(Some(estimate), estimate_proving_time(num_constraints));
+        assert!(estimate_proving_time(2 * num_constraints).unwrap() >= estimate);
+        // Too many constraints to count rather than a truncated count
+        assert_eq!(estimate_proving_time(usize::max_value()), None);
+
+        let start = Instant::now();
+        gen_proof_of_leaf_membership(&tree, k, &mut test_rng, b"VSMTEstimate", &pc_gens, &bp_gens).unwrap();
+        let actual = start.elapsed();
+
+        // Timings vary a lot on shared machines, only check the order of magnitude
+        assert!(actual < estimate * 20, "actual {:?}, estimate {:?}", actual, estimate);
+        assert!(estimate < actual * 20, "actual {:?}, estimate {:?}", actual, estimate);
+    }
+
+    #[test]
+    fn test_leaf_not_equal_at_index_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        let prove = |index: Scalar, v: Scalar| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            let leaf = tree.get(index, &mut merkle_proof);
+            let merkle_proof = merkle_proof.unwrap();
 
-        self.root = cur_val;
+            prove_and_verify!(b"VSMT_not_equal_at_index", &p_params, &bp_gens, |cs, inputs, statics| {
+                let v = inputs.value(cs, v);
+                let leaf = inputs.value(cs, leaf);
+                // Gadget takes the proof nodes from the leaf to the root
+                let proof_nodes = inputs.nodes(cs, &merkle_proof);
+                leaf_not_equal_at_index_gadget(cs, depth, &tree.raw_root(), &index, v, leaf, proof_nodes, statics,
+                                               &p_params)
+            })
+        };
 
-        cur_val
+        // Slot 5 holds 5
+        assert!(prove(Scalar::from(5u32), Scalar::from(7u32)));
+        assert!(!prove(Scalar::from(5u32), Scalar::from(5u32)));
+        // Empty slot does not hold a non-zero value but holds 0
+        assert!(prove(Scalar::from(100u32), Scalar::from(5u32)));
+        assert!(!prove(Scalar::from(100u32), Scalar::zero()));
     }
 
-    /// Get a value from tree, if `proof` is not None, populate `proof` with the merkle proof
-    pub fn get(&self, idx: Scalar, proof: &mut Option<Vec<Scalar>>) -> Scalar {
-        let mut cur_idx = ScalarBits::from_scalar(&idx, TreeDepth);
-        let mut cur_node = self.root.clone();
-
-        let need_proof = proof.is_some();
-        let mut proof_vec = Vec::<Scalar>::new();
-
-        for i in 0..self.depth {
-            let k = cur_node.to_bytes();
-            let v = self.db.get(&k).unwrap();
-            if cur_idx.is_msb_set() {
-                // MSB is set, traverse to right subtree
-                cur_node = v.1;
-                if need_proof { proof_vec.push(v.0); }
-            } else {
-                // MSB is unset, traverse to left subtree
-                cur_node = v.0;
-                if need_proof { proof_vec.push(v.1); }
-            }
-            cur_idx.shl();
+    #[test]
+    fn test_leaf_is_hash_of_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
         }
+        let (a, b) = (Scalar::from(1111u32), Scalar::from(2222u32));
+        let k = Scalar::from(7u32);
+        tree.update(k, Poseidon_hash_2(a, b, &p_params, &SboxType::Inverse));
 
-        match proof {
-            Some(v) => {
-                v.extend_from_slice(&proof_vec);
-            }
-            None => ()
-        }
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
 
-        cur_node
-    }
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
 
-    /// Verify a merkle proof, if `root` is None, use the current root else use given root
-    pub fn verify_proof(&self, idx: Scalar, val: Scalar, proof: &[Scalar], root: Option<&Scalar>) -> bool {
-        let mut cur_idx = ScalarBits::from_scalar(&idx, TreeDepth);
-        let mut cur_val = val.clone();
+        let prove = |a: Scalar, b: Scalar| -> bool {
+            prove_and_verify!(b"VSMT_leaf_hash", &p_params, &bp_gens, |cs, inputs, statics| {
+                let committed = inputs.path(cs, k, leaf, &merkle_proof_vec);
+                let a = inputs.value(cs, a);
+                let b = inputs.value(cs, b);
+                leaf_is_hash_of_gadget(cs, committed.leaf, a, b, statics.clone(), &p_params).unwrap();
+                vanilla_merkle_merkle_tree_verif_gadget(cs, tree.proof_len(), &tree.raw_root(), committed.leaf,
+                                                        committed.leaf_index_bits, committed.proof_nodes, statics,
+                                                        &p_params)
+            })
+        };
 
-        for i in 0..self.depth {
-            cur_val = {
-                if cur_idx.is_lsb_set() {
-                    // mimc(&proof[self.depth-1-i], &cur_val, self.hash_constants)
-                    Poseidon_hash_2(proof[self.depth-1-i].clone(), cur_val.clone(), self.hash_params, &SboxType::Inverse)
-                } else {
-                    // mimc(&cur_val, &proof[self.depth-1-i], self.hash_constants)
-                    Poseidon_hash_2(cur_val.clone(), proof[self.depth-1-i].clone(), self.hash_params, &SboxType::Inverse)
-                }
-            };
+        assert!(prove(a, b));
+        // Hash of other values or of the same values in the other order is not the leaf
+        assert!(!prove(a, b + Scalar::one()));
+        assert!(!prove(b, a));
+    }
 
-            cur_idx.shr();
+    #[test]
+    fn test_nullifier_membership_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
         }
+        let secret = Scalar::from(987654321u64);
+        let epoch = Scalar::from(2024u32);
+        let k = Scalar::from(7u32);
+        tree.update(k, Poseidon_hash_2(epoch, secret, &p_params, &SboxType::Inverse));
 
-        // Check if root is equal to cur_val
-        match root {
-            Some(r) => {
-                cur_val == *r
-            }
-            None => {
-                cur_val == self.root
-            }
-        }
-    }
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
 
-    fn update_db_with_key_val(&mut self, key: Scalar, val: DBVal) {
-        self.db.insert(key.to_bytes(), val);
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
+
+        // Prove with `prover_epoch` and verify with `verifier_epoch`
+        let prove = |secret: Scalar, prover_epoch: Scalar, verifier_epoch: Scalar| -> bool {
+            prove_and_verify!(b"VSMT_nullifier", &p_params, &bp_gens, |cs, inputs, statics| {
+                // Leaf position is the secret's commitment followed by the index bits and proof nodes
+                let committed = inputs.path(cs, k, secret, &merkle_proof_vec);
+                let epoch = inputs.either(prover_epoch, verifier_epoch);
+                nullifier_membership_gadget(cs, depth, &tree.raw_root(), epoch, committed.leaf,
+                                            committed.leaf_index_bits, committed.proof_nodes, statics, &p_params)
+            })
+        };
+
+        assert!(prove(secret, epoch, epoch));
+        // Same secret under another epoch
+        let next_epoch = epoch + Scalar::one();
+        assert!(!prove(secret, next_epoch, next_epoch));
+        assert!(!prove(secret, epoch, next_epoch));
+        assert!(!prove(secret + Scalar::one(), epoch, epoch));
     }
-}
 
+    #[test]
+    fn test_preimage_membership_gadget() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
 
-/// left = (1-leaf_side) * leaf + (leaf_side * proof_node)
-/// right = leaf_side * leaf + ((1-leaf_side) * proof_node))
-pub fn vanilla_merkle_merkle_tree_verif_gadget<CS: ConstraintSystem>(
-    cs: &mut CS,
-    depth: usize,
-    root: &Scalar,
-    leaf_val: AllocatedScalar,
-    leaf_index_bits: Vec<AllocatedScalar>,
-    proof_nodes: Vec<AllocatedScalar>,
-    statics: Vec<AllocatedScalar>,
-    poseidon_params: &PoseidonParams
-) -> Result<(), R1CSError> {
+        let secret = Scalar::from(987654321u64);
+        let k = Scalar::from(7u32);
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+        tree.update(k, Poseidon_hash_2(secret, secret, &p_params, &SboxType::Inverse));
 
-    let mut prev_hash = LinearCombination::default();
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        tree.get(k, &mut merkle_proof);
+        let merkle_proof_vec = merkle_proof.unwrap();
 
-    let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+        let bp_gens = BulletproofGens::new(1 << 14, 1);
 
-    for i in 0..depth {
-        let leaf_val_lc = if i == 0 {
-            LinearCombination::from(leaf_val.variable)
-        } else {
-            prev_hash.clone()
+        let prove = |secret: Scalar| -> bool {
+            prove_and_verify!(b"VSMT_preimage", &p_params, &bp_gens, |cs, inputs, statics| {
+                let secret = inputs.value(cs, secret);
+                let bits = inputs.bits(cs, &k, tree.proof_len());
+                let nodes = inputs.nodes(cs, &merkle_proof_vec);
+                preimage_membership_gadget(cs, tree.proof_len(), &tree.raw_root(), secret, bits, nodes, statics,
+                                           &p_params)
+            })
         };
-        let one_minus_leaf_side: LinearCombination = Variable::One() - leaf_index_bits[i].variable;
 
-        let (_, _, left_1) = cs.multiply(one_minus_leaf_side.clone(), leaf_val_lc.clone());
-        let (_, _, left_2) = cs.multiply(leaf_index_bits[i].variable.into(), proof_nodes[i].variable.into());
-        let left = left_1 + left_2;
+        assert!(prove(secret));
+        assert!(!prove(secret + Scalar::one()));
+        // The leaf itself is not a valid secret
+        assert!(!prove(Poseidon_hash_2(secret, secret, &p_params, &SboxType::Inverse)));
+    }
+
+    #[test]
+    fn test_kv_membership_gadget() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_leaf_encoding(&p_params, LeafEncoding::Hashed);
 
-        let (_, _, right_1) = cs.multiply(leaf_index_bits[i].variable.into(), leaf_val_lc);
-        let (_, _, right_2) = cs.multiply(one_minus_leaf_side, proof_nodes[i].variable.into());
-        let right = right_1 + right_2;
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
 
-        // prev_hash = mimc_hash_2::<CS>(cs, left, right, mimc_rounds, mimc_constants)?;
-        prev_hash = Poseidon_hash_2_constraints::<CS>(cs, left, right, statics.clone(), poseidon_params, &SboxType::Inverse)?;
-    }
+        // Place the leaf of (7, 7) at position 20 as well, so it has a valid merkle proof at an index other than 7
+        let misplaced = Scalar::from(20u32);
+        let mut path = get_bits(&misplaced, tree.proof_len());
+        path.reverse();
+        tree.update_at_path(&path, hashed_leaf(Scalar::from(7u32), Scalar::from(7u32), &p_params)).unwrap();
 
-    constrain_lc_with_scalar::<CS>(cs, prev_hash, root);
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
 
-    Ok(())
-}
+        let kv_proof = |idx: Scalar, val: Scalar, position: Scalar| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(position, &mut merkle_proof);
+            let merkle_proof_vec = merkle_proof.unwrap();
 
+            prove_and_verify!(b"VSMT_kv", &p_params, &bp_gens, |cs, inputs, statics| {
+                let idx = inputs.value(cs, idx);
+                let val = inputs.value(cs, val);
+                let bits = inputs.bits(cs, &position, tree.proof_len());
+                let nodes = inputs.nodes(cs, &merkle_proof_vec);
+                kv_membership_gadget(cs, tree.proof_len(), &tree.raw_root(), idx, val, bits, nodes, statics, &p_params)
+            })
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use merlin::Transcript;
-    use curve25519_dalek::constants::BASEPOINT_ORDER;
-    use rand::SeedableRng;
-    use super::rand::rngs::StdRng;
-    // For benchmarking
-    use std::time::{Duration, Instant};
+        let seven = Scalar::from(7u32);
+        assert!(kv_proof(seven, seven, seven));
+        // Wrong value
+        assert!(!kv_proof(seven, Scalar::from(8u32), seven));
+        // The leaf is a member at position 20 but the index bits are not the bits of the index
+        assert_eq!(tree.get(misplaced, &mut None), hashed_leaf(seven, seven, &p_params));
+        assert!(!kv_proof(seven, seven, misplaced));
+    }
 
     #[test]
-    fn test_vanilla_sparse_merkle_tree() {
-        let mut test_rng: OsRng = OsRng::default();
-
-        // Generate the MiMC round constants
-        /*let constants = (0..MIMC_ROUNDS).map(|_| Scalar::random(&mut test_rng)).collect::<Vec<_>>();
-        let mut tree = VanillaSparseMerkleTree::new(&constants);*/
+    fn test_VSMT_Verif_non_boolean_index_bit() {
         let width = 6;
-        let (full_b, full_e) = (4, 4);
-        let partial_rounds = 140;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
         let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
         let mut tree = VanillaSparseMerkleTree::new(&p_params);
 
-        for i in 1..10 {
+        for i in 1..=10 {
             let s = Scalar::from(i as u32);
             tree.update(s, s);
         }
 
-        for i in 1..10 {
-            let s = Scalar::from(i as u32);
-            assert_eq!(s, tree.get(s, &mut None));
-            let mut proof_vec = Vec::<Scalar>::new();
-            let mut proof = Some(proof_vec);
-            assert_eq!(s, tree.get(s, &mut proof));
-            proof_vec = proof.unwrap();
-            assert!(tree.verify_proof(s, s, &proof_vec, None));
-            assert!(tree.verify_proof(s, s, &proof_vec, Some(&tree.root)));
-        }
+        let mut merkle_proof_vec = Vec::<Scalar>::new();
+        let mut merkle_proof = Some(merkle_proof_vec);
+        let k =  Scalar::from(7u32);
+        assert_eq!(k, tree.get(k, &mut merkle_proof));
+        merkle_proof_vec = merkle_proof.unwrap();
+        let proof_nodes: Vec<Scalar> = merkle_proof_vec.iter().rev().map(|p| p.clone()).collect();
 
-        let kvs: Vec<(Scalar, Scalar)> = (0..100).map(|_| (Scalar::random(&mut test_rng), Scalar::random(&mut test_rng))).collect();
-        for i in 0..kvs.len() {
-            tree.update(kvs[i].0, kvs[i].1);
-        }
+        // Replace the first index bit with a value that is not a bit
+        let mut index_bits: Vec<Scalar> = get_bits(&k, TreeDepth).iter().map(|b| Scalar::from(*b)).collect();
+        index_bits[0] = Scalar::from(2u8);
 
-        for i in 0..kvs.len() {
-            assert_eq!(kvs[i].1, tree.get(kvs[i].0, &mut None));
+        // Compute the root the same way the gadget does, i.e. by treating the index bits as field elements.
+        // Without checking that the index bits are bits, the gadget accepts this root.
+        let mut forged_root = k.clone();
+        for i in 0..tree.proof_len() {
+            let b = index_bits[i];
+            let left = (Scalar::one() - b) * forged_root + b * proof_nodes[i];
+            let right = b * forged_root + (Scalar::one() - b) * proof_nodes[i];
+            forged_root = Poseidon_hash_2(left, right, &p_params, &SboxType::Inverse);
         }
+
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        // Prove and verify the forged witness with the current gadget if `bit_check` is true else with the gadget as it
+        // was before the index bits were constrained to be bits.
+        let prove = |bit_check: bool| -> bool {
+            prove_and_verify!(b"VSMT", &p_params, &bp_gens, |cs, inputs, statics| {
+                let leaf = inputs.value(cs, k);
+                let bits = inputs.values(cs, &index_bits);
+                let nodes = inputs.values(cs, &proof_nodes);
+                if bit_check {
+                    vanilla_merkle_merkle_tree_verif_gadget(cs, tree.proof_len(), &forged_root, leaf, bits, nodes, statics,
+                                                            &p_params)
+                } else {
+                    verif_gadget_without_bit_check(cs, tree.proof_len(), &forged_root, leaf, &bits, &nodes, statics,
+                                                   &p_params).map_err(GadgetError::from)
+                }
+            })
+        };
+
+        // The forged proof was accepted before the index bits were checked
+        assert!(prove(false));
+        // The index bit check fails
+        assert!(!prove(true));
     }
 
-    #[test]
-    fn test_VSMT_Verif() {
-        let mut test_rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+    /// Merkle gadget as it was before the leaf index bits were constrained to be bits, used to show that
+    /// `test_VSMT_Verif_non_boolean_index_bit` forges a proof it accepts.
+    fn verif_gadget_without_bit_check<CS: ConstraintSystem>(
+        cs: &mut CS,
+        depth: usize,
+        root: &Scalar,
+        leaf_val: AllocatedScalar,
+        leaf_index_bits: &[AllocatedScalar],
+        proof_nodes: &[AllocatedScalar],
+        statics: Vec<AllocatedScalar>,
+        poseidon_params: &PoseidonParams
+    ) -> Result<(), R1CSError> {
+        let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+        let mut leaf_val_lc: LinearCombination = leaf_val.variable.into();
+        for i in 0..depth {
+            let one_minus_leaf_side: LinearCombination = Variable::One() - leaf_index_bits[i].variable;
 
-        // Generate the MiMC round constants
-        /*let constants = (0..MIMC_ROUNDS).map(|_| Scalar::random(&mut test_rng)).collect::<Vec<_>>();
-        let mut tree = VanillaSparseMerkleTree::new(&constants);*/
+            let (_, _, left_1) = cs.multiply(one_minus_leaf_side.clone(), leaf_val_lc.clone());
+            let (_, _, left_2) = cs.multiply(leaf_index_bits[i].variable.into(), proof_nodes[i].variable.into());
+            let (_, _, right_1) = cs.multiply(leaf_index_bits[i].variable.into(), leaf_val_lc.clone());
+            let (_, _, right_2) = cs.multiply(one_minus_leaf_side, proof_nodes[i].variable.into());
 
+            leaf_val_lc = Poseidon_hash_2_constraints::<CS>(cs, left_1 + left_2, right_1 + right_2, statics.clone(),
+                                                            poseidon_params, &SboxType::Inverse)?;
+        }
+        constrain_lc_with_scalar::<CS>(cs, leaf_val_lc, root);
+        Ok(())
+    }
+
+    #[test]
+    fn test_VSMT_update_transition() {
         let width = 6;
         let (full_b, full_e) = (8, 8);
         let partial_rounds = 105;
-        let total_rounds = full_b + partial_rounds + full_e;
         let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
         let mut tree = VanillaSparseMerkleTree::new(&p_params);
 
@@ -278,124 +6228,194 @@ mod tests {
             tree.update(s, s);
         }
 
+        let k =  Scalar::from(7u32);
+        let old_val = k.clone();
+        let new_val = Scalar::from(100u32);
+
         let mut merkle_proof_vec = Vec::<Scalar>::new();
         let mut merkle_proof = Some(merkle_proof_vec);
-        let k =  Scalar::from(7u32);
-        assert_eq!(k, tree.get(k, &mut merkle_proof));
+        assert_eq!(old_val, tree.get(k, &mut merkle_proof));
         merkle_proof_vec = merkle_proof.unwrap();
-        assert!(tree.verify_proof(k, k, &merkle_proof_vec, None));
-        assert!(tree.verify_proof(k, k, &merkle_proof_vec, Some(&tree.root)));
 
-        let pc_gens = PedersenGens::default();
-        let gens_capacity = 1 << 15; // 2^15 is minimal
-        let bp_gens = BulletproofGens::new(gens_capacity, 1);
+        let old_root = tree.raw_root();
+        let new_root = tree.update(k, new_val);
+        assert_ne!(old_root, new_root);
+        assert!(tree.verify_proof(k, old_val, &merkle_proof_vec, Some(&MerkleRoot(old_root))));
+        assert!(tree.verify_proof(k, new_val, &merkle_proof_vec, Some(&MerkleRoot(new_root))));
 
-        let (proof, commitments) = {
-            let mut prover_transcript = Transcript::new(b"VSMT");
-            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+        let bp_gens = BulletproofGens::new(1 << 16, 1);
 
-            let (com_leaf, var_leaf) = prover.commit(k, Scalar::random(&mut test_rng));
-            let leaf_alloc_scalar = AllocatedScalar {
-                variable: var_leaf,
-                assignment: Some(k),
-            };
+        assert!(prove_and_verify!(b"VSMT_update", &p_params, &bp_gens, |cs, inputs, statics| {
+            let old_leaf = inputs.value(cs, old_val);
+            let new_leaf = inputs.value(cs, new_val);
+            let bits = inputs.bits(cs, &k, tree.proof_len());
+            let nodes = inputs.nodes(cs, &merkle_proof_vec);
+            merkle_update_transition_gadget(cs, tree.proof_len(), &old_root, &new_root, bits, old_leaf, new_leaf, nodes,
+                                            statics, &p_params)
+        }));
+    }
 
-            let mut leaf_index_comms = vec![];
-            let mut leaf_index_vars = vec![];
-            let mut leaf_index_alloc_scalars = vec![];
-            for b in get_bits(&k, TreeDepth).iter().take(tree.depth) {
-                let val: Scalar = Scalar::from(*b as u8);
-                let (c, v) = prover.commit(val.clone(), Scalar::random(&mut test_rng));
-                leaf_index_comms.push(c);
-                leaf_index_vars.push(v);
-                leaf_index_alloc_scalars.push(AllocatedScalar {
-                    variable: v,
-                    assignment: Some(val),
-                });
-            }
+    #[test]
+    fn test_transfer_gadget() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 16;
+        let n_bits = 32;
+        let (sender, recv) = (Scalar::from(1u32), Scalar::from(2u32));
 
-            let mut proof_comms = vec![];
-            let mut proof_vars = vec![];
-            let mut proof_alloc_scalars = vec![];
-            for p in merkle_proof_vec.iter().rev() {
-                let (c, v) = prover.commit(*p, Scalar::random(&mut test_rng));
-                proof_comms.push(c);
-                proof_vars.push(v);
-                proof_alloc_scalars.push(AllocatedScalar {
-                    variable: v,
-                    assignment: Some(*p),
-                });
-            }
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
 
-            let num_statics = 4;
-            let statics = allocate_statics_for_prover(&mut prover, num_statics);
+        let transfer = |sender_new: Scalar, recv_new: Scalar| -> bool {
+            let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+            tree.update(sender, Scalar::from(100u32));
+            tree.update(recv, Scalar::from(50u32));
+            tree.update(Scalar::from(3u32), Scalar::from(10u32));
 
-            let start = Instant::now();
-            assert!(vanilla_merkle_merkle_tree_verif_gadget(
-                &mut prover,
-                tree.depth,
-                &tree.root,
-                leaf_alloc_scalar,
-                leaf_index_alloc_scalars,
-                proof_alloc_scalars,
-                statics,
-                &p_params).is_ok());
+            let old_root = tree.raw_root();
+            let mut sender_proof = Some(Vec::<Scalar>::new());
+            let sender_old = tree.get(sender, &mut sender_proof);
+            tree.update(sender, sender_new);
+            let mut recv_proof = Some(Vec::<Scalar>::new());
+            let recv_old = tree.get(recv, &mut recv_proof);
+            let new_root = tree.update(recv, recv_new);
 
-//            println!("For tree height {} and MiMC rounds {}, no of constraints is {}", tree.depth, &MIMC_ROUNDS, &prover.num_constraints());
+            let (sender_proof, recv_proof) = (sender_proof.unwrap(), recv_proof.unwrap());
 
-            println!("For binary tree of height {} and Poseidon rounds {}, no of multipliers is {} and constraints is {}", tree.depth, total_rounds, &prover.num_multipliers(), &prover.num_constraints());
+            prove_and_verify!(b"VSMT_transfer", &p_params, &bp_gens, |cs, inputs, statics| {
+                let balances = inputs.values(cs, &[sender_old, sender_new, recv_old, recv_new]);
+                let sender_bits = inputs.bits(cs, &sender, depth);
+                let sender_nodes = inputs.nodes(cs, &sender_proof);
+                let recv_bits = inputs.bits(cs, &recv, depth);
+                let recv_nodes = inputs.nodes(cs, &recv_proof);
+                transfer_gadget(cs, depth, &old_root, &new_root, sender_bits, balances[0], balances[1], sender_nodes,
+                                recv_bits, balances[2], balances[3], recv_nodes, statics, &p_params, n_bits)
+            })
+        };
 
-            let proof = prover.prove(&bp_gens).unwrap();
-            let end = start.elapsed();
+        // Transfer 30
+        assert!(transfer(Scalar::from(70u32), Scalar::from(80u32)));
+        // Whole balance
+        assert!(transfer(Scalar::zero(), Scalar::from(150u32)));
+        // Receiver gets more than the sender gave
+        assert!(!transfer(Scalar::from(70u32), Scalar::from(90u32)));
+        // Sender's balance becomes negative
+        assert!(!transfer(-Scalar::from(20u32), Scalar::from(170u32)));
+        // Negative amount
+        assert!(!transfer(Scalar::from(110u32), Scalar::from(40u32)));
+    }
 
-            println!("Proving time is {:?}", end);
+    #[test]
+    fn test_monotonic_update_gadget() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
 
-            (proof, (com_leaf, leaf_index_comms, proof_comms))
-        };
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
 
-        let mut verifier_transcript = Transcript::new(b"VSMT");
-        let mut verifier = Verifier::new(&mut verifier_transcript);
-        let var_leaf = verifier.commit(commitments.0);
-        let leaf_alloc_scalar = AllocatedScalar {
-            variable: var_leaf,
-            assignment: None,
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+        let n_bits = 32;
+        let k = Scalar::from(7u32);
+
+        let mut update = |new_val: Scalar| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            let old_val = tree.get(k, &mut merkle_proof);
+            let merkle_proof = merkle_proof.unwrap();
+            let old_root = tree.raw_root();
+            let new_root = tree.update(k, new_val);
+            let depth = tree.proof_len();
+
+            prove_and_verify!(b"VSMT_monotonic", &p_params, &bp_gens, |cs, inputs, statics| {
+                let old_leaf = inputs.value(cs, old_val);
+                let new_leaf = inputs.value(cs, new_val);
+                let bits = inputs.bits(cs, &k, depth);
+                let nodes = inputs.nodes(cs, &merkle_proof);
+                monotonic_update_gadget(cs, depth, &old_root, &new_root, bits, old_leaf, new_leaf, nodes, statics,
+                                        &p_params, n_bits)
+            })
         };
 
-        let mut leaf_index_alloc_scalars = vec![];
-        for l in commitments.1 {
-            let v = verifier.commit(l);
-            leaf_index_alloc_scalars.push(AllocatedScalar {
-                variable: v,
-                assignment: None,
-            });
+        // 7 -> 100
+        assert!(update(Scalar::from(100u32)));
+        // Same value
+        assert!(update(Scalar::from(100u32)));
+        // 100 -> 3
+        assert!(!update(Scalar::from(3u32)));
+        // 3 -> 2^32 - 1
+        assert!(update(Scalar::from((1u64 << n_bits) - 1)));
+        // The increase fits in `n_bits` but the new leaf does not
+        assert!(!update(Scalar::from((1u64 << n_bits) + 1)));
+    }
+
+    #[test]
+    fn test_vanilla_sparse_merkle_tree_hashed_keys() {
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 140;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_hashed_keys(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
         }
 
-        let mut proof_alloc_scalars = vec![];
-        for p in commitments.2 {
-            let v = verifier.commit(p);
-            proof_alloc_scalars.push(AllocatedScalar {
-                variable: v,
-                assignment: None,
-            });
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            // The leaf commits to the key and the value
+            let leaf = Poseidon_hash_2(tree.key_position(&s), s, &p_params, &SboxType::Inverse);
+            assert_eq!(leaf, tree.get(s, &mut None));
+            let mut proof = Some(Vec::<Scalar>::new());
+            assert_eq!(leaf, tree.get(s, &mut proof));
+            assert!(tree.verify_proof(s, s, &proof.unwrap(), None));
+
+            let position = tree.key_position(&s);
+            assert_ne!(position, s);
+            assert_eq!(Some((s, s)), tree.get_index_at_position(&position));
         }
+    }
 
-        let num_statics = 4;
-        let statics = allocate_statics_for_verifier(&mut verifier, num_statics, &pc_gens);
+    #[test]
+    fn test_VSMT_hashed_keys_Verif() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree::new_hashed_keys(&p_params);
 
-        let start = Instant::now();
-        assert!(vanilla_merkle_merkle_tree_verif_gadget(
-            &mut verifier,
-            tree.depth,
-            &tree.root,
-            leaf_alloc_scalar,
-            leaf_index_alloc_scalars,
-            proof_alloc_scalars,
-            statics,
-            &p_params).is_ok());
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
 
-        assert!(verifier.verify(&proof, &pc_gens, &bp_gens).is_ok());
-        let end = start.elapsed();
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
 
-        println!("Verification time is {:?}", end);
+        // Prove that `val` is stored for `idx` using the position and merkle proof of `key`
+        let prove = |idx: Scalar, val: Scalar, key: Scalar| -> bool {
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(key, &mut merkle_proof);
+            let merkle_proof_vec = merkle_proof.unwrap();
+            let position = tree.key_position(&key);
+
+            prove_and_verify!(b"VSMT_hashed_keys", &p_params, &bp_gens, |cs, inputs, statics| {
+                let idx = inputs.value(cs, idx);
+                let val = inputs.value(cs, val);
+                let bits = inputs.bits(cs, &position, tree.proof_len());
+                let nodes = inputs.nodes(cs, &merkle_proof_vec);
+                hashed_key_membership_gadget(cs, tree.proof_len(), &tree.raw_root(), idx, val, bits, nodes, statics,
+                                             &p_params)
+            })
+        };
+
+        let k = Scalar::from(7u32);
+        assert!(prove(k, k, k));
+        // Wrong value
+        assert!(!prove(k, Scalar::from(8u32), k));
+        // Value of another key
+        assert!(!prove(k, Scalar::from(8u32), Scalar::from(8u32)));
+        assert!(!prove(Scalar::from(8u32), Scalar::from(8u32), k));
     }
-}
\ No newline at end of file
+}
@@ -311,6 +311,29 @@ pub fn vanilla_merkle_merkle_tree_4_verif_gadget<CS: ConstraintSystem>(
     Ok(())
 }
 
+/// Number of multipliers of `vanilla_merkle_merkle_tree_4_verif_gadget` with `params`, counted by synthesizing the
+/// circuit once with dummy values. The depth is always `TreeDepth`.
+pub fn num_membership_multipliers_4(params: &PoseidonParams) -> usize {
+    let pc_gens = PedersenGens::default();
+    let mut transcript = Transcript::new(b"VSMT4Sizing");
+    let mut prover = Prover::new(&pc_gens, &mut transcript);
+
+    let mut commit_zero = || {
+        let (_, v) = prover.commit(Scalar::zero(), Scalar::zero());
+        AllocatedScalar { variable: v, assignment: Some(Scalar::zero()) }
+    };
+    let leaf = commit_zero();
+    let leaf_index = commit_zero();
+    let proof_nodes = (0..3*TreeDepth).map(|_| commit_zero()).collect();
+    let statics = allocate_statics_for_prover(&mut prover, params.width - 4);
+
+    // Only the number of multipliers is needed so the root does not matter
+    vanilla_merkle_merkle_tree_4_verif_gadget(&mut prover, TreeDepth, &Scalar::zero(), leaf, leaf_index, proof_nodes,
+                                              statics, params).unwrap();
+
+    prover.num_multipliers()
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,497 @@
+extern crate rand;
+extern crate curve25519_dalek;
+extern crate merlin;
+extern crate bulletproofs;
+
+use std::collections::HashMap;
+use rand::SeedableRng;
+use rand::rngs::OsRng;
+use curve25519_dalek::scalar::Scalar;
+use bulletproofs::r1cs::{ConstraintSystem, R1CSError, R1CSProof, Variable, Prover, Verifier};
+use bulletproofs::{BulletproofGens, PedersenGens};
+use merlin::Transcript;
+use bulletproofs::r1cs::LinearCombination;
+
+use crate::scalar_utils::{ScalarBytes, ScalarBits, index_fits, scalar_bytes_from_canonical};
+use crate::r1cs_utils::{AllocatedScalar, constrain_lc_with_scalar};
+use crate::errors::GadgetError;
+use crate::gadget_poseidon::{PoseidonParams, Poseidon_hash_8, Poseidon_hash_8_constraints, SboxType};
+use crate::gadget_vsmt_2::constrain_leaf_index_bits;
+
+/*
+    Sparse merkle tree with width 8. Compared to the trees of width 2 and 4, each node is more expensive to hash as
+    the 8 children do not fit in a single Poseidon permutation of width 6 and need 2 permutations, but there are fewer
+    levels (11 rather than 32 or 16 for 2^32 leaves). Which tree has the fewest constraints depends on the width of
+    the Poseidon permutation, `test_compare_arity` prints the multipliers needed by each tree.
+*/
+
+type DBVal = [Scalar; 8];
+type ProofNode = [Scalar; 7];
+
+/// Depth of the tree. 8ˆ11=2ˆ33
+pub const TreeDepth: usize = 11;
+
+/// Number of bits to represent leaf index, 3 bits for each level
+pub const LeafIndexBits: usize = TreeDepth * 3;
+
+// TODO: ABSTRACT HASH FUNCTION BETTER
+/// Sparse merkle tree with width 8, .i.e each node has 8 children.
+pub struct VanillaSparseMerkleTree8<'a> {
+    pub depth: usize,
+    empty_tree_hashes: Vec<Scalar>,
+    db: HashMap<ScalarBytes, DBVal>,
+    hash_params: &'a PoseidonParams,
+    pub root: Scalar
+}
+
+impl<'a> VanillaSparseMerkleTree8<'a> {
+    pub fn new(hash_params: &'a PoseidonParams) -> VanillaSparseMerkleTree8<'a> {
+        let depth = TreeDepth;
+        let mut db = HashMap::new();
+        let mut empty_tree_hashes: Vec<Scalar> = vec![];
+        empty_tree_hashes.push(Scalar::zero());
+        for i in 1..=depth {
+            let prev = empty_tree_hashes[i-1];
+            let input: [Scalar; 8] = [prev.clone(); 8];
+            // Hash all 8 children at once
+            let new = Poseidon_hash_8(input.clone(), hash_params, &SboxType::Inverse);
+            let key = new.to_bytes();
+
+            db.insert(key, input);
+            empty_tree_hashes.push(new);
+        }
+
+        let root = empty_tree_hashes[depth].clone();
+
+        VanillaSparseMerkleTree8 {
+            depth,
+            empty_tree_hashes,
+            db,
+            hash_params,
+            root
+        }
+    }
+
+    pub fn update(&mut self, idx: Scalar, val: Scalar) -> Scalar {
+
+        // Find path to insert the new key
+        let mut sidenodes_wrap = Some(Vec::<ProofNode>::new());
+        self.get(idx, &mut sidenodes_wrap);
+        let mut sidenodes = sidenodes_wrap.unwrap();
+
+        // Base 8 digits of leaf index starting from the leaf
+        let mut cur_idx = get_base_8_digits(&idx);
+        cur_idx.reverse();
+        let mut cur_val = val.clone();
+
+        for d in cur_idx {
+            let mut side_elem = sidenodes.pop().unwrap().to_vec();
+            // Insert the value at the position determined by the base 8 digit
+            side_elem.insert(d as usize, cur_val);
+
+            let mut input: DBVal = [Scalar::zero(); 8];
+            input.copy_from_slice(side_elem.as_slice());
+            let h = Poseidon_hash_8(input.clone(), self.hash_params, &SboxType::Inverse);
+            self.update_db_with_key_val(h, input);
+            cur_val = h;
+        }
+
+        self.root = cur_val;
+
+        cur_val
+    }
+
+    /// Get a value from tree, if `proof` is not None, populate `proof` with the merkle proof. Panics if `idx` does not
+    /// fit in the tree.
+    pub fn get(&self, idx: Scalar, proof: &mut Option<Vec<ProofNode>>) -> Scalar {
+        assert!(self.index_in_range(&idx), "index does not fit in a tree of depth {}", self.depth);
+        let cur_idx = get_base_8_digits(&idx);
+        let mut cur_node = self.root.clone();
+
+        let need_proof = proof.is_some();
+        let mut proof_vec = Vec::<ProofNode>::new();
+
+        for d in cur_idx {
+            let children = self.get_node(&cur_node);
+            cur_node = children[d as usize];
+            if need_proof {
+                let mut proof_node: ProofNode = [Scalar::zero(); 7];
+                let mut j = 0;
+                for (i, c) in children.iter().enumerate() {
+                    if i != (d as usize) {
+                        proof_node[j] = c.clone();
+                        j += 1;
+                    }
+                }
+                proof_vec.push(proof_node);
+            }
+        }
+
+        match proof {
+            Some(v) => {
+                v.extend_from_slice(&proof_vec);
+            }
+            None => ()
+        }
+
+        cur_node
+    }
+
+    /// Verify a merkle proof, if `root` is None, use the current root else use given root. A proof of the wrong length
+    /// or an index that does not fit in the tree fails.
+    pub fn verify_proof(&self, idx: Scalar, val: Scalar, proof: &[ProofNode], root: Option<&Scalar>) -> bool {
+        if proof.len() != self.depth || !self.index_in_range(&idx) {
+            return false;
+        }
+        let mut cur_idx = get_base_8_digits(&idx);
+        cur_idx.reverse();
+        let mut cur_val = val.clone();
+
+        for (i, d) in cur_idx.iter().enumerate() {
+            let mut p = proof[self.depth-1-i].clone().to_vec();
+            p.insert(*d as usize, cur_val);
+            let mut input: DBVal = [Scalar::zero(); 8];
+            input.copy_from_slice(p.as_slice());
+            cur_val = Poseidon_hash_8(input, self.hash_params, &SboxType::Inverse);
+        }
+
+        // Check if root is equal to cur_val
+        match root {
+            Some(r) => {
+                cur_val == *r
+            }
+            None => {
+                cur_val == self.root
+            }
+        }
+    }
+
+    /// Whether `idx` is the index of a leaf, i.e. has no bits set above the 3 bits of each level. Larger indices would
+    /// be truncated to the index of another leaf.
+    fn index_in_range(&self, idx: &Scalar) -> bool {
+        index_fits(idx, 3 * self.depth)
+    }
+
+    fn get_node(&self, node: &Scalar) -> &DBVal {
+        let k = scalar_bytes_from_canonical(node.to_bytes()).expect("node is not a canonical scalar");
+        self.db.get(&k).unwrap()
+    }
+
+    fn update_db_with_key_val(&mut self, key: Scalar, val: DBVal) {
+        self.db.insert(key.to_bytes(), val);
+    }
+}
+
+/// Base 8 digits of the leaf index, the most significant digit (the one for the root) first.
+fn get_base_8_digits(idx: &Scalar) -> Vec<u8> {
+    let bits = ScalarBits::from_scalar(idx, LeafIndexBits);
+    (0..TreeDepth).rev().map(|i| {
+        let b = bits.slice(3*i, 3);
+        b[0] + 2*b[1] + 4*b[2]
+    }).collect()
+}
+
+/*
+    The position p of the hidden node (node in path to leaf) among the 8 children is given by 3 bits of the
+    leaf index, p = 4*b2 + 2*b1 + b0. Selector s_p is 1 for the position of the hidden node and 0 for the others.
+
+    s_0 = (1-b0)*(1-b1)*(1-b2), s_1 = b0*(1-b1)*(1-b2), ..., s_7 = b0*b1*b2
+
+    Proof elements are the 7 other children in order P0, P1, .. P6. The hidden node N goes at position p and the
+    proof elements before p stay where they are and the ones after p move one position right. So child c_j is
+
+    c_j = s_j*N + G_j*P_j + L_j*P_(j-1)
+
+    where G_j = s_(j+1) + .. + s_7 is 1 when the hidden node is after j and L_j = s_0 + .. + s_(j-1) is 1
+    when the hidden node is before j.
+*/
+pub fn vanilla_merkle_merkle_tree_8_verif_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    leaf_val: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    if depth == 0 {
+        return Err(GadgetError::InvalidDepth(depth));
+    }
+    if leaf_index_bits.len() != 3*depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: 3*depth, found: leaf_index_bits.len() });
+    }
+    if proof_nodes.len() != 7*depth {
+        return Err(GadgetError::ProofLengthMismatch { expected: 7*depth, found: proof_nodes.len() });
+    }
+
+    constrain_leaf_index_bits(cs, &leaf_index_bits)?;
+
+    let mut prev_hash = LinearCombination::from(leaf_val.variable);
+
+    for i in 0..depth {
+        let b0 = leaf_index_bits[3*i].variable;
+        let b1 = leaf_index_bits[3*i + 1].variable;
+        let b2 = leaf_index_bits[3*i + 2].variable;
+
+        // Selectors for the lower 2 bits
+        let (_, _, b0_1_b1_1) = cs.multiply(Variable::One() - b0, Variable::One() - b1);
+        let (_, _, b0_b1_1) = cs.multiply(b0.into(), Variable::One() - b1);
+        let (_, _, b0_1_b1) = cs.multiply(Variable::One() - b0, b1.into());
+        let (_, _, b0_b1) = cs.multiply(b0.into(), b1.into());
+        let low = [b0_1_b1_1, b0_b1_1, b0_1_b1, b0_b1];
+
+        // s_p = low_p * (1 - b2) and s_(p+4) = low_p * b2 = low_p - s_p
+        let mut selectors: Vec<LinearCombination> = vec![LinearCombination::default(); 8];
+        for p in 0..4 {
+            let (_, _, s) = cs.multiply(low[p].into(), Variable::One() - b2);
+            selectors[p] = s.into();
+            selectors[p+4] = low[p] - s;
+        }
+
+        let level_proof_nodes: Vec<LinearCombination> = proof_nodes[7*i..7*(i+1)].iter().map(|n| n.variable.into()).collect();
+
+        let mut children: [LinearCombination; 8] = [LinearCombination::default(), LinearCombination::default(),
+            LinearCombination::default(), LinearCombination::default(), LinearCombination::default(),
+            LinearCombination::default(), LinearCombination::default(), LinearCombination::default()];
+        for j in 0..8 {
+            // s_j*N
+            let (_, _, c) = cs.multiply(selectors[j].clone(), prev_hash.clone());
+            let mut child = LinearCombination::from(c);
+            if j < 7 {
+                // G_j*P_j
+                let g = selectors[j+1..].iter().fold(LinearCombination::default(), |acc, s| acc + s.clone());
+                let (_, _, c) = cs.multiply(g, level_proof_nodes[j].clone());
+                child = child + c;
+            }
+            if j > 0 {
+                // L_j*P_(j-1)
+                let l = selectors[..j].iter().fold(LinearCombination::default(), |acc, s| acc + s.clone());
+                let (_, _, c) = cs.multiply(l, level_proof_nodes[j-1].clone());
+                child = child + c;
+            }
+            children[j] = child;
+        }
+
+        prev_hash = Poseidon_hash_8_constraints::<CS>(cs, children, poseidon_params, &SboxType::Inverse)?;
+    }
+
+    constrain_lc_with_scalar::<CS>(cs, prev_hash, root);
+
+    Ok(())
+}
+
+/// Number of multipliers of `vanilla_merkle_merkle_tree_8_verif_gadget` for a tree of the given `depth` hashed with
+/// `params`, counted by synthesizing the circuit once with dummy values.
+pub fn num_membership_multipliers_8(depth: usize, params: &PoseidonParams) -> usize {
+    assert!(depth > 0);
+    let pc_gens = PedersenGens::default();
+    let mut transcript = Transcript::new(b"VSMT8Sizing");
+    let mut prover = Prover::new(&pc_gens, &mut transcript);
+
+    let mut commit_zero = || {
+        let (_, v) = prover.commit(Scalar::zero(), Scalar::zero());
+        AllocatedScalar { variable: v, assignment: Some(Scalar::zero()) }
+    };
+    let leaf = commit_zero();
+    let leaf_index_bits = (0..3*depth).map(|_| commit_zero()).collect();
+    let proof_nodes = (0..7*depth).map(|_| commit_zero()).collect();
+
+    // Only the number of multipliers is needed so the root does not matter
+    vanilla_merkle_merkle_tree_8_verif_gadget(&mut prover, depth, &Scalar::zero(), leaf, leaf_index_bits, proof_nodes,
+                                              params).unwrap();
+
+    prover.num_multipliers()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merlin::Transcript;
+    use rand::SeedableRng;
+    use super::rand::rngs::StdRng;
+    use crate::scalar_utils::{get_bits, bits_to_scalar};
+    use crate::gadget_vsmt_2::{VanillaSparseMerkleTree, vanilla_merkle_merkle_tree_verif_gadget};
+    use crate::gadget_vsmt_4::{VanillaSparseMerkleTree_4, vanilla_merkle_merkle_tree_4_verif_gadget};
+    use crate::gadget_poseidon::{allocate_statics_for_prover};
+    // For benchmarking
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_vanilla_sparse_merkle_tree_8() {
+        let mut test_rng: OsRng = OsRng::default();
+
+        let width = 6;
+        let (full_b, full_e) = (4, 4);
+        let partial_rounds = 6;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree8::new(&p_params);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            assert_eq!(s, tree.get(s, &mut None));
+            let mut proof_vec = Vec::<ProofNode>::new();
+            let mut proof = Some(proof_vec);
+            assert_eq!(s, tree.get(s, &mut proof));
+            proof_vec = proof.unwrap();
+            assert!(tree.verify_proof(s, s, &proof_vec, None));
+            assert!(tree.verify_proof(s, s, &proof_vec, Some(&tree.root)));
+        }
+
+        let kvs: Vec<(Scalar, Scalar)> = (0..10).map(|_| {
+            (bits_to_scalar(&get_bits(&Scalar::random(&mut test_rng), LeafIndexBits)), Scalar::random(&mut test_rng))
+        }).collect();
+        for i in 0..kvs.len() {
+            tree.update(kvs[i].0, kvs[i].1);
+        }
+
+        for i in 0..kvs.len() {
+            assert_eq!(kvs[i].1, tree.get(kvs[i].0, &mut None));
+        }
+
+        let s = Scalar::from(5u32);
+        let mut proof = Some(Vec::<ProofNode>::new());
+        tree.get(s, &mut proof);
+        let proof_vec = proof.unwrap();
+        assert!(tree.verify_proof(s, s, &proof_vec, None));
+        // Short proof
+        assert!(!tree.verify_proof(s, s, &proof_vec[1..], None));
+        assert!(!tree.verify_proof(s, s, &[], None));
+        // Index with the same lower bits as `s`
+        let aliased = s + Scalar::from(1u64 << LeafIndexBits);
+        assert!(!tree.verify_proof(aliased, s, &proof_vec, None));
+    }
+
+    #[test]
+    #[should_panic(expected = "index does not fit")]
+    fn test_get_index_out_of_range_8() {
+        let p_params = PoseidonParams::new(6, 4, 4, 6);
+        let tree = VanillaSparseMerkleTree8::new(&p_params);
+        tree.get(Scalar::from(1u64 << LeafIndexBits), &mut None);
+    }
+
+    #[test]
+    fn test_VSMT_8_Verif() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let mut tree = VanillaSparseMerkleTree8::new(&p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let bp_gens = BulletproofGens::new(1 << 15, 1);
+
+        let prove = |k: Scalar, leaf: Scalar| -> bool {
+            let mut merkle_proof = Some(Vec::<ProofNode>::new());
+            tree.get(k, &mut merkle_proof);
+            // Proof nodes from the leaf to the root
+            let proof_nodes: Vec<Scalar> = merkle_proof.unwrap().iter().rev().flat_map(|p| p.to_vec()).collect();
+
+            prove_and_verify!(b"VSMT_8", &bp_gens, |cs, inputs| {
+                let leaf = inputs.value(cs, leaf);
+                let bits = inputs.bits(cs, &k, LeafIndexBits);
+                let nodes = inputs.values(cs, &proof_nodes);
+                vanilla_merkle_merkle_tree_8_verif_gadget(cs, tree.depth, &tree.root, leaf, bits, nodes, &p_params)
+            })
+        };
+
+        let k = Scalar::from(7u32);
+        assert!(prove(k, k));
+        assert!(!prove(k, k + Scalar::one()));
+    }
+
+    /// Print the number of multipliers for proving membership in trees of width 2, 4 and 8 with 2^32 leaves or more.
+    #[test]
+    fn test_compare_arity() {
+        let width = 6;
+        let (full_b, full_e) = (8, 8);
+        let partial_rounds = 105;
+        let p_params = PoseidonParams::new(width, full_b, full_e, partial_rounds);
+        let pc_gens = PedersenGens::default();
+        let k = Scalar::from(7u32);
+
+        {
+            let mut tree = VanillaSparseMerkleTree::new(&p_params);
+            tree.update(k, k);
+            let mut merkle_proof = Some(Vec::<Scalar>::new());
+            tree.get(k, &mut merkle_proof);
+
+            let mut prover_transcript = Transcript::new(b"VSMT");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+            let (_, var_leaf) = prover.commit(k, Scalar::zero());
+            let leaf = AllocatedScalar { variable: var_leaf, assignment: Some(k) };
+            let index_bits = get_bits(&k, tree.depth).iter().map(|b| {
+                let (_, v) = prover.commit(Scalar::from(*b), Scalar::zero());
+                AllocatedScalar { variable: v, assignment: Some(Scalar::from(*b)) }
+            }).collect();
+            let proof_nodes = merkle_proof.unwrap().iter().rev().map(|p| {
+                let (_, v) = prover.commit(*p, Scalar::zero());
+                AllocatedScalar { variable: v, assignment: Some(*p) }
+            }).collect();
+            let statics = allocate_statics_for_prover(&mut prover, 4);
+            assert!(vanilla_merkle_merkle_tree_verif_gadget(&mut prover, tree.depth, &tree.raw_root(), leaf,
+                                                            index_bits, proof_nodes, statics, &p_params).is_ok());
+            println!("2-ary tree of height {}, no of multipliers is {}", tree.depth, prover.num_multipliers());
+        }
+
+        {
+            let mut tree = VanillaSparseMerkleTree_4::new(&p_params);
+            tree.update(k, k);
+            let mut merkle_proof = Some(Vec::<[Scalar; 3]>::new());
+            tree.get(k, &mut merkle_proof);
+
+            let mut prover_transcript = Transcript::new(b"VSMT");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+            let (_, var_leaf) = prover.commit(k, Scalar::zero());
+            let leaf = AllocatedScalar { variable: var_leaf, assignment: Some(k) };
+            let (_, var_idx) = prover.commit(k, Scalar::zero());
+            let leaf_idx = AllocatedScalar { variable: var_idx, assignment: Some(k) };
+            let mut proof_nodes = vec![];
+            for p in merkle_proof.unwrap().iter() {
+                for i in p {
+                    let (_, v) = prover.commit(*i, Scalar::zero());
+                    proof_nodes.push(AllocatedScalar { variable: v, assignment: Some(*i) });
+                }
+            }
+            let statics = allocate_statics_for_prover(&mut prover, 2);
+            assert!(vanilla_merkle_merkle_tree_4_verif_gadget(&mut prover, tree.depth, &tree.root, leaf,
+                                                              leaf_idx, proof_nodes, statics, &p_params).is_ok());
+            println!("4-ary tree of height {}, no of multipliers is {}", tree.depth, prover.num_multipliers());
+        }
+
+        {
+            let mut tree = VanillaSparseMerkleTree8::new(&p_params);
+            tree.update(k, k);
+            let mut merkle_proof = Some(Vec::<ProofNode>::new());
+            tree.get(k, &mut merkle_proof);
+
+            let mut prover_transcript = Transcript::new(b"VSMT");
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+            let (_, var_leaf) = prover.commit(k, Scalar::zero());
+            let leaf = AllocatedScalar { variable: var_leaf, assignment: Some(k) };
+            let index_bits = get_bits(&k, LeafIndexBits).iter().map(|b| {
+                let (_, v) = prover.commit(Scalar::from(*b), Scalar::zero());
+                AllocatedScalar { variable: v, assignment: Some(Scalar::from(*b)) }
+            }).collect();
+            let mut proof_nodes = vec![];
+            for p in merkle_proof.unwrap().iter().rev() {
+                for i in p {
+                    let (_, v) = prover.commit(*i, Scalar::zero());
+                    proof_nodes.push(AllocatedScalar { variable: v, assignment: Some(*i) });
+                }
+            }
+            assert!(vanilla_merkle_merkle_tree_8_verif_gadget(&mut prover, tree.depth, &tree.root, leaf,
+                                                              index_bits, proof_nodes, &p_params).is_ok());
+            println!("8-ary tree of height {}, no of multipliers is {}", tree.depth, prover.num_multipliers());
+        }
+    }
+}
@@ -65,6 +65,23 @@ pub fn is_nonzero_gadget<CS: ConstraintSystem>(
     Ok(())
 }
 
+/// Enforces that `leaf` is not 0, i.e. the slot of the tree is occupied. Unlike `is_nonzero_gadget` the inverse is not
+/// committed to but allocated by the gadget, `leaf * inv = 1` can only be satisfied when `leaf` is invertible.
+pub fn leaf_nonzero_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    leaf: AllocatedScalar,
+) -> Result<(), R1CSError> {
+    let (l, _, o) = cs.allocate_multiplier(leaf.assignment.map(|x| (x, x.invert())))?;
+
+    // Allocated variable should be the leaf
+    cs.constrain(l - leaf.variable);
+
+    // leaf * inv = 1
+    cs.constrain(o - Variable::One());
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,207 @@
+use curve25519_dalek::scalar::Scalar;
+
+use crate::scalar_utils::{ScalarBits, ScalarBytes, get_bits};
+use crate::gadget_poseidon::{PoseidonParams, Poseidon_hash_2, SboxType};
+
+/// Operations the tree traversal needs on the nodes, so that it does not depend on the scalar field of Ristretto.
+/// `VanillaSparseMerkleTree` goes through it for the empty leaf, the keys of its store and `traverse_path` and
+/// `hash_path`. The gadgets still need `Scalar` as the constraint systems of bulletproofs are over that field.
+pub trait TreeNode: Copy + Eq {
+    /// Value of an empty leaf
+    fn zero() -> Self;
+
+    /// Canonical 32 byte encoding, used as key of the node in the store
+    fn to_bytes(&self) -> ScalarBytes;
+
+    /// Inverse of `to_bytes`, None if `bytes` is not a canonical encoding
+    fn from_canonical_bytes(bytes: ScalarBytes) -> Option<Self>;
+}
+
+impl TreeNode for Scalar {
+    fn zero() -> Self {
+        Scalar::zero()
+    }
+
+    fn to_bytes(&self) -> ScalarBytes {
+        Scalar::to_bytes(self)
+    }
+
+    fn from_canonical_bytes(bytes: ScalarBytes) -> Option<Self> {
+        Scalar::from_canonical_bytes(bytes)
+    }
+}
+
+impl TreeNode for ScalarBytes {
+    fn zero() -> Self {
+        [0u8; 32]
+    }
+
+    fn to_bytes(&self) -> ScalarBytes {
+        *self
+    }
+
+    fn from_canonical_bytes(bytes: ScalarBytes) -> Option<Self> {
+        Some(bytes)
+    }
+}
+
+/// Native 2-to-1 hash of the nodes of a merkle tree. Only used outside the circuit, so trees over hash functions
+/// without a gadget, like `VanillaSparseMerkleTreeSha`, share the traversal with the Poseidon tree.
+pub trait HashFunction {
+    type Node: TreeNode;
+
+    /// Value of an empty leaf
+    fn empty_leaf(&self) -> Self::Node {
+        Self::Node::zero()
+    }
+
+    fn hash_2(&self, left: &Self::Node, right: &Self::Node) -> Self::Node;
+}
+
+/// Poseidon with the inverse S-box as used by `VanillaSparseMerkleTree`
+pub struct PoseidonHash<'a>(pub &'a PoseidonParams);
+
+impl<'a> HashFunction for PoseidonHash<'a> {
+    type Node = Scalar;
+
+    fn hash_2(&self, left: &Scalar, right: &Scalar) -> Scalar {
+        Poseidon_hash_2(left.clone(), right.clone(), self.0, &SboxType::Inverse)
+    }
+}
+
+/// Roots of empty subtrees of height 0 to `depth`
+pub fn empty_tree_hashes<H: HashFunction>(hasher: &H, depth: usize) -> Vec<H::Node> {
+    let mut hashes = vec![hasher.empty_leaf()];
+    for i in 1..=depth {
+        let prev = hashes[i-1];
+        hashes.push(hasher.hash_2(&prev, &prev));
+    }
+    hashes
+}
+
+/// Compute the root of the tree from the leaf at `idx` and its merkle proof, the proof node for the root's level first.
+pub fn merkle_root_from_path<H: HashFunction>(hasher: &H, idx: &Scalar, leaf: H::Node, proof: &[H::Node], depth: usize) -> H::Node {
+    let mut cur_idx = ScalarBits::from_scalar(idx, depth);
+    let mut cur_val = leaf;
+
+    for i in 0..depth {
+        cur_val = {
+            if cur_idx.is_lsb_set() {
+                hasher.hash_2(&proof[depth-1-i], &cur_val)
+            } else {
+                hasher.hash_2(&cur_val, &proof[depth-1-i])
+            }
+        };
+
+        cur_idx.shr();
+    }
+
+    cur_val
+}
+
+/// Binary path from the root to the leaf at `idx`, i.e. the lower `depth` bits of `idx` MSB first, 1 for the right child
+pub fn index_path(idx: &Scalar, depth: usize) -> Vec<u8> {
+    let mut path = get_bits(&idx.reduce(), depth);
+    path.reverse();
+    path
+}
+
+/// Node reached by following `path` from `root`, 0 to go left and 1 to go right, where `children` returns the children
+/// of a node. If `proof` is not None, it is populated with the sibling at each level, the one for the root's level first.
+pub fn traverse_path<N: TreeNode, F: FnMut(&N) -> (N, N)>(root: N, path: &[u8], mut children: F,
+                                                          proof: &mut Option<Vec<N>>) -> N {
+    let mut cur_node = root;
+
+    for b in path {
+        let v = children(&cur_node);
+        if *b == 1 {
+            // Traverse to right subtree
+            cur_node = v.1;
+            if let Some(p) = proof { p.push(v.0); }
+        } else {
+            // Traverse to left subtree
+            cur_node = v.0;
+            if let Some(p) = proof { p.push(v.1); }
+        }
+    }
+
+    cur_node
+}
+
+/// Hash `leaf` at the end of `path` with its siblings `sidenodes`, as returned by `traverse_path`, up to the root and
+/// return the root. `store` is called with each new node and its children so the tree can keep them.
+pub fn hash_path<H: HashFunction, F: FnMut(H::Node, (H::Node, H::Node))>(hasher: &H, path: &[u8], leaf: H::Node,
+                                                                        sidenodes: &[H::Node], mut store: F) -> H::Node {
+    let mut cur_val = leaf;
+
+    // Go up from the leaf
+    for (child, side_elem) in path.iter().rev().zip(sidenodes.iter().rev()) {
+        let children = if *child == 1 {
+            // Current node is the right child
+            (*side_elem, cur_val)
+        } else {
+            // Current node is the left child
+            (cur_val, *side_elem)
+        };
+        cur_val = hasher.hash_2(&children.0, &children.1);
+        store(cur_val, children);
+    }
+
+    cur_val
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use rand::rngs::OsRng;
+    use curve25519_dalek::constants::BASEPOINT_ORDER;
+
+    #[test]
+    fn test_tree_node() {
+        let mut csprng: OsRng = OsRng::default();
+        for _ in 0..10 {
+            let s = Scalar::random(&mut csprng);
+            let bytes = TreeNode::to_bytes(&s);
+            assert_eq!(<Scalar as TreeNode>::from_canonical_bytes(bytes), Some(s));
+            assert_eq!(<ScalarBytes as TreeNode>::from_canonical_bytes(bytes), Some(bytes));
+        }
+        assert_eq!(<Scalar as TreeNode>::from_canonical_bytes(BASEPOINT_ORDER.to_bytes()), None);
+
+        let p_params = PoseidonParams::new(6, 4, 4, 140);
+        let hasher = PoseidonHash(&p_params);
+        assert_eq!(hasher.empty_leaf(), Scalar::zero());
+        let hashes = empty_tree_hashes(&hasher, 3);
+        assert_eq!(hashes[0], <Scalar as TreeNode>::zero());
+        assert_eq!(hashes[1], hasher.hash_2(&Scalar::zero(), &Scalar::zero()));
+    }
+
+    #[test]
+    fn test_traverse_and_hash_path() {
+        let p_params = PoseidonParams::new(6, 4, 4, 140);
+        let hasher = PoseidonHash(&p_params);
+        let depth = 3;
+        let hashes = empty_tree_hashes(&hasher, depth);
+        let mut db = HashMap::new();
+        for i in 1..=depth {
+            db.insert(hashes[i].to_bytes(), (hashes[i-1], hashes[i-1]));
+        }
+
+        let idx = Scalar::from(5u32);
+        let path = index_path(&idx, depth);
+        assert_eq!(path, vec![1, 0, 1]);
+
+        let mut sidenodes = Some(vec![]);
+        assert_eq!(traverse_path(hashes[depth], &path, |n| db[&n.to_bytes()], &mut sidenodes), Scalar::zero());
+        let sidenodes = sidenodes.unwrap();
+        assert_eq!(sidenodes, hashes[..depth].iter().rev().cloned().collect::<Vec<_>>());
+
+        let leaf = Scalar::from(10u32);
+        let root = hash_path(&hasher, &path, leaf, &sidenodes, |h, children| { db.insert(h.to_bytes(), children); });
+        assert_eq!(root, merkle_root_from_path(&hasher, &idx, leaf, &sidenodes, depth));
+
+        let mut proof = Some(vec![]);
+        assert_eq!(traverse_path(root, &path, |n| db[&n.to_bytes()], &mut proof), leaf);
+        assert_eq!(proof.unwrap(), sidenodes);
+    }
+}
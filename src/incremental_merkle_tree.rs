@@ -0,0 +1,197 @@
+use curve25519_dalek::scalar::Scalar;
+use bulletproofs::r1cs::ConstraintSystem;
+
+use crate::errors::GadgetError;
+use crate::gadget_poseidon::PoseidonParams;
+use crate::gadget_vsmt_2::{TreeDepth, compute_root_from_proof, vanilla_merkle_merkle_tree_verif_gadget};
+use crate::hash_function::{HashFunction, PoseidonHash, empty_tree_hashes};
+use crate::r1cs_utils::AllocatedScalar;
+
+/// Append-only merkle tree where leaves are inserted at increasing indices starting from 0. Only the root and the
+/// "frontier", i.e. the last left node filled at each height, are kept so memory does not grow with the number of
+/// leaves. Nodes are hashed the same way as in `VanillaSparseMerkleTree` with empty leaves as 0, so the root is the
+/// same as of a sparse tree of the same depth with the same leaves and membership is proven with
+/// `incremental_tree_membership_gadget`. Since the tree does not keep the leaves, merkle proofs are built by
+/// `proof_from_leaves` from the leaves kept elsewhere, e.g. published by whoever appended them.
+pub struct IncrementalMerkleTree<'a> {
+    pub depth: usize,
+    /// Index the next leaf is appended at, also the number of leaves
+    next_index: usize,
+    /// Element at index i is the last node at height i that is a left child, meaningful only where a left node has
+    /// been filled
+    frontier: Vec<Scalar>,
+    empty_tree_hashes: Vec<Scalar>,
+    root: Scalar,
+    hash_params: &'a PoseidonParams
+}
+
+impl<'a> IncrementalMerkleTree<'a> {
+    /// Create an empty tree of the given depth. `depth` should not be more than `TreeDepth`.
+    pub fn new(depth: usize, hash_params: &'a PoseidonParams) -> IncrementalMerkleTree<'a> {
+        assert!(depth <= TreeDepth);
+        let empty_tree_hashes = empty_tree_hashes(&PoseidonHash(hash_params), depth);
+        let root = empty_tree_hashes[depth];
+        IncrementalMerkleTree {
+            depth,
+            next_index: 0,
+            frontier: empty_tree_hashes[..depth].to_vec(),
+            empty_tree_hashes,
+            root,
+            hash_params
+        }
+    }
+
+    pub fn root(&self) -> Scalar {
+        self.root
+    }
+
+    /// Number of leaves appended so far
+    pub fn num_leaves(&self) -> usize {
+        self.next_index
+    }
+
+    pub fn is_full(&self) -> bool {
+        // In u64 since the shift overflows usize on 32-bit targets for a tree of depth `TreeDepth`
+        self.next_index as u64 == 1u64 << self.depth
+    }
+
+    /// Add `leaf` after the last leaf, update the root and return the index of `leaf`. Panics if the tree is full.
+    pub fn append(&mut self, leaf: Scalar) -> usize {
+        assert!(!self.is_full(), "tree of depth {} is full", self.depth);
+        let hasher = PoseidonHash(self.hash_params);
+        let index = self.next_index;
+
+        let mut cur_idx = index;
+        let mut cur_val = leaf;
+        for i in 0..self.depth {
+            cur_val = if cur_idx & 1 == 0 {
+                // Left child, the right subtree is still empty
+                self.frontier[i] = cur_val;
+                hasher.hash_2(&cur_val, &self.empty_tree_hashes[i])
+            } else {
+                hasher.hash_2(&self.frontier[i], &cur_val)
+            };
+            cur_idx >>= 1;
+        }
+
+        self.root = cur_val;
+        self.next_index += 1;
+        index
+    }
+
+    /// Merkle proof of the leaf at `index` in the same order as returned by `VanillaSparseMerkleTree::get`, i.e. the
+    /// proof node for the root's level first. `leaves` must be all leaves appended to the tree, in order. None if
+    /// `index` is not less than the number of leaves or `leaves` do not give the current root.
+    pub fn proof_from_leaves(&self, leaves: &[Scalar], index: usize) -> Option<Vec<Scalar>> {
+        if index >= self.next_index || leaves.len() != self.next_index {
+            return None;
+        }
+
+        let hasher = PoseidonHash(self.hash_params);
+        let mut level = leaves.to_vec();
+        let mut proof = Vec::with_capacity(self.depth);
+        for i in 0..self.depth {
+            let sibling = level.get((index >> i) ^ 1).cloned().unwrap_or(self.empty_tree_hashes[i]);
+            proof.push(sibling);
+            level = level.chunks(2).map(|c| {
+                hasher.hash_2(&c[0], c.get(1).unwrap_or(&self.empty_tree_hashes[i]))
+            }).collect();
+        }
+
+        if level[0] != self.root {
+            return None;
+        }
+        proof.reverse();
+        Some(proof)
+    }
+
+    /// Verify a proof returned by `proof_from_leaves` against the current root. An `index` that does not fit in the
+    /// tree fails.
+    pub fn verify_proof(&self, index: usize, val: Scalar, proof: &[Scalar]) -> bool {
+        if index as u64 >= 1u64 << self.depth {
+            return false;
+        }
+        compute_root_from_proof(Scalar::from(index as u64), val, proof, self.hash_params, self.depth) == Some(self.root)
+    }
+}
+
+/// Enforces that `leaf_val` is at the index given by `leaf_index_bits` (LSB first) in an `IncrementalMerkleTree` with
+/// `root`. `proof_nodes` are from leaf to root, i.e. the reverse of `proof_from_leaves`. As the tree is hashed like
+/// `VanillaSparseMerkleTree`, the constraints are those of `vanilla_merkle_merkle_tree_verif_gadget`.
+pub fn incremental_tree_membership_gadget<CS: ConstraintSystem>(
+    cs: &mut CS,
+    depth: usize,
+    root: &Scalar,
+    leaf_val: AllocatedScalar,
+    leaf_index_bits: Vec<AllocatedScalar>,
+    proof_nodes: Vec<AllocatedScalar>,
+    statics: Vec<AllocatedScalar>,
+    poseidon_params: &PoseidonParams
+) -> Result<(), GadgetError> {
+    vanilla_merkle_merkle_tree_verif_gadget(cs, depth, root, leaf_val, leaf_index_bits, proof_nodes, statics,
+                                            poseidon_params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bulletproofs::BulletproofGens;
+    use crate::gadget_vsmt_2::VanillaSparseMerkleTree;
+
+    #[test]
+    fn test_incremental_merkle_tree() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let depth = 5;
+        let mut tree = IncrementalMerkleTree::new(depth, &p_params);
+        let mut sparse_tree = VanillaSparseMerkleTree::new_with_depth(depth, &p_params);
+        assert_eq!(tree.root(), sparse_tree.raw_root());
+
+        let leaves: Vec<Scalar> = (1..=8u32).map(|i| Scalar::from(i * 10)).collect();
+        for (i, l) in leaves.iter().enumerate() {
+            assert_eq!(tree.append(*l), i);
+            // Same root as the sparse tree with the same leaves after every append
+            sparse_tree.update(Scalar::from(i as u64), *l);
+            assert_eq!(tree.root(), sparse_tree.raw_root());
+        }
+        assert_eq!(tree.num_leaves(), 8);
+        assert!(!tree.is_full());
+
+        // 5th leaf
+        let index = 4;
+        let merkle_proof = tree.proof_from_leaves(&leaves, index).unwrap();
+        let mut sparse_proof = Some(Vec::<Scalar>::new());
+        sparse_tree.get(Scalar::from(index as u64), &mut sparse_proof);
+        assert_eq!(merkle_proof, sparse_proof.unwrap());
+        assert!(tree.verify_proof(index, leaves[index], &merkle_proof));
+        assert!(!tree.verify_proof(index, leaves[index + 1], &merkle_proof));
+        // Index with the same lower bits as the leaf's
+        assert!(!tree.verify_proof(index + (1 << depth), leaves[index], &merkle_proof));
+        assert!(tree.proof_from_leaves(&leaves, 8).is_none());
+        assert!(tree.proof_from_leaves(&leaves[..7], index).is_none());
+
+        let bp_gens = BulletproofGens::new(1 << 13, 1);
+
+        let prove = |leaf: Scalar| -> bool {
+            prove_and_verify!(b"IncrementalMT", &p_params, &bp_gens, |cs, inputs, statics| {
+                let committed = inputs.path(cs, Scalar::from(index as u64), leaf, &merkle_proof);
+                incremental_tree_membership_gadget(cs, depth, &tree.root(), committed.leaf, committed.leaf_index_bits,
+                                                   committed.proof_nodes, statics, &p_params)
+            })
+        };
+
+        assert!(prove(leaves[index]));
+        assert!(!prove(leaves[index - 1]));
+    }
+
+    #[test]
+    #[should_panic(expected = "is full")]
+    fn test_incremental_merkle_tree_full() {
+        let p_params = PoseidonParams::new(6, 8, 8, 105);
+        let mut tree = IncrementalMerkleTree::new(2, &p_params);
+        for i in 0..4u32 {
+            tree.append(Scalar::from(i + 1));
+        }
+        assert!(tree.is_full());
+        tree.append(Scalar::one());
+    }
+}
@@ -4,8 +4,13 @@ extern crate bulletproofs;
 extern crate curve25519_dalek;
 extern crate merlin;
 
+#[cfg(test)]
+#[macro_use]
+mod test_utils;
+
 pub mod scalar_utils;
 pub mod r1cs_utils;
+pub mod errors;
 pub mod factors;
 pub mod gadget_not_equals;
 pub mod gadget_bound_check;
@@ -17,6 +22,17 @@ pub mod gadget_zero_nonzero;
 pub mod gadget_mimc;
 pub mod gadget_vsmt_2;
 pub mod gadget_vsmt_4;
+pub mod gadget_vsmt_8;
+pub mod gadget_index_range;
 pub mod gadget_osmt;    /// This is incomplete
 mod poseidon_constants;
 pub mod gadget_poseidon;
+pub mod poseidon_presets;
+pub mod hash_function;
+pub mod vsmt_sha;
+pub mod dense_merkle_tree;
+pub mod incremental_merkle_tree;
+/// Membership proofs over byte slices for use from WebAssembly, so that callers do not need the types of
+/// `curve25519_dalek` or `bulletproofs`.
+#[cfg(feature = "wasm")]
+pub mod wasm;
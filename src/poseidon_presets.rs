@@ -0,0 +1,78 @@
+//! Named parameter sets for Poseidon over the scalar field of Ristretto, a prime field of about 2^252, so that users
+//! do not have to pick round numbers. Only width 6 is supported as the crate has the MDS matrix for that width, the
+//! round keys and the MDS matrix are the ones generated by `Poseidon_Ristretto.ipynb` from the sage worksheet of the
+//! Poseidon authors. The round numbers are not derived here from the bounds of the Poseidon paper
+//! (https://eprint.iacr.org/2019/458) and no security level is claimed for them, use `PoseidonParams::from_constants`
+//! with the constants and round numbers of a reviewed specification if that is needed.
+
+use crate::gadget_poseidon::PoseidonParams;
+
+/// Width 6 (5 inputs and a capacity element) for use with `SboxType::Inverse`, the S-box used by the merkle trees of
+/// this crate. 16 full rounds (8 at the beginning and 8 at the end) and 105 partial rounds, the numbers used by the
+/// tests of this crate since its first version.
+pub fn poseidon_x_inv_252_6() -> PoseidonParams {
+    PoseidonParams::new(6, 8, 8, 105)
+}
+
+/// Width 6 (5 inputs and a capacity element) for use with `SboxType::Cube`, with 8 full rounds (4 at the beginning and
+/// 4 at the end) and 140 partial rounds. 3 divides the order of the field minus 1, so the cube is not a permutation of
+/// the field and this is not a Poseidon instance as defined in the paper. It is meant for compatibility with hashes
+/// already made with the cube S-box, prefer `poseidon_x_inv_252_6` otherwise.
+pub fn poseidon_x3_252_6() -> PoseidonParams {
+    PoseidonParams::new(6, 4, 4, 140)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use bulletproofs::{BulletproofGens, PedersenGens};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::gadget_poseidon::{Poseidon_hash_2, Poseidon_hash_2_gadget, SboxType};
+    use crate::gadget_vsmt_2::{VanillaSparseMerkleTree, gen_proof_of_leaf_membership, verify_proof_of_leaf_membership,
+                               recommended_bp_gens};
+
+    #[test]
+    fn test_presets() {
+        let depth = 8;
+        let pc_gens = PedersenGens::default();
+        for params in vec![poseidon_x_inv_252_6(), poseidon_x3_252_6()] {
+            assert_eq!(params.width, 6);
+            assert_eq!(params.round_keys().len(), params.get_total_rounds() * params.width);
+            assert_eq!(params.mds().len(), params.width);
+            assert!(params.mds().iter().all(|row| row.len() == params.width));
+            assert!(params.full_rounds_beginning + params.full_rounds_end >= 8);
+
+            let mut tree = VanillaSparseMerkleTree::new_with_depth(depth, &params);
+            for i in 1..=10u32 {
+                tree.update(Scalar::from(i), Scalar::from(i));
+            }
+            let mut rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+            let bp_gens: BulletproofGens = recommended_bp_gens(depth, &params);
+            let (proof, commitments) = gen_proof_of_leaf_membership(&tree, Scalar::from(7u32), &mut rng, b"Presets",
+                                                                    &pc_gens, &bp_gens).unwrap();
+            assert!(verify_proof_of_leaf_membership(depth, &tree.raw_root(), &params, proof, commitments, b"Presets",
+                                                    &pc_gens, &bp_gens).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_x3_preset_with_cube_sbox() {
+        let params = poseidon_x3_252_6();
+        let bp_gens = BulletproofGens::new(2048, 1);
+        let (xl, xr) = (Scalar::from(1111u32), Scalar::from(2222u32));
+        let expected = Poseidon_hash_2(xl, xr, &params, &SboxType::Cube);
+        assert_ne!(expected, Poseidon_hash_2(xl, xr, &params, &SboxType::Inverse));
+
+        let prove = |output: Scalar| -> bool {
+            prove_and_verify!(b"Presets_cube", &params, &bp_gens, |cs, inputs, statics| {
+                let xl = inputs.value(cs, xl);
+                let xr = inputs.value(cs, xr);
+                Poseidon_hash_2_gadget(cs, xl, xr, statics, &params, &SboxType::Cube, &output)
+            })
+        };
+        assert!(prove(expected));
+        assert!(!prove(expected + Scalar::one()));
+    }
+}
@@ -1,7 +1,12 @@
-use bulletproofs::r1cs::{ConstraintSystem, R1CSError, Variable};
+use bulletproofs::r1cs::{ConstraintSystem, R1CSError, Variable, Prover, Verifier};
 use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::ristretto::CompressedRistretto;
 use bulletproofs::{BulletproofGens, PedersenGens};
 use bulletproofs::r1cs::LinearCombination;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::errors::GadgetError;
 
 /// Represents a variable for quantity, along with its assignment.
 #[derive(Copy, Clone, Debug)]
@@ -16,6 +21,17 @@ pub struct AllocatedScalar {
     pub assignment: Option<Scalar>
 }
 
+/// Wipes the assignment and sets it to None, the variable is kept
+#[cfg(feature = "zeroize")]
+impl Zeroize for AllocatedScalar {
+    fn zeroize(&mut self) {
+        if let Some(a) = self.assignment.as_mut() {
+            a.zeroize();
+        }
+        self.assignment = None;
+    }
+}
+
 /// Enforces that the quantity of v is in the range [0, 2^n).
 pub fn positive_no_gadget<CS: ConstraintSystem>(
     cs: &mut CS,
@@ -50,4 +66,38 @@ pub fn positive_no_gadget<CS: ConstraintSystem>(
 /// Constrain a linear combination to be equal to a scalar
 pub fn constrain_lc_with_scalar<CS: ConstraintSystem>(cs: &mut CS, lc: LinearCombination, scalar: &Scalar) {
     cs.constrain(lc - LinearCombination::from(*scalar));
-}
\ No newline at end of file
+}
+
+/// Allocate `value` as the opening (`value`, `blinding`) of `expected_commitment`, a Pedersen commitment made elsewhere
+/// with `pc_gens`, so that gadgets using the returned variable prove statements about the committed value. The opening
+/// cannot be checked in the circuit as the commitment is a group element, instead the variable is committed to with the
+/// same blinding which makes its commitment equal to `expected_commitment`. `pc_gens` must be the ones of `prover`.
+pub fn pedersen_open_gadget_for_prover(
+    prover: &mut Prover,
+    value: Scalar,
+    blinding: Scalar,
+    pc_gens: &PedersenGens,
+    expected_commitment: &CompressedRistretto
+) -> Result<AllocatedScalar, GadgetError> {
+    if pc_gens.commit(value, blinding).compress() != *expected_commitment {
+        return Err(GadgetError::CommitmentMismatch);
+    }
+    let (_, var) = prover.commit(value, blinding);
+    Ok(AllocatedScalar {
+        variable: var,
+        assignment: Some(value),
+    })
+}
+
+/// Allocate the value committed in `expected_commitment` for Verifier. The proof only verifies if the prover
+/// allocated the same value with `pedersen_open_gadget_for_prover` at the same point.
+pub fn pedersen_open_gadget_for_verifier(
+    verifier: &mut Verifier,
+    expected_commitment: &CompressedRistretto
+) -> AllocatedScalar {
+    let var = verifier.commit(*expected_commitment);
+    AllocatedScalar {
+        variable: var,
+        assignment: None,
+    }
+}
@@ -1,6 +1,7 @@
 extern crate byteorder;
 extern crate rand;
 extern crate curve25519_dalek;
+extern crate base64;
 
 use rand::SeedableRng;
 use rand::rngs::OsRng;
@@ -10,8 +11,10 @@ use std::fmt;
 
 pub type ScalarBytes = [u8; 32];
 
-/// Get a bit array of this scalar, LSB is first element of this array
-#[derive(Clone)]
+/// Get a bit array of this scalar, LSB is first element of this array. Shifts keep the number of bits and shift in 0s,
+/// so after shifting as many times as there are bits, all bits are 0 and `is_msb_set` and `is_lsb_set` return false.
+/// Shifting an empty array does nothing and neither bit is set.
+#[derive(Clone, PartialEq)]
 pub struct ScalarBits {
     pub bit_array: Vec<u8>
 }
@@ -66,12 +69,15 @@ impl ScalarBits {
         self.to_non_reduced_scalar().reduce()
     }
 
+    /// Scalar with these bits, LSB first. Bits above the 256 bits of a scalar, like the padding of `get_bits` to a
+    /// larger width, must be 0 and are ignored.
     pub fn to_non_reduced_scalar(&self) -> Scalar {
+        assert!(self.bit_array.iter().skip(256).all(|b| *b == 0), "bits above bit 255 do not fit in a scalar");
         let mut bytes: [u8; 32] = [0; 32];
         let powers_of_2: [u8; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
         let mut i = 0;
         let mut current_byte = 0u8;
-        for b in self.bit_array.iter() {
+        for b in self.bit_array.iter().take(256) {
             if *b == 1 {
                 current_byte += powers_of_2[i % 8];
             }
@@ -81,25 +87,32 @@ impl ScalarBits {
                 current_byte = 0;
             }
         }
-        bytes[31] = current_byte;
+        // Bits that did not fill a complete byte
+        if (i % 8) != 0 {
+            bytes[i / 8] = current_byte;
+        }
         Scalar::from_bits(bytes)
     }
 
-    /// Shift left by 1 bit
+    /// Shift left by 1 bit, the MSB is dropped
     pub fn shl(&mut self) {
         for i in (1..self.bit_array.len()).rev() {
             self.bit_array[i] = self.bit_array[i-1];
         }
-        self.bit_array[0] = 0;
+        if let Some(b) = self.bit_array.first_mut() {
+            *b = 0;
+        }
     }
 
-    /// Shift right by 1 bit
+    /// Shift right by 1 bit, the LSB is dropped
     pub fn shr(&mut self) {
         let size = self.bit_array.len();
         for i in 1..size {
             self.bit_array[i-1] = self.bit_array[i];
         }
-        self.bit_array[size-1] = 0;
+        if let Some(b) = self.bit_array.last_mut() {
+            *b = 0;
+        }
     }
 
     /// Return a new bit-array shifted to the left with 1 bit
@@ -110,7 +123,6 @@ impl ScalarBits {
         for i in (1..size).rev() {
             new_array[i] = self.bit_array[i-1];
         }
-        new_array[0] = 0;
         Self {
             bit_array: new_array
         }
@@ -124,7 +136,6 @@ impl ScalarBits {
         for i in 1..size {
             new_array[i-1] = self.bit_array[i];
         }
-        new_array[size-1] = 0;
         Self {
             bit_array: new_array
         }
@@ -132,19 +143,35 @@ impl ScalarBits {
 
     /// Check if most significant bit is set
     pub fn is_msb_set(&self) -> bool {
-        self.bit_array[self.bit_array.len()-1] == 1
+        self.bit_array.last() == Some(&1)
     }
 
     /// Check if least significant bit is set
     pub fn is_lsb_set(&self) -> bool {
-        self.bit_array[0] == 1
+        self.bit_array.first() == Some(&1)
+    }
+
+    /// Return `len` bits starting from bit `start`, LSB first
+    pub fn slice(&self, start: usize, len: usize) -> Vec<u8> {
+        self.bit_array[start..start+len].to_vec()
+    }
+
+    /// Reverse the order of bits, the LSB becomes the MSB and vice versa
+    pub fn reverse(&mut self) {
+        self.bit_array.reverse();
     }
 }
 
+/// Number of bits in a canonical scalar, the order of the group is less than 2^253
+pub const ScalarBitLength: usize = 253;
+
+/// Bits of `scalar`, LSB first. Only the lowest `process_bits` bits are returned. As a canonical scalar has
+/// `ScalarBitLength` bits, if `process_bits` is more than that the high bits are zero, so the result is padded with zeroes.
 pub fn get_bits(scalar: &Scalar, process_bits: usize) -> Vec<u8> {
     let mut bits = vec![0u8; process_bits];
     let bytes = scalar.as_bytes();
-    for i in 0..process_bits {
+    // Bits beyond the 256 bits of the byte encoding are left as zero
+    for i in 0..process_bits.min(256) {
         // As i runs from 0..256, the bottom 3 bits index the bit,
         // while the upper bits index the byte.
         bits[i] = ((bytes[i>>3] >> (i&7)) & 1u8) as u8;
@@ -152,6 +179,41 @@ pub fn get_bits(scalar: &Scalar, process_bits: usize) -> Vec<u8> {
     bits
 }
 
+/// True if `idx` has no bits set at or above bit `depth`, i.e. it is the index of a leaf in a binary tree of that
+/// depth. `get_bits(idx, depth)` drops the higher bits so such an index would be taken for a smaller one.
+pub fn index_fits(idx: &Scalar, depth: usize) -> bool {
+    let bytes = idx.as_bytes();
+    (depth..256).all(|i| (bytes[i>>3] >> (i&7)) & 1 == 0)
+}
+
+/// Index of a leaf for `n`, the same as `Scalar::from(n)`.
+pub fn u64_to_index(n: u64) -> Scalar {
+    Scalar::from(n)
+}
+
+/// `idx` as a u64 if it is the index of a leaf in a binary tree of the given depth, None if it has bits set at or
+/// above bit `depth` or bit 64.
+pub fn index_to_u64(idx: &Scalar, depth: usize) -> Option<u64> {
+    if !index_fits(idx, depth.min(64)) {
+        return None;
+    }
+    Some(scalar_to_u64_array(idx)[0])
+}
+
+/// Scalar from its bits, LSB first, as returned by `get_bits`. The result is reduced if the bits represent a number
+/// larger than the order of the group.
+pub fn bits_to_scalar(bits: &[u8]) -> Scalar {
+    let mut result = Scalar::zero();
+    let mut exp_2 = Scalar::one();
+    for b in bits {
+        if *b == 1 {
+            result += exp_2;
+        }
+        exp_2 = exp_2 + exp_2;
+    }
+    result
+}
+
 pub fn scalar_to_u64_array(scalar: &Scalar) -> [u64; 4] {
     let bytes = scalar.to_bytes();
     let mut result = [0; 4];
@@ -186,17 +248,28 @@ pub fn get_base_4_repr(scalar: &Scalar, limit_bytes: usize) -> Vec<u8> {
 }
 
 
+/// Number of bytes of a byte string that go in one scalar. 31 bytes are always less than the group order.
+pub const BytesPerScalar: usize = 31;
+
+/// Split `bytes` into chunks of `BytesPerScalar` bytes and convert each chunk into a scalar, the
+/// first byte of the chunk being the least significant.
+pub fn bytes_to_scalars(bytes: &[u8]) -> Vec<Scalar> {
+    bytes.chunks(BytesPerScalar).map(|chunk| {
+        let mut b: ScalarBytes = [0; 32];
+        b[..chunk.len()].copy_from_slice(chunk);
+        Scalar::from_bits(b)
+    }).collect()
+}
+
 /// Following code for handling Hex is taken from https://play.rust-lang.org/?version=stable&mode=debug&edition=2015&gist=e241493d100ecaadac3c99f37d0f766f
 use std::num::ParseIntError;
 
 pub fn decode_hex(s: &str) -> Result<Vec<u8>, DecodeHexError> {
-    let s = if s[0..2] == *"0x" || s[0..2] == *"0X" {
-        match s.char_indices().skip(2).next() {
-            Some((pos, _)) => &s[pos..],
-            None => "",
-        }
-    } else { s };
-    if s.len() % 2 != 0 {
+    let s = if s.starts_with("0x") || s.starts_with("0X") { &s[2..] } else { s };
+    // Pairs of bytes are sliced below which is only valid at char boundaries
+    if !s.is_ascii() {
+        Err(DecodeHexError::NonAscii)
+    } else if s.len() % 2 != 0 {
         Err(DecodeHexError::OddLength)
     } else {
         (0..s.len())
@@ -209,6 +282,8 @@ pub fn decode_hex(s: &str) -> Result<Vec<u8>, DecodeHexError> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DecodeHexError {
     OddLength,
+    /// Input has characters that are not ASCII so it cannot be hex
+    NonAscii,
     ParseInt(ParseIntError),
 }
 
@@ -222,6 +297,7 @@ impl fmt::Display for DecodeHexError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             DecodeHexError::OddLength => "input string has an odd number of bytes".fmt(f),
+            DecodeHexError::NonAscii => "input string has non ASCII characters".fmt(f),
             DecodeHexError::ParseInt(e) => e.fmt(f),
         }
     }
@@ -236,11 +312,88 @@ pub fn get_scalar_from_hex(hex_str: &str) -> Result<Scalar, DecodeHexError> {
     Ok(Scalar::from_bytes_mod_order(result))
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScalarError {
+    /// Number of bytes is not 32
+    InvalidLength(usize),
+    /// Bytes are not the canonical encoding of a scalar, i.e. the encoded number is not less than the group order
+    NonCanonical,
+    Hex(DecodeHexError),
+    Base64(base64::DecodeError),
+}
+
+impl From<DecodeHexError> for ScalarError {
+    fn from(e: DecodeHexError) -> Self {
+        ScalarError::Hex(e)
+    }
+}
+
+impl From<base64::DecodeError> for ScalarError {
+    fn from(e: base64::DecodeError) -> Self {
+        ScalarError::Base64(e)
+    }
+}
+
+impl fmt::Display for ScalarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScalarError::InvalidLength(l) => write!(f, "expected 32 bytes but found {}", l),
+            ScalarError::NonCanonical => "bytes are not a canonical encoding of a scalar".fmt(f),
+            ScalarError::Hex(e) => e.fmt(f),
+            ScalarError::Base64(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ScalarError {}
+
+/// Get scalar from its canonical byte encoding
+pub fn scalar_from_bytes(bytes: &[u8]) -> Result<Scalar, ScalarError> {
+    if bytes.len() != 32 {
+        return Err(ScalarError::InvalidLength(bytes.len()))
+    }
+    let mut result: ScalarBytes = [0; 32];
+    result.copy_from_slice(bytes);
+    Scalar::from_canonical_bytes(result).ok_or(ScalarError::NonCanonical)
+}
+
+/// Returns `bytes` if they are the canonical encoding of a scalar, i.e. less than the group order, else None.
+/// A non-canonical encoding and its reduced form are different keys, so lookups keyed by them would silently miss.
+pub fn scalar_bytes_from_canonical(bytes: [u8; 32]) -> Option<ScalarBytes> {
+    Scalar::from_canonical_bytes(bytes).map(|s| s.to_bytes())
+}
+
+/// Hex of the bytes of the scalar prefixed with 0x
+pub fn scalar_to_hex(s: &Scalar) -> String {
+    let mut hex = String::from("0x");
+    for b in s.as_bytes() {
+        hex.push_str(&format!("{:02x}", b));
+    }
+    hex
+}
+
+/// Parse the output of `scalar_to_hex`. Unlike `get_scalar_from_hex`, does not reduce the scalar and
+/// rejects non canonical bytes.
+pub fn scalar_from_hex(s: &str) -> Result<Scalar, ScalarError> {
+    let bytes = decode_hex(s)?;
+    scalar_from_bytes(&bytes)
+}
+
+pub fn scalar_to_base64(s: &Scalar) -> String {
+    base64::encode(s.as_bytes())
+}
+
+pub fn scalar_from_base64(s: &str) -> Result<Scalar, ScalarError> {
+    let bytes = base64::decode(s)?;
+    scalar_from_bytes(&bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use curve25519_dalek::constants::BASEPOINT_ORDER;
     use crate::gadget_vsmt_2::TreeDepth;
+    use rand::RngCore;
 
     #[test]
     fn test_shl_shr() {
@@ -260,6 +413,262 @@ mod tests {
         }*/
     }
 
+    #[test]
+    fn test_shift_boundaries() {
+        let mut csprng: OsRng = OsRng::default();
+        for depth in [1usize, 2, 13, TreeDepth, 253].iter() {
+            let depth = *depth;
+            for _ in 0..10 {
+                let r: Scalar = Scalar::random(&mut csprng);
+                let bits = ScalarBits::from_scalar(&r, depth);
+
+                // Shifting left reads the bits MSB first, as when traversing from the root
+                let mut b_arr = bits.clone();
+                for i in (0..depth).rev() {
+                    assert_eq!(b_arr.is_msb_set(), bits.bit_array[i] == 1);
+                    assert_eq!(b_arr.new_left_shifted().bit_array, { let mut c = b_arr.clone(); c.shl(); c.bit_array });
+                    b_arr.shl();
+                }
+                assert_eq!(b_arr.bit_array.len(), depth);
+                assert!(!b_arr.is_msb_set() && !b_arr.is_lsb_set());
+                b_arr.shl();
+                assert!(b_arr.bit_array.iter().all(|b| *b == 0));
+
+                // Shifting right reads the bits LSB first, as when going up from the leaf
+                let mut b_arr = bits.clone();
+                for i in 0..depth {
+                    assert_eq!(b_arr.is_lsb_set(), bits.bit_array[i] == 1);
+                    assert_eq!(b_arr.new_right_shifted().bit_array, { let mut c = b_arr.clone(); c.shr(); c.bit_array });
+                    b_arr.shr();
+                }
+                assert!(!b_arr.is_msb_set() && !b_arr.is_lsb_set());
+                b_arr.shr();
+                assert!(b_arr.bit_array.iter().all(|b| *b == 0));
+
+                // Shifting left k times and back right clears the k highest bits
+                let k = (csprng.next_u32() as usize) % (depth + 1);
+                let mut b_arr = bits.clone();
+                for _ in 0..k {
+                    b_arr.shl();
+                }
+                for _ in 0..k {
+                    b_arr.shr();
+                }
+                let mut expected = bits.bit_array.clone();
+                for b in expected[depth - k..].iter_mut() {
+                    *b = 0;
+                }
+                assert_eq!(b_arr.bit_array, expected);
+            }
+        }
+
+        // Empty array
+        let mut empty = ScalarBits::from_scalar(&Scalar::one(), 0);
+        empty.shl();
+        empty.shr();
+        assert!(empty.bit_array.is_empty());
+        assert!(!empty.is_msb_set() && !empty.is_lsb_set());
+        assert!(empty.new_left_shifted().bit_array.is_empty());
+        assert!(empty.new_right_shifted().bit_array.is_empty());
+    }
+
+    #[test]
+    fn test_to_scalar_round_trip() {
+        let mut csprng: OsRng = OsRng::default();
+        for _ in 0..100 {
+            let r: Scalar = Scalar::random(&mut csprng);
+            let b_arr = ScalarBits::from_scalar(&r, TreeDepth);
+            assert_eq!(b_arr, ScalarBits::from_scalar(&b_arr.to_scalar(), TreeDepth));
+
+            // Using all bits gives back the same scalar
+            let b_arr = ScalarBits::from_scalar(&r, 256);
+            assert_eq!(r, b_arr.to_scalar());
+        }
+
+        // Bit width that is not a multiple of 8
+        let s = Scalar::from(1000u64);
+        let b_arr = ScalarBits::from_scalar(&s, 13);
+        assert_eq!(s, b_arr.to_scalar());
+
+        // Zero padding above the 256 bits of a scalar
+        let r: Scalar = Scalar::random(&mut csprng);
+        let b_arr = ScalarBits::from_scalar(&r, 300);
+        assert_eq!(r, b_arr.to_scalar());
+        assert_eq!(r, b_arr.to_non_reduced_scalar());
+    }
+
+    #[test]
+    #[should_panic(expected = "do not fit in a scalar")]
+    fn test_to_scalar_bits_above_256() {
+        let mut b_arr = ScalarBits::from_scalar(&Scalar::one(), 300);
+        b_arr.bit_array[280] = 1;
+        b_arr.to_scalar();
+    }
+
+    #[test]
+    fn test_index_fits() {
+        assert!(index_fits(&Scalar::zero(), 0));
+        assert!(!index_fits(&Scalar::one(), 0));
+        assert!(index_fits(&Scalar::from(255u32), 8));
+        assert!(!index_fits(&Scalar::from(256u32), 8));
+        assert!(index_fits(&Scalar::from(u32::MAX), 32));
+        assert!(!index_fits(&Scalar::from(1u64 << 32), 32));
+        assert!(index_fits(&-Scalar::one(), ScalarBitLength));
+        assert!(!index_fits(&-Scalar::one(), 252));
+        assert!(index_fits(&-Scalar::one(), 300));
+    }
+
+    #[test]
+    fn test_index_to_u64() {
+        for n in [0u64, 1, 1000, u64::MAX].iter() {
+            assert_eq!(u64_to_index(*n), Scalar::from(*n));
+            assert_eq!(index_to_u64(&u64_to_index(*n), 64), Some(*n));
+        }
+        assert_eq!(index_to_u64(&u64_to_index(255), 8), Some(255));
+        assert_eq!(index_to_u64(&u64_to_index(256), 8), None);
+        assert_eq!(index_to_u64(&u64_to_index(u64::MAX), 32), None);
+        // Larger depths do not allow more than 64 bits
+        assert_eq!(index_to_u64(&u64_to_index(u64::MAX), 100), Some(u64::MAX));
+        assert_eq!(index_to_u64(&(Scalar::from(u64::MAX) + Scalar::one()), 100), None);
+        assert_eq!(index_to_u64(&-Scalar::one(), 256), None);
+    }
+
+    #[test]
+    fn test_get_bits_widths() {
+        let s = Scalar::from(u64::MAX);
+
+        let bits = get_bits(&s, 1);
+        assert_eq!(bits, vec![1]);
+        assert_eq!(bits_to_scalar(&bits), Scalar::one());
+
+        let bits = get_bits(&s, 32);
+        assert_eq!(bits_to_scalar(&bits), Scalar::from(u32::MAX));
+
+        // All bits of a canonical scalar
+        let bits = get_bits(&s, ScalarBitLength);
+        assert_eq!(bits_to_scalar(&bits), s);
+
+        // High bits are zero
+        let bits = get_bits(&s, 256);
+        assert!(bits[64..].iter().all(|b| *b == 0));
+        assert_eq!(bits_to_scalar(&bits), s);
+
+        // Padded with zeroes beyond the byte encoding
+        let bits = get_bits(&s, 300);
+        assert_eq!(bits.len(), 300);
+        assert!(bits[64..].iter().all(|b| *b == 0));
+        assert_eq!(bits_to_scalar(&bits), s);
+
+        let mut csprng: OsRng = OsRng::default();
+        for _ in 0..10 {
+            let r: Scalar = Scalar::random(&mut csprng);
+            assert_eq!(bits_to_scalar(&get_bits(&r, ScalarBitLength)), r);
+            assert!(get_bits(&r, 256)[ScalarBitLength..].iter().all(|b| *b == 0));
+        }
+    }
+
+    #[test]
+    fn test_slice_reverse() {
+        let s = Scalar::from(0b1011_0110u64);
+        let mut b_arr = ScalarBits::from_scalar(&s, 8);
+        assert_eq!(b_arr.slice(0, 4), vec![0, 1, 1, 0]);
+        assert_eq!(b_arr.slice(4, 4), vec![1, 1, 0, 1]);
+        assert_eq!(b_arr.slice(2, 0), Vec::<u8>::new());
+
+        b_arr.reverse();
+        assert_eq!(b_arr.bit_array, vec![1, 0, 1, 1, 0, 1, 1, 0]);
+        assert_eq!(b_arr.to_scalar(), Scalar::from(0b0110_1101u64));
+
+        let mut csprng: OsRng = OsRng::default();
+        for _ in 0..100 {
+            let r: Scalar = Scalar::random(&mut csprng);
+            let b_arr = ScalarBits::from_scalar(&r, TreeDepth);
+            let mut rev = b_arr.clone();
+            rev.reverse();
+            for i in 0..TreeDepth {
+                assert_eq!(b_arr.slice(i, 1)[0], rev.bit_array[TreeDepth-1-i]);
+            }
+            rev.reverse();
+            assert_eq!(b_arr, rev);
+        }
+    }
+
+    #[test]
+    fn test_bytes_to_scalars() {
+        assert_eq!(bytes_to_scalars(&[]).len(), 0);
+        assert_eq!(bytes_to_scalars(&[1u8, 2u8]), vec![Scalar::from(513u64)]);
+
+        let bytes = [255u8; 64];
+        let scalars = bytes_to_scalars(&bytes);
+        assert_eq!(scalars.len(), 3);
+        for s in scalars {
+            // Chunks are always canonical
+            assert!(Scalar::from_canonical_bytes(s.to_bytes()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_scalar_hex_base64() {
+        let mut csprng: OsRng = OsRng::default();
+        for _ in 0..100 {
+            let r: Scalar = Scalar::random(&mut csprng);
+            assert_eq!(r, scalar_from_hex(&scalar_to_hex(&r)).unwrap());
+            assert_eq!(r, scalar_from_base64(&scalar_to_base64(&r)).unwrap());
+            assert_eq!(r, get_scalar_from_hex(&scalar_to_hex(&r)).unwrap());
+        }
+
+        // 33 bytes
+        let hex = format!("{}00", scalar_to_hex(&Scalar::one()));
+        assert_eq!(scalar_from_hex(&hex), Err(ScalarError::InvalidLength(33)));
+        assert_eq!(scalar_from_base64(&base64::encode(&[1u8; 33])), Err(ScalarError::InvalidLength(33)));
+
+        // Not less than the group order
+        let order_bytes = BASEPOINT_ORDER.to_bytes();
+        assert_eq!(scalar_from_bytes(&order_bytes), Err(ScalarError::NonCanonical));
+        assert_eq!(scalar_from_bytes(&[255u8; 32]), Err(ScalarError::NonCanonical));
+        assert_eq!(scalar_from_base64(&base64::encode(&[255u8; 32])), Err(ScalarError::NonCanonical));
+
+        assert!(scalar_from_hex("0x0").is_err());
+
+        // Short or non ASCII inputs are errors rather than panics
+        assert_eq!(scalar_from_hex(""), Err(ScalarError::InvalidLength(0)));
+        assert_eq!(scalar_from_hex("a"), Err(ScalarError::Hex(DecodeHexError::OddLength)));
+        assert_eq!(scalar_from_hex("0x"), Err(ScalarError::InvalidLength(0)));
+        assert_eq!(scalar_from_hex("é"), Err(ScalarError::Hex(DecodeHexError::NonAscii)));
+        assert_eq!(scalar_from_hex("0xéé"), Err(ScalarError::Hex(DecodeHexError::NonAscii)));
+        assert_eq!(decode_hex("0x"), Ok(vec![]));
+        assert!(scalar_from_base64("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_scalar_bytes_from_canonical() {
+        let mut csprng: OsRng = OsRng::default();
+        let mut num_canonical = 0;
+        for _ in 0..1000 {
+            let mut bytes = [0u8; 32];
+            csprng.fill_bytes(&mut bytes);
+            let reduced = Scalar::from_bits(bytes).reduce().to_bytes();
+            match scalar_bytes_from_canonical(bytes) {
+                Some(b) => {
+                    assert_eq!(b, bytes);
+                    assert_eq!(reduced, bytes);
+                    num_canonical += 1;
+                }
+                None => assert_ne!(reduced, bytes)
+            }
+        }
+        // About 1 in 16 of random 32 byte arrays is less than the group order
+        assert!(num_canonical > 0 && num_canonical < 1000);
+
+        for _ in 0..100 {
+            let r = Scalar::random(&mut csprng);
+            assert_eq!(scalar_bytes_from_canonical(r.to_bytes()), Some(r.to_bytes()));
+        }
+        assert_eq!(scalar_bytes_from_canonical(BASEPOINT_ORDER.to_bytes()), None);
+        assert_eq!(scalar_bytes_from_canonical([255u8; 32]), None);
+        assert_eq!(scalar_bytes_from_canonical([0u8; 32]), Some([0u8; 32]));
+    }
+
     #[test]
     fn test_scalar_to_u64_array() {
         for n in vec![32, 255, 127, 488, 256, 257].iter() {
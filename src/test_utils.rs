@@ -0,0 +1,144 @@
+//! Helpers for the tests of the gadgets, so that each test does not build its own prover and verifier.
+
+use bulletproofs::r1cs::{Prover, Verifier};
+use bulletproofs::PedersenGens;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use rand::rngs::StdRng;
+
+use crate::r1cs_utils::AllocatedScalar;
+use crate::scalar_utils::get_bits;
+use crate::gadget_vsmt_2::{MerkleProofBuilder, CommittedMerkleProof};
+use crate::gadget_poseidon::{allocate_statics_for_prover, allocate_statics_for_verifier};
+
+/// Commits to the inputs of a gadget for the prover in `prove_and_verify`, keeping the commitments in order
+pub struct ProverInputs {
+    pub rng: StdRng,
+    pub commitments: Vec<CompressedRistretto>
+}
+
+impl ProverInputs {
+    pub fn value(&mut self, prover: &mut Prover, v: Scalar) -> AllocatedScalar {
+        let (c, variable) = prover.commit(v, Scalar::random(&mut self.rng));
+        self.commitments.push(c);
+        AllocatedScalar { variable, assignment: Some(v) }
+    }
+
+    pub fn values(&mut self, prover: &mut Prover, vs: &[Scalar]) -> Vec<AllocatedScalar> {
+        vs.iter().map(|v| self.value(prover, *v)).collect()
+    }
+
+    /// Bits of `position` for a tree of the given `depth`, LSB first
+    pub fn bits(&mut self, prover: &mut Prover, position: &Scalar, depth: usize) -> Vec<AllocatedScalar> {
+        get_bits(position, depth).iter().map(|b| self.value(prover, Scalar::from(*b))).collect()
+    }
+
+    /// Nodes of `merkle_proof`, as returned by `VanillaSparseMerkleTree::get`, from the leaf to the root
+    pub fn nodes(&mut self, prover: &mut Prover, merkle_proof: &[Scalar]) -> Vec<AllocatedScalar> {
+        merkle_proof.iter().rev().map(|p| self.value(prover, *p)).collect()
+    }
+
+    /// Leaf at `position` with its merkle proof as returned by `VanillaSparseMerkleTree::get`
+    pub fn path(&mut self, prover: &mut Prover, position: Scalar, leaf: Scalar, merkle_proof: &[Scalar]) -> CommittedMerkleProof {
+        let committed = MerkleProofBuilder::new(position, leaf, merkle_proof).commit(prover, &mut self.rng);
+        self.commitments.extend_from_slice(&committed.commitments);
+        committed
+    }
+
+    /// Statics for other hash parameters than those of `prove_and_verify`, e.g. the leaf hash parameters
+    pub fn statics(&mut self, prover: &mut Prover, num_statics: usize) -> Vec<AllocatedScalar> {
+        allocate_statics_for_prover(prover, num_statics)
+    }
+
+    /// `prover` here and `verifier` in `VerifierInputs`, for tests where the two sides use different public inputs
+    pub fn either<T>(&self, prover: T, _verifier: T) -> T {
+        prover
+    }
+}
+
+/// Allocates the variables of the commitments made with `ProverInputs` for the verifier in `prove_and_verify`. The
+/// methods take the same arguments as those of `ProverInputs` but only use them for the number of commitments.
+pub struct VerifierInputs {
+    pub commitments: Vec<CompressedRistretto>,
+    pub next: usize
+}
+
+impl VerifierInputs {
+    pub fn value(&mut self, verifier: &mut Verifier, _v: Scalar) -> AllocatedScalar {
+        let variable = verifier.commit(self.commitments[self.next]);
+        self.next += 1;
+        AllocatedScalar { variable, assignment: None }
+    }
+
+    pub fn values(&mut self, verifier: &mut Verifier, vs: &[Scalar]) -> Vec<AllocatedScalar> {
+        vs.iter().map(|v| self.value(verifier, *v)).collect()
+    }
+
+    pub fn bits(&mut self, verifier: &mut Verifier, _position: &Scalar, depth: usize) -> Vec<AllocatedScalar> {
+        (0..depth).map(|_| self.value(verifier, Scalar::zero())).collect()
+    }
+
+    pub fn nodes(&mut self, verifier: &mut Verifier, merkle_proof: &[Scalar]) -> Vec<AllocatedScalar> {
+        self.values(verifier, merkle_proof)
+    }
+
+    pub fn path(&mut self, verifier: &mut Verifier, _position: Scalar, _leaf: Scalar, merkle_proof: &[Scalar]) -> CommittedMerkleProof {
+        let depth = merkle_proof.len();
+        let commitments = &self.commitments[self.next..self.next + 1 + 2*depth];
+        self.next += commitments.len();
+        MerkleProofBuilder::verifier_view(verifier, depth, commitments).unwrap()
+    }
+
+    pub fn statics(&mut self, verifier: &mut Verifier, num_statics: usize) -> Vec<AllocatedScalar> {
+        allocate_statics_for_verifier(verifier, num_statics, &PedersenGens::default())
+    }
+
+    pub fn either<T>(&self, _prover: T, verifier: T) -> T {
+        verifier
+    }
+}
+
+/// Creates a proof with the constraints of `$gadget` and verifies it with the same constraints, evaluating to whether
+/// the proof verifies. `$gadget` is expanded once for the prover and once for the verifier, with `$cs` the constraint
+/// system, `$inputs` a `ProverInputs` or `VerifierInputs` to commit to the inputs of the gadget and `$statics` the
+/// statics for `$p_params`. It should evaluate to the result of the gadget, which is asserted to be Ok on both sides.
+/// Gadgets that do not hash leave out `$p_params` and `$statics`.
+macro_rules! prove_and_verify {
+    (@run $label:expr, $bp_gens:expr, $num_statics:expr, |$cs:ident, $inputs:ident, $statics:ident| $gadget:expr) => {{
+        let pc_gens = bulletproofs::PedersenGens::default();
+        let (proof, commitments) = {
+            let mut prover_transcript = merlin::Transcript::new($label);
+            let mut prover = bulletproofs::r1cs::Prover::new(&pc_gens, &mut prover_transcript);
+            #[allow(unused_mut)]
+            let mut $inputs = $crate::test_utils::ProverInputs {
+                rng: rand::SeedableRng::from_seed([24u8; 32]),
+                commitments: vec![]
+            };
+            let $statics = $crate::gadget_poseidon::allocate_statics_for_prover(&mut prover, $num_statics);
+            {
+                let $cs = &mut prover;
+                assert!($gadget.is_ok());
+            }
+            // The prover does not check that the constraints are satisfied so a proof is created for any witness
+            (prover.prove($bp_gens).unwrap(), $inputs.commitments)
+        };
+
+        let mut verifier_transcript = merlin::Transcript::new($label);
+        let mut verifier = bulletproofs::r1cs::Verifier::new(&mut verifier_transcript);
+        #[allow(unused_mut)]
+        let mut $inputs = $crate::test_utils::VerifierInputs { commitments, next: 0 };
+        let $statics = $crate::gadget_poseidon::allocate_statics_for_verifier(&mut verifier, $num_statics, &pc_gens);
+        {
+            let $cs = &mut verifier;
+            assert!($gadget.is_ok());
+        }
+        verifier.verify(&proof, &pc_gens, $bp_gens).is_ok()
+    }};
+    ($label:expr, $bp_gens:expr, |$cs:ident, $inputs:ident| $gadget:expr) => {
+        prove_and_verify!(@run $label, $bp_gens, 0, |$cs, $inputs, _statics| $gadget)
+    };
+    ($label:expr, $p_params:expr, $bp_gens:expr, |$cs:ident, $inputs:ident, $statics:ident| $gadget:expr) => {
+        prove_and_verify!(@run $label, $bp_gens, $crate::gadget_poseidon::num_required_statics($p_params),
+                          |$cs, $inputs, $statics| $gadget)
+    };
+}
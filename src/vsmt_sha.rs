@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use curve25519_dalek::scalar::Scalar;
+use sha2::{Sha256, Digest};
+
+use crate::scalar_utils::ScalarBytes;
+use crate::hash_function::{HashFunction, empty_tree_hashes, merkle_root_from_path, index_path, traverse_path, hash_path};
+use crate::gadget_vsmt_2::TreeDepth;
+
+/// SHA-256 of the concatenation of the children
+pub struct Sha256Hash;
+
+impl HashFunction for Sha256Hash {
+    type Node = ScalarBytes;
+
+    fn hash_2(&self, left: &ScalarBytes, right: &ScalarBytes) -> ScalarBytes {
+        let mut hasher = Sha256::new();
+        hasher.input(left);
+        hasher.input(right);
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&hasher.result());
+        out
+    }
+}
+
+/// Sparse merkle tree like `VanillaSparseMerkleTree` but hashed with SHA-256, for consumers outside of the circuit that
+/// need a SHA-256 root. There is no gadget for this tree, keep it alongside a `VanillaSparseMerkleTree` with the same
+/// leaves to prove membership. Nodes and leaves are 32 byte strings, e.g. the bytes of the leaf of the Poseidon tree.
+pub struct VanillaSparseMerkleTreeSha {
+    pub depth: usize,
+    empty_tree_hashes: Vec<ScalarBytes>,
+    db: HashMap<ScalarBytes, (ScalarBytes, ScalarBytes)>,
+    root: ScalarBytes
+}
+
+impl Default for VanillaSparseMerkleTreeSha {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VanillaSparseMerkleTreeSha {
+    pub fn new() -> VanillaSparseMerkleTreeSha {
+        Self::new_with_depth(TreeDepth)
+    }
+
+    /// Create a tree of the given depth. `depth` should not be more than `TreeDepth`.
+    pub fn new_with_depth(depth: usize) -> VanillaSparseMerkleTreeSha {
+        assert!(depth <= TreeDepth);
+        let empty_tree_hashes = empty_tree_hashes(&Sha256Hash, depth);
+        let mut db = HashMap::new();
+        for i in 1..=depth {
+            let prev = empty_tree_hashes[i-1];
+            db.insert(empty_tree_hashes[i], (prev, prev));
+        }
+
+        let root = empty_tree_hashes[depth];
+
+        VanillaSparseMerkleTreeSha {
+            depth,
+            empty_tree_hashes,
+            db,
+            root
+        }
+    }
+
+    pub fn root(&self) -> ScalarBytes {
+        self.root
+    }
+
+    pub fn update(&mut self, idx: Scalar, val: ScalarBytes) -> ScalarBytes {
+        self.update_with_proof(idx, val, &mut None)
+    }
+
+    /// Update the tree and if `proof` is not None, populate `proof` with the merkle proof of the updated leaf.
+    pub fn update_with_proof(&mut self, idx: Scalar, val: ScalarBytes, proof: &mut Option<Vec<ScalarBytes>>) -> ScalarBytes {
+        let path = index_path(&idx, self.depth);
+
+        // Find path to insert the new key
+        let mut sidenodes = Some(Vec::<ScalarBytes>::new());
+        traverse_path(self.root, &path, |n| self.db[n], &mut sidenodes);
+        let sidenodes: Vec<ScalarBytes> = sidenodes.unwrap();
+
+        // Updating a leaf does not change its sibling nodes so they are the proof for the new value
+        if let Some(v) = proof {
+            v.extend_from_slice(&sidenodes);
+        }
+
+        let db = &mut self.db;
+        self.root = hash_path(&Sha256Hash, &path, val, &sidenodes, |h, children| { db.insert(h, children); });
+
+        self.root
+    }
+
+    /// Get a value from tree, if `proof` is not None, populate `proof` with the merkle proof
+    pub fn get(&self, idx: Scalar, proof: &mut Option<Vec<ScalarBytes>>) -> ScalarBytes {
+        traverse_path(self.root, &index_path(&idx, self.depth), |n| self.db[n], proof)
+    }
+
+    /// Verify a merkle proof, if `root` is None, use the current root else use given root
+    pub fn verify_proof(&self, idx: Scalar, val: ScalarBytes, proof: &[ScalarBytes], root: Option<&ScalarBytes>) -> bool {
+        let root = match root {
+            Some(r) => *r,
+            None => self.root
+        };
+        merkle_root_from_path(&Sha256Hash, &idx, val, proof, self.depth) == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use crate::gadget_poseidon::PoseidonParams;
+    use crate::gadget_vsmt_2::VanillaSparseMerkleTree;
+
+    #[test]
+    fn test_vanilla_sparse_merkle_tree_sha() {
+        let mut test_rng: OsRng = OsRng::default();
+        let mut tree = VanillaSparseMerkleTreeSha::new();
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s.to_bytes());
+        }
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            assert_eq!(s.to_bytes(), tree.get(s, &mut None));
+            let mut proof = Some(Vec::<ScalarBytes>::new());
+            assert_eq!(s.to_bytes(), tree.get(s, &mut proof));
+            let proof_vec = proof.unwrap();
+            assert!(tree.verify_proof(s, s.to_bytes(), &proof_vec, None));
+            assert!(tree.verify_proof(s, s.to_bytes(), &proof_vec, Some(&tree.root())));
+            assert!(!tree.verify_proof(s, [1u8; 32], &proof_vec, None));
+        }
+
+        let kvs: Vec<(Scalar, Scalar)> = (0..100).map(|_| (Scalar::random(&mut test_rng), Scalar::random(&mut test_rng))).collect();
+        for i in 0..kvs.len() {
+            tree.update(kvs[i].0, kvs[i].1.to_bytes());
+        }
+
+        for i in 0..kvs.len() {
+            assert_eq!(kvs[i].1.to_bytes(), tree.get(kvs[i].0, &mut None));
+        }
+    }
+
+    #[test]
+    fn test_update_with_proof_sha() {
+        let mut tree = VanillaSparseMerkleTreeSha::new_with_depth(16);
+        assert_eq!(tree.root(), empty_tree_hashes(&Sha256Hash, 16)[16]);
+
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            let mut proof = Some(Vec::<ScalarBytes>::new());
+            let root = tree.update_with_proof(s, s.to_bytes(), &mut proof);
+            let proof_vec = proof.unwrap();
+            assert_eq!(tree.depth, proof_vec.len());
+            assert!(tree.verify_proof(s, s.to_bytes(), &proof_vec, Some(&root)));
+
+            let mut get_proof = Some(Vec::<ScalarBytes>::new());
+            tree.get(s, &mut get_proof);
+            assert_eq!(proof_vec, get_proof.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_sha_tree_alongside_poseidon_tree() {
+        let p_params = PoseidonParams::new(6, 4, 4, 140);
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+        let mut sha_tree = VanillaSparseMerkleTreeSha::new_with_depth(16);
+
+        // Same leaves in both trees
+        for i in 1..10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+            sha_tree.update(s, s.to_bytes());
+        }
+
+        for i in 0..12 {
+            let s = Scalar::from(i as u32);
+            assert_eq!(tree.get(s, &mut None).to_bytes(), sha_tree.get(s, &mut None));
+        }
+        assert_ne!(tree.root().to_bytes(), sha_tree.root());
+    }
+}
@@ -0,0 +1,130 @@
+use rand::rngs::OsRng;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use bulletproofs::r1cs::R1CSProof;
+use bulletproofs::{BulletproofGens, PedersenGens};
+use byteorder::{ByteOrder, LittleEndian};
+use wasm_bindgen::prelude::*;
+
+use crate::scalar_utils::{ScalarBytes, scalar_from_bytes};
+use crate::gadget_poseidon::PoseidonParams;
+use crate::gadget_vsmt_2::{TreeDepth, gen_proof_of_leaf_membership_from_path, verify_proof_of_leaf_membership};
+
+const TRANSCRIPT_LABEL: &'static [u8] = b"VSMTMembershipWasm";
+
+/// Enough generators for the membership proof of a tree of depth `TreeDepth`
+const NUM_GENERATORS: usize = 1 << 15;
+
+/// Poseidon parameters for the trees proven by `prove_membership_bytes`
+pub fn hash_params() -> PoseidonParams {
+    PoseidonParams::new(6, 8, 8, 105)
+}
+
+/// Prove knowledge of the leaf `val` at index `idx` of the tree with root `tree_root` without revealing them. `proof`
+/// is the merkle proof returned by `VanillaSparseMerkleTree::get` with the 32 bytes of each node concatenated, the
+/// depth of the tree is the number of nodes. Scalars are in their canonical 32 byte encoding.
+/// Returns the depth as 4 bytes little endian followed by the commitments and the proof, or an empty vector if any
+/// input is malformed.
+#[wasm_bindgen]
+pub fn prove_membership_bytes(tree_root: &[u8], idx: &[u8], val: &[u8], proof: &[u8]) -> Vec<u8> {
+    let (root, idx, val) = match (scalar_from_bytes(tree_root), scalar_from_bytes(idx), scalar_from_bytes(val)) {
+        (Ok(r), Ok(i), Ok(v)) => (r, i, v),
+        _ => return vec![]
+    };
+    if proof.len() % 32 != 0 || proof.len() / 32 > TreeDepth {
+        return vec![];
+    }
+    let mut merkle_proof = vec![];
+    for node in proof.chunks(32) {
+        match scalar_from_bytes(node) {
+            Ok(n) => merkle_proof.push(n),
+            Err(_) => return vec![]
+        }
+    }
+
+    let pc_gens = PedersenGens::default();
+    let bp_gens = BulletproofGens::new(NUM_GENERATORS, 1);
+    let mut rng = OsRng::default();
+    let (r1cs_proof, commitments) = match gen_proof_of_leaf_membership_from_path(&root, &hash_params(), idx, val, &merkle_proof,
+                                                                                &mut rng, TRANSCRIPT_LABEL, &pc_gens, &bp_gens) {
+        Ok(p) => p,
+        Err(_) => return vec![]
+    };
+
+    let mut bytes = vec![0u8; 4];
+    LittleEndian::write_u32(&mut bytes, merkle_proof.len() as u32);
+    for c in commitments {
+        bytes.extend_from_slice(c.as_bytes());
+    }
+    bytes.extend_from_slice(&r1cs_proof.to_bytes());
+    bytes
+}
+
+/// Verify the output of `prove_membership_bytes` for the tree with root `tree_root`
+#[wasm_bindgen]
+pub fn verify_membership_bytes(tree_root: &[u8], proof: &[u8]) -> bool {
+    let root = match scalar_from_bytes(tree_root) {
+        Ok(r) => r,
+        Err(_) => return false
+    };
+    if proof.len() < 4 {
+        return false;
+    }
+    let depth = LittleEndian::read_u32(&proof[..4]) as usize;
+    if depth > TreeDepth {
+        return false;
+    }
+    // Commitment to leaf and one commitment each for the leaf index bit and proof node of each level
+    let comms_end = 4 + 32 * (1 + 2*depth);
+    if proof.len() < comms_end {
+        return false;
+    }
+    let commitments: Vec<CompressedRistretto> = proof[4..comms_end].chunks(32).map(|c| {
+        let mut b: ScalarBytes = [0; 32];
+        b.copy_from_slice(c);
+        CompressedRistretto(b)
+    }).collect();
+    let r1cs_proof = match R1CSProof::from_bytes(&proof[comms_end..]) {
+        Ok(p) => p,
+        Err(_) => return false
+    };
+
+    let pc_gens = PedersenGens::default();
+    let bp_gens = BulletproofGens::new(NUM_GENERATORS, 1);
+    verify_proof_of_leaf_membership(depth, &root, &hash_params(), r1cs_proof, commitments,
+                                    TRANSCRIPT_LABEL, &pc_gens, &bp_gens).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gadget_vsmt_2::VanillaSparseMerkleTree;
+
+    #[test]
+    fn test_membership_bytes() {
+        let p_params = hash_params();
+        let mut tree = VanillaSparseMerkleTree::new_with_depth(16, &p_params);
+
+        for i in 1..=10 {
+            let s = Scalar::from(i as u32);
+            tree.update(s, s);
+        }
+
+        let k = Scalar::from(7u32);
+        let mut merkle_proof = Some(Vec::<Scalar>::new());
+        let leaf = tree.get(k, &mut merkle_proof);
+        let proof_bytes: Vec<u8> = merkle_proof.unwrap().iter().flat_map(|n| n.to_bytes().to_vec()).collect();
+        let root = tree.root().to_bytes();
+
+        let proof = prove_membership_bytes(&root, k.as_bytes(), leaf.as_bytes(), &proof_bytes);
+        assert!(!proof.is_empty());
+        assert!(verify_membership_bytes(&root, &proof));
+
+        // Another root, a truncated proof and malformed inputs
+        let other_root = Scalar::one().to_bytes();
+        assert!(!verify_membership_bytes(&other_root, &proof));
+        assert!(!verify_membership_bytes(&root, &proof[..proof.len() - 1]));
+        assert!(prove_membership_bytes(&root, k.as_bytes(), leaf.as_bytes(), &proof_bytes[1..]).is_empty());
+        assert!(prove_membership_bytes(&root[1..], k.as_bytes(), leaf.as_bytes(), &proof_bytes).is_empty());
+    }
+}